@@ -10,6 +10,8 @@ use crate::circuits::params::CircuitParams;
 use crate::circuits::sample_cells::{SampleCircuit, SampleCircuitInput, SampleTargets};
 use crate::Result;
 
+/// a bundle of sampling proofs, each kept under its index (e.g. the slot index in the tree).
+/// use `TreeRecursion::prove_bundle` to prove and aggregate a bundle in one call.
 pub struct Bundle<
     F: RichField + Extendable<D> + Poseidon2,
     C: GenericConfig<D, F = F>,
@@ -54,6 +56,14 @@ impl<
         Ok(())
     }
 
+    /// proves each circuit input and keeps the proof under the given index
+    pub fn prove_indexed(&mut self, circ_inputs: HashMap<usize, SampleCircuitInput<F, D>>) -> Result<()>{
+        for (index, input) in circ_inputs.into_iter(){
+            self.prove(index, input)?;
+        }
+        Ok(())
+    }
+
     pub fn prove(&mut self, index: usize, circ_input: SampleCircuitInput<F, D>) -> Result<()>{
         let proof = self.circuit.prove(&self.sample_targets, &circ_input, &self.prover_data)?;
         self.bundle_proofs.insert(index, proof);
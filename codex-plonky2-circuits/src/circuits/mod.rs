@@ -1,5 +1,6 @@
 pub mod merkle_circuit;
 pub mod sample_cells;
+pub mod multi_slot_sample_cells;
 pub mod utils;
 pub mod params;
 pub mod keyed_compress;
@@ -0,0 +1,351 @@
+// Sample cells of several slots of the same dataset in one proof
+// circuit consists of:
+// - for each slot: reconstruct the dataset merkle root using the slot root as leaf
+// - for each slot: samples multiple cells by calling the sample_cells (see `SampleCircuit`)
+// the dataset root, entropy, number of slots and number of cells per slot are shared by all slots.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::{
+        hash_types::{HashOut, NUM_HASH_OUT_ELTS, RichField},
+    },
+    iop::{
+        target::Target,
+        witness::{PartialWitness, WitnessWrite},
+    },
+    plonk::circuit_builder::CircuitBuilder,
+};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use serde::{Deserialize, Serialize};
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+
+use crate::{
+    circuits::{
+        merkle_circuit::MerkleProofTarget,
+        params::CircuitParams,
+        sample_cells::{Cell, CellTarget, MerklePath, SampleCircuit, SampleCircuitInput, ENTROPY_BYTES},
+        utils::{assert_less_than, assert_count_in_range, ceiling_log2},
+    },
+    Result,
+    error::CircuitError,
+};
+use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::serialization::SerializableHashOutTarget;
+
+/// circuit for sampling `k` slots in a dataset merkle tree
+/// the slot indices must be strictly increasing, so each slot is sampled at most once per proof.
+#[derive(Clone, Debug)]
+pub struct MultiSlotSampleCircuit<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>,
+> {
+    sample_circuit: SampleCircuit<F, D, H>,
+    params: CircuitParams,
+    k: usize,
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>,
+> MultiSlotSampleCircuit<F, D, H> {
+    pub fn new(params: CircuitParams, k: usize) -> Self{
+        Self{
+            sample_circuit: SampleCircuit::new(params.clone()),
+            params,
+            k,
+        }
+    }
+
+    /// returns the number of slots `k` sampled in each proof
+    pub fn slots_per_proof(&self) -> usize {
+        self.k
+    }
+}
+
+/// struct of input to the circuit as targets
+/// used to build the circuit and can be assigned after building
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MultiSlotSampleTargets {
+
+    pub entropy: SerializableHashOutTarget, // public input
+    pub entropy_bytes: Option<Vec<Target>>, // public input, only with `entropy_from_bytes`
+    pub dataset_root: SerializableHashOutTarget, // public input
+
+    pub n_cells_per_slot: Target,
+    pub n_slots_per_dataset: Target,
+    pub n_samples_active: Option<Target>, // public input, only with `variable_n_samples`
+
+    pub slots: Vec<SlotSampleTargets>,
+}
+
+/// the targets of one sampled slot
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SlotSampleTargets {
+    pub slot_index: Target, // public input
+    pub slot_root: SerializableHashOutTarget,
+    pub slot_proof: MerkleProofTarget,
+
+    pub cell_data: Vec<CellTarget>,
+    pub merkle_paths: Vec<MerkleProofTarget>,
+}
+
+/// circuit input as field elements
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiSlotSampleCircuitInput<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>{
+    pub entropy: HashOut<F>, // public input
+    pub entropy_bytes: Option<[u8; ENTROPY_BYTES]>, // public input, only with `entropy_from_bytes`
+    pub dataset_root: HashOut<F>, // public input
+
+    pub n_cells_per_slot: F,
+    pub n_slots_per_dataset: F,
+
+    pub slots: Vec<SlotSampleInput<F, D>>,
+}
+
+/// input of one sampled slot as field elements
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotSampleInput<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>{
+    pub slot_index: F, // public input
+    pub slot_root: HashOut<F>,
+    pub slot_proof: Vec<HashOut<F>>,
+
+    pub cell_data: Vec<Cell<F,D>>,
+    pub merkle_paths: Vec<MerklePath<F,D>>,
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+> MultiSlotSampleCircuitInput<F, D> {
+    /// returns the single slot circuit input (see `SampleCircuit`) of the slot at position `i`
+    pub fn slot_input(&self, i: usize) -> Result<SampleCircuitInput<F, D>> {
+        let slot = self.slots.get(i).ok_or(CircuitError::IndexOutOfRange(i, self.slots.len()))?;
+        Ok(SampleCircuitInput {
+            entropy: self.entropy,
+            entropy_bytes: self.entropy_bytes,
+            dataset_root: self.dataset_root,
+            slot_index: slot.slot_index,
+            slot_root: slot.slot_root,
+            n_cells_per_slot: self.n_cells_per_slot,
+            n_slots_per_dataset: self.n_slots_per_dataset,
+            slot_proof: slot.slot_proof.clone(),
+            cell_data: slot.cell_data.clone(),
+            merkle_paths: slot.merkle_paths.clone(),
+        })
+    }
+}
+
+//------- circuit impl --------
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>,
+> MultiSlotSampleCircuit<F, D, H> {
+
+    /// samples and registers the public input: [slot_index_0, .., slot_index_k-1, dataset_root, entropy]
+    /// with `entropy_from_bytes` the raw entropy bytes are appended after the entropy
+    /// and with `variable_n_samples` the number of active samples (the same for all slots) is the last public input
+    pub fn sample_slots_circuit_with_public_input(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Result<MultiSlotSampleTargets> {
+        let targets = self.sample_slots_circuit(builder)?;
+        let mut pub_targets = vec![];
+        pub_targets.extend(targets.slots.iter().map(|slot| slot.slot_index));
+        pub_targets.extend_from_slice(&targets.dataset_root.0.elements);
+        pub_targets.extend_from_slice(&targets.entropy.0.elements);
+        if let Some(entropy_bytes) = &targets.entropy_bytes {
+            pub_targets.extend_from_slice(entropy_bytes);
+        }
+        pub_targets.extend(targets.n_samples_active);
+        builder.register_public_inputs(&pub_targets);
+        Ok(targets)
+    }
+
+    /// in-circuit sampling of `k` slots
+    /// WARNING: no public input are registered when calling this function
+    pub fn sample_slots_circuit(
+        &self,
+        builder: &mut CircuitBuilder::<F, D>,
+    ) -> Result<MultiSlotSampleTargets> {
+        if self.k == 0 {
+            return Err(CircuitError::InvalidArgument("the number of slots per proof must be at least 1".to_string()));
+        }
+        let max_log2_n_slots = self.params.max_log2_n_slots;
+
+        // ***** shared by all slots *********
+
+        // expected dataset Merkle root and entropy
+        let d_expected_root = builder.add_virtual_hash(); // public input
+        let (entropy_target, entropy_bytes) = self.sample_circuit.entropy_circuit(builder)?; // public input
+
+        // dataset last bits and mask bits
+        let n_slots_per_dataset = builder.add_virtual_target();
+        let (d_last_bits, d_mask_bits) =
+            ceiling_log2(builder, n_slots_per_dataset, max_log2_n_slots);
+        // 1 <= n_slots_per_dataset <= 2^max_log2_n_slots
+        assert_count_in_range(builder, n_slots_per_dataset, max_log2_n_slots);
+
+        // block and slot tree last bits and mask bits
+        let n_cells_per_slot = builder.add_virtual_target();
+        let cell_mask_bits = self.sample_circuit.cell_mask_bits_circuit(builder, n_cells_per_slot);
+        let active_samples = self.sample_circuit.active_samples_circuit(builder); // public input
+
+        let mut slots: Vec<SlotSampleTargets> = Vec::with_capacity(self.k);
+        for i in 0..self.k {
+            // ***** prove slot root is in dataset tree *********
+
+            // Create virtual target for slot root and index
+            let slot_root = builder.add_virtual_hash();
+            let slot_index = builder.add_virtual_target();// public input
+
+            // dataset path bits (binary decomposition of leaf_index)
+            let d_path_bits = builder.split_le(slot_index,max_log2_n_slots);
+
+            // the slot indices are strictly increasing and in the dataset: slot_index < n_slots_per_dataset
+            if let Some(prev) = slots.last() {
+                assert_less_than(builder, prev.slot_index, slot_index, max_log2_n_slots);
+            }
+            assert_less_than(builder, slot_index, n_slots_per_dataset, max_log2_n_slots);
+
+            // dataset reconstructed root and Merkle path
+            let (d_reconstructed_root, d_merkle_path) = self.sample_circuit.reconstruct_dataset_root_circuit(
+                builder, slot_root, d_path_bits, d_last_bits.clone(), d_mask_bits.clone()
+            )?;
+
+            // check equality with expected root
+            for j in 0..NUM_HASH_OUT_ELTS {
+                builder.connect(d_expected_root.elements[j], d_reconstructed_root.elements[j]);
+            }
+
+            //*********** do the sampling ************
+            let (cell_data, merkle_paths) =
+                self.sample_circuit.sample_cells_circuit(
+                    builder, &entropy_target, &slot_root, &cell_mask_bits, active_samples.as_ref().map(|(_, flags)| flags.as_slice())
+                ).map_err(|e| CircuitError::InvalidArgument(format!("slot {}: {}", i, e)))?;
+
+            slots.push(SlotSampleTargets {
+                slot_index,
+                slot_root: slot_root.into(),
+                slot_proof: d_merkle_path,
+                cell_data,
+                merkle_paths,
+            });
+        }
+
+        Ok(MultiSlotSampleTargets {
+            entropy: entropy_target.into(),
+            entropy_bytes,
+            dataset_root: d_expected_root.into(),
+            n_cells_per_slot,
+            n_slots_per_dataset,
+            n_samples_active: active_samples.map(|(n, _)| n),
+            slots,
+        })
+    }
+
+    /// helper method to assign the targets in the circuit to actual field elems
+    pub fn sample_slots_assign_witness(
+        &self,
+        pw: &mut PartialWitness<F>,
+        targets: &MultiSlotSampleTargets,
+        witnesses: &MultiSlotSampleCircuitInput<F, D>,
+    ) -> Result<()>{
+        if witnesses.slots.len() != self.k {
+            return Err(CircuitError::InvalidArgument(format!(
+                "Expected k={} sampled slots, got {}", self.k, witnesses.slots.len()
+            )));
+        }
+
+        // assign n_cells_per_slot
+        pw.set_target(targets.n_cells_per_slot, witnesses.n_cells_per_slot)
+            .map_err(|e| {
+                CircuitError::TargetAssignmentError("n_cells_per_slot".to_string(), e.to_string())
+            })?;
+
+        // assign n_slots_per_dataset
+        pw.set_target(targets.n_slots_per_dataset, witnesses.n_slots_per_dataset)
+            .map_err(|e| {
+                CircuitError::TargetAssignmentError("n_slots_per_dataset".to_string(), e.to_string())
+            })?;
+
+        // assign the expected Merkle root of dataset to the target
+        pw.set_hash_target(targets.dataset_root.0, witnesses.dataset_root)
+            .map_err(|e| {
+                CircuitError::HashTargetAssignmentError("dataset_root".to_string(), e.to_string())
+            })?;
+
+        // assign entropy
+        self.sample_circuit.assign_entropy(pw, &targets.entropy.0, &targets.entropy_bytes, &witnesses.entropy, &witnesses.entropy_bytes)?;
+
+        // assign the number of active samples, all slots have the same number of sampled cells
+        let n_samples_active = witnesses.slots[0].cell_data.len();
+        if witnesses.slots.iter().any(|slot| slot.cell_data.len() != n_samples_active) {
+            return Err(CircuitError::InvalidArgument(
+                "all sampled slots must have the same number of sampled cells".to_string()
+            ));
+        }
+        self.sample_circuit.assign_n_samples_active(pw, targets.n_samples_active, n_samples_active)?;
+
+        for (slot_targets, slot) in targets.slots.iter().zip(&witnesses.slots) {
+            // assign dataset proof
+            for (i, sibling_hash) in slot.slot_proof.iter().enumerate() {
+                pw.set_hash_target(slot_targets.slot_proof.path[i].0, *sibling_hash)
+                    .map_err(|e| {
+                        CircuitError::HashTargetAssignmentError("slot_proof".to_string(), e.to_string())
+                    })?;
+            }
+            // assign slot index
+            pw.set_target(slot_targets.slot_index, slot.slot_index)
+                .map_err(|e| {
+                    CircuitError::TargetAssignmentError("slot_index".to_string(), e.to_string())
+                })?;
+
+            // assign the sampled slot
+            pw.set_hash_target(slot_targets.slot_root.0, slot.slot_root)
+                .map_err(|e| {
+                    CircuitError::HashTargetAssignmentError("slot_root".to_string(), e.to_string())
+                })?;
+
+            // assign the sampled cells and their Merkle paths
+            self.sample_circuit.assign_cells(
+                pw, &slot_targets.cell_data, &slot_targets.merkle_paths, &slot.cell_data, &slot.merkle_paths
+            )?;
+        }
+
+        Ok(())
+    }
+
+}
+
+/// Implements the Plonky2Circuit trait
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    H: AlgebraicHasher<F>,
+> Plonky2Circuit<F,C,D> for MultiSlotSampleCircuit<F,D,H> {
+    type Targets = MultiSlotSampleTargets;
+    type Input = MultiSlotSampleCircuitInput<F, D>;
+
+    fn add_targets(&self, builder: &mut CircuitBuilder<F, D>, register_pi: bool) -> Result<Self::Targets> {
+        if register_pi {
+            self.sample_slots_circuit_with_public_input(builder)
+        } else {
+            self.sample_slots_circuit(builder)
+        }
+    }
+
+    fn assign_targets(&self, pw: &mut PartialWitness<F>, targets: &Self::Targets, input: &Self::Input) -> Result<()> {
+        self.sample_slots_assign_witness(pw,targets,input)
+    }
+
+}
@@ -11,7 +11,17 @@ pub struct CircuitParams{
     pub max_log2_n_slots: usize,
     pub block_tree_depth: usize,
     pub n_field_elems_per_cell: usize,
+    /// the number of samples, with `variable_n_samples` the maximum number of samples
     pub n_samples: usize,
+    /// the number of active samples `n_samples_active` (1 <= n_samples_active <= n_samples) is chosen by the prover
+    /// and is public input, the samples after it are disabled (see `SampleCircuit`)
+    pub variable_n_samples: bool,
+    /// derive the entropy in-circuit from its raw bytes with the bytes sponge,
+    /// the raw bytes are then public input (see `SampleCircuit`)
+    pub entropy_from_bytes: bool,
+    /// with `Some(cell_size)`, the cells are given as `cell_size` raw bytes and packed in-circuit
+    /// into the `n_field_elems_per_cell` field elements (10* padding, 31 bytes into 4 limbs of 62 bits)
+    pub cell_bytes: Option<usize>,
 }
 
 impl CircuitParams {
@@ -22,8 +32,11 @@ impl CircuitParams {
     /// - `BLOCK_TREE_DEPTH`:The block tree depth
     /// - `N_FIELD_ELEMS_PER_CELL`: The number of field elements per cell
     /// - `N_SAMPLES`: number of samples
+    /// - `MAX_SAMPLES`: optional, the maximum number of samples with a variable number of samples, overrides `N_SAMPLES`
+    /// - `ENTROPY_FROM_BYTES`: optional, `true` to derive the entropy in-circuit from its raw bytes, `false` by default
+    /// - `CELL_BYTES`: optional, the cell size in bytes to input the cells as raw bytes packed in-circuit
     ///
-    /// Returns an error if any environment variable is missing or fails to parse.
+    /// Returns an error if any required environment variable is missing or fails to parse.
     pub fn from_env() -> Result<Self> {
         let max_depth = env::var("MAX_DEPTH")
             .context("MAX_DEPTH is not set")?
@@ -50,12 +63,33 @@ impl CircuitParams {
             .parse::<usize>()
             .context("N_SAMPLES must be a valid usize")?;
 
+        // with MAX_SAMPLES, the number of samples is variable up to MAX_SAMPLES
+        let max_samples = env::var("MAX_SAMPLES")
+            .ok()
+            .map(|v| v.parse::<usize>().context("MAX_SAMPLES must be a valid usize"))
+            .transpose()?;
+        let variable_n_samples = max_samples.is_some();
+        let n_samples = max_samples.unwrap_or(n_samples);
+
+        let entropy_from_bytes = match env::var("ENTROPY_FROM_BYTES") {
+            Ok(v) => v.parse::<bool>().context("ENTROPY_FROM_BYTES must be true or false")?,
+            Err(_) => false,
+        };
+
+        let cell_bytes = env::var("CELL_BYTES")
+            .ok()
+            .map(|v| v.parse::<usize>().context("CELL_BYTES must be a valid usize"))
+            .transpose()?;
+
         Ok(CircuitParams {
             max_depth,
             max_log2_n_slots,
             block_tree_depth,
             n_field_elems_per_cell,
             n_samples,
+            variable_n_samples,
+            entropy_from_bytes,
+            cell_bytes,
         })
     }
 }
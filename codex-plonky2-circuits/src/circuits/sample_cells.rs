@@ -26,8 +26,8 @@ use crate::{
     circuits::{
     merkle_circuit::{MerkleProofTarget, MerkleTreeCircuit, MerkleTreeTargets},
     params::CircuitParams,
-    sponge::{hash_n_no_padding, hash_n_with_padding},
-    utils::{assign_hash_out_targets, ceiling_log2},
+    sponge::{hash_bytes, hash_n_no_padding, hash_n_with_padding, pack_bytes_with_padding},
+    utils::{assert_count_in_range, assert_less_than, assign_hash_out_targets, ceiling_log2},
     },
     Result,
     error::CircuitError,
@@ -35,6 +35,9 @@ use crate::{
 use crate::circuit_trait::Plonky2Circuit;
 use crate::circuits::serialization::SerializableHashOutTarget;
 
+/// number of bytes of the raw entropy, e.g. a block hash
+pub const ENTROPY_BYTES: usize = 32;
+
 /// circuit for sampling a slot in a dataset merkle tree
 #[derive(Clone, Debug)]
 pub struct SampleCircuit<
@@ -66,8 +69,10 @@ impl<
 pub struct SampleTargets {
 
     pub entropy: SerializableHashOutTarget, // public input
+    pub entropy_bytes: Option<Vec<Target>>, // public input, only with `entropy_from_bytes`
     pub dataset_root: SerializableHashOutTarget, // public input
     pub slot_index: Target, // public input
+    pub n_samples_active: Option<Target>, // public input, only with `variable_n_samples`
 
     pub slot_root: SerializableHashOutTarget,
     pub n_cells_per_slot: Target,
@@ -86,6 +91,7 @@ pub struct SampleCircuitInput<
     const D: usize,
 >{
     pub entropy: HashOut<F>, // public input
+    pub entropy_bytes: Option<[u8; ENTROPY_BYTES]>, // public input, only with `entropy_from_bytes`
     pub dataset_root: HashOut<F>, // public input
     pub slot_index: F, // public input
 
@@ -109,13 +115,23 @@ pub struct MerklePath<
     pub path: Vec<HashOut<F>>
 }
 
-/// a vec of cell targets
+/// a vec of cell targets, with `cell_bytes` the raw byte targets of the cell
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CellTarget {
     pub data: Vec<Target>
 }
 
-/// cell data as field elements
+/// the last and mask bits of the block and slot trees, computed from the number of cells in the slot
+#[derive(Clone, Debug)]
+pub(crate) struct CellMaskBits {
+    pub mask_bits: Vec<BoolTarget>,
+    pub b_last_bits: Vec<BoolTarget>,
+    pub b_mask_bits: Vec<BoolTarget>,
+    pub s_last_bits: Vec<BoolTarget>,
+    pub s_mask_bits: Vec<BoolTarget>,
+}
+
+/// cell data as field elements, with `cell_bytes` the raw bytes of the cell (one byte per element)
 #[derive(Clone, Debug, PartialEq)]
 pub struct Cell<
     F: RichField + Extendable<D> + Poseidon2,
@@ -131,7 +147,9 @@ impl<
     H: AlgebraicHasher<F>,
 > SampleCircuit<F, D, H> {
 
-    /// samples and registers the public input
+    /// samples and registers the public input: [slot_index, dataset_root, entropy]
+    /// with `entropy_from_bytes` the raw entropy bytes are appended: [slot_index, dataset_root, entropy, entropy_bytes]
+    /// and with `variable_n_samples` the number of active samples is the last public input
     pub fn sample_slot_circuit_with_public_input(
         &self,
         builder: &mut CircuitBuilder<F, D>,
@@ -141,6 +159,10 @@ impl<
         pub_targets.push(targets.slot_index);
         pub_targets.extend_from_slice(&targets.dataset_root.0.elements);
         pub_targets.extend_from_slice(&targets.entropy.0.elements);
+        if let Some(entropy_bytes) = &targets.entropy_bytes {
+            pub_targets.extend_from_slice(entropy_bytes);
+        }
+        pub_targets.extend(targets.n_samples_active);
         builder.register_public_inputs(&pub_targets);
         Ok(targets)
     }
@@ -152,17 +174,7 @@ impl<
         builder: &mut CircuitBuilder::<F, D>,
     ) -> Result<SampleTargets> {
         // circuit params
-        let CircuitParams {
-            max_depth,
-            max_log2_n_slots,
-            block_tree_depth,
-            n_field_elems_per_cell,
-            n_samples,
-        } = self.params;
-
-        // constants
-        let zero = builder.zero();
-        let one = builder.one();
+        let max_log2_n_slots = self.params.max_log2_n_slots;
 
         // ***** prove slot root is in dataset tree *********
 
@@ -180,6 +192,79 @@ impl<
         let (d_last_bits, d_mask_bits) =
             ceiling_log2(builder, n_slots_per_dataset, max_log2_n_slots);
 
+        // bounds of the private parameters: 1 <= n_slots_per_dataset <= 2^max_log2_n_slots
+        // and slot_index < n_slots_per_dataset, otherwise the prover can claim a slot outside the dataset
+        assert_count_in_range(builder, n_slots_per_dataset, max_log2_n_slots);
+        assert_less_than(builder, slot_index, n_slots_per_dataset, max_log2_n_slots);
+
+        // dataset reconstructed root and Merkle path
+        let (d_reconstructed_root, d_merkle_path) =
+            self.reconstruct_dataset_root_circuit(builder, slot_root, d_path_bits, d_last_bits, d_mask_bits)?;
+
+        // expected Merkle root
+        let d_expected_root = builder.add_virtual_hash(); // public input
+
+        // check equality with expected root
+        for i in 0..NUM_HASH_OUT_ELTS {
+            builder.connect(d_expected_root.elements[i], d_reconstructed_root.elements[i]);
+        }
+
+        //*********** do the sampling ************
+
+        let (entropy_target, entropy_bytes) = self.entropy_circuit(builder)?; // public input
+
+        // virtual target for n_cells_per_slot
+        let n_cells_per_slot = builder.add_virtual_target();
+
+        let cell_mask_bits = self.cell_mask_bits_circuit(builder, n_cells_per_slot);
+        let active_samples = self.active_samples_circuit(builder); // public input
+        let (data_targets, slot_sample_proofs) = self.sample_cells_circuit(
+            builder, &entropy_target, &slot_root, &cell_mask_bits, active_samples.as_ref().map(|(_, flags)| flags.as_slice())
+        )?;
+
+        let st = SampleTargets {
+            entropy: entropy_target.into(),
+            entropy_bytes,
+            dataset_root: d_expected_root.into(),
+            slot_index,
+            n_samples_active: active_samples.map(|(n, _)| n),
+            slot_root: slot_root.into(),
+            n_cells_per_slot,
+            n_slots_per_dataset,
+            slot_proof: d_merkle_path,
+            cell_data: data_targets,
+            merkle_paths: slot_sample_proofs,
+        };
+
+        Ok(st)
+    }
+
+    /// the entropy digest, a virtual hash target or with `entropy_from_bytes`
+    /// the hash of the raw entropy bytes (bytes sponge), returned with the byte targets.
+    pub(crate) fn entropy_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Result<(HashOutTarget, Option<Vec<Target>>)> {
+        if !self.params.entropy_from_bytes {
+            return Ok((builder.add_virtual_hash(), None));
+        }
+        let entropy_bytes = builder.add_virtual_targets(ENTROPY_BYTES);
+        let entropy = hash_bytes::<F, D, H>(builder, &entropy_bytes)?;
+        Ok((entropy, Some(entropy_bytes)))
+    }
+
+    /// reconstructs the dataset root from the slot root and its dataset Merkle path (sibling hashes from leaf to root),
+    /// returns the reconstructed root and the virtual targets for the path.
+    pub(crate) fn reconstruct_dataset_root_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        slot_root: HashOutTarget,
+        d_path_bits: Vec<BoolTarget>,
+        d_last_bits: Vec<BoolTarget>,
+        d_mask_bits: Vec<BoolTarget>,
+    ) -> Result<(HashOutTarget, MerkleProofTarget)> {
+        let max_log2_n_slots = self.params.max_log2_n_slots;
+
         // dataset Merkle path (sibling hashes from leaf to root)
         let d_merkle_path = MerkleProofTarget {
             path: (0..max_log2_n_slots).map(|_| builder.add_virtual_hash()).map(SerializableHashOutTarget::from).collect(),
@@ -198,22 +283,24 @@ impl<
         let d_reconstructed_root =
             MerkleTreeCircuit::<F,D, H>::reconstruct_merkle_root_circuit_with_mask(builder, &mut d_targets, max_log2_n_slots)?;
 
-        // expected Merkle root
-        let d_expected_root = builder.add_virtual_hash(); // public input
-
-        // check equality with expected root
-        for i in 0..NUM_HASH_OUT_ELTS {
-            builder.connect(d_expected_root.elements[i], d_reconstructed_root.elements[i]);
-        }
-
-        //*********** do the sampling ************
+        Ok((d_reconstructed_root, d_targets.merkle_path))
+    }
 
-        let mut data_targets =vec![];
-        let mut slot_sample_proofs = vec![];
-        let entropy_target = builder.add_virtual_hash(); // public input
+    /// computes the last and mask bits of the block and slot trees from `n_cells_per_slot`
+    pub(crate) fn cell_mask_bits_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        n_cells_per_slot: Target,
+    ) -> CellMaskBits {
+        let CircuitParams {
+            max_depth,
+            block_tree_depth,
+            ..
+        } = self.params;
+        let one = builder.one();
 
-        // virtual target for n_cells_per_slot
-        let n_cells_per_slot = builder.add_virtual_target();
+        // bound of the private parameter: 1 <= n_cells_per_slot <= 2^max_depth
+        assert_count_in_range(builder, n_cells_per_slot, max_depth);
 
         // calculate last index = n_cells_per_slot-1
         let slot_last_index = builder.sub(n_cells_per_slot, one);
@@ -233,11 +320,103 @@ impl<
         b_mask_bits.push(builder.constant_bool(false));
         s_mask_bits.push(builder.constant_bool(false));
 
+        CellMaskBits {
+            mask_bits,
+            b_last_bits,
+            b_mask_bits,
+            s_last_bits,
+            s_mask_bits,
+        }
+    }
+
+    /// with `variable_n_samples`, returns the virtual target for the number of active samples `n_samples_active`
+    /// and the flags of the active samples: sample `i` is active iff `i < n_samples_active`.
+    /// constrains `1 <= n_samples_active <= n_samples`.
+    pub(crate) fn active_samples_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Option<(Target, Vec<BoolTarget>)> {
+        if !self.params.variable_n_samples {
+            return None;
+        }
+        let n_samples = self.params.n_samples;
+        let n_samples_active = builder.add_virtual_target();
+
+        // 1 <= n_samples_active <= 2^n_bits and n_samples_active < n_samples + 1
+        let n_bits = (usize::BITS - n_samples.leading_zeros()) as usize;
+        assert_count_in_range(builder, n_samples_active, n_bits);
+        let bound = builder.constant(F::from_canonical_usize(n_samples + 1));
+        assert_less_than(builder, n_samples_active, bound, n_bits);
+
+        // the flag of sample i is set until n_samples_active == i
+        let mut active = builder._true();
+        let mut flags = Vec::with_capacity(n_samples);
+        for i in 0..n_samples {
+            let i_target = builder.constant(F::from_canonical_usize(i));
+            let is_end = builder.is_equal(n_samples_active, i_target);
+            let not_end = builder.not(is_end);
+            active = builder.and(active, not_end);
+            flags.push(active);
+        }
+        Some((n_samples_active, flags))
+    }
+
+    /// samples `n_samples` cells of the slot with root `slot_root`, and checks each cell is in the slot tree.
+    /// with `active_samples` flags, only the active samples are checked.
+    /// with `cell_bytes`, the cell data are raw byte targets packed in-circuit before hashing.
+    /// returns the virtual targets for the cell data and the cell Merkle paths (block path followed by slot path).
+    pub(crate) fn sample_cells_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        entropy_target: &HashOutTarget,
+        slot_root: &HashOutTarget,
+        cell_mask_bits: &CellMaskBits,
+        active_samples: Option<&[BoolTarget]>,
+    ) -> Result<(Vec<CellTarget>, Vec<MerkleProofTarget>)> {
+        let CircuitParams {
+            max_depth,
+            block_tree_depth,
+            n_field_elems_per_cell,
+            n_samples,
+            cell_bytes,
+            ..
+        } = self.params;
+        if let Some(cell_size) = cell_bytes {
+            let expected = (cell_size + 62) / 62 * 8;
+            if expected != n_field_elems_per_cell {
+                return Err(CircuitError::InvalidArgument(format!(
+                    "{} cell bytes pack into {} field elements, expected {}",
+                    cell_size, expected, n_field_elems_per_cell
+                )));
+            }
+        }
+        let CellMaskBits {
+            mask_bits,
+            b_last_bits,
+            b_mask_bits,
+            s_last_bits,
+            s_mask_bits,
+        } = cell_mask_bits;
+        let zero = builder.zero();
+
+        let mut data_targets =vec![];
+        let mut slot_sample_proofs = vec![];
+
         for i in 0..n_samples{
-            // cell data targets
-            let data_i = (0..n_field_elems_per_cell).map(|_| builder.add_virtual_target()).collect::<Vec<_>>();
-            // hash the cell data
-            let data_i_hash = hash_n_no_padding::<F,D,H>(builder, data_i.clone())?;
+            // cell data targets, raw bytes are range checked and packed into field elements
+            let (data_i, data_i_hash) = match cell_bytes {
+                None => {
+                    let data_i = (0..n_field_elems_per_cell).map(|_| builder.add_virtual_target()).collect::<Vec<_>>();
+                    let data_i_hash = hash_n_no_padding::<F,D,H>(builder, data_i.clone())?;
+                    (data_i, data_i_hash)
+                }
+                Some(cell_size) => {
+                    let data_i = builder.add_virtual_targets(cell_size);
+                    let packed = pack_bytes_with_padding(builder, &data_i)?;
+                    let data_i_hash = hash_n_no_padding::<F,D,H>(builder, packed)?;
+                    (data_i, data_i_hash)
+                }
+            };
             // make the counter into hash digest
             let ctr_target = builder.constant(F::from_canonical_u64((i+1) as u64));
             let mut ctr = builder.add_virtual_hash();
@@ -249,7 +428,7 @@ impl<
                 }
             }
             // paths for block and slot
-            let mut b_path_bits = self.calculate_cell_index_bits(builder, &entropy_target, &d_targets.leaf, &ctr, mask_bits.clone())?;
+            let mut b_path_bits = self.calculate_cell_index_bits(builder, entropy_target, slot_root, &ctr, mask_bits.clone())?;
             let s_path_bits = b_path_bits.split_off(block_tree_depth);
 
             let b_merkle_path = MerkleProofTarget {
@@ -282,9 +461,14 @@ impl<
             // reconstruct slot root with block root as leaf
             let slot_reconstructed_root = MerkleTreeCircuit::<F,D,H>::reconstruct_merkle_root_circuit_with_mask(builder, &mut slot_targets, max_depth-block_tree_depth)?;
 
-            // check equality with expected root
-            for i in 0..NUM_HASH_OUT_ELTS {
-                builder.connect( d_targets.leaf.elements[i], slot_reconstructed_root.elements[i]);
+            // check equality with expected root, if the sample is active
+            match active_samples {
+                None => for j in 0..NUM_HASH_OUT_ELTS {
+                    builder.connect( slot_root.elements[j], slot_reconstructed_root.elements[j]);
+                },
+                Some(flags) => for j in 0..NUM_HASH_OUT_ELTS {
+                    builder.conditional_assert_eq(flags[i].target, slot_root.elements[j], slot_reconstructed_root.elements[j]);
+                },
             }
 
             // combine block and slot path to get the full path so we can assign it later.
@@ -301,19 +485,7 @@ impl<
 
         }
 
-        let st = SampleTargets {
-            entropy: entropy_target.into(),
-            dataset_root: d_expected_root.into(),
-            slot_index,
-            slot_root: d_targets.leaf.into(),
-            n_cells_per_slot,
-            n_slots_per_dataset,
-            slot_proof: d_targets.merkle_path,
-            cell_data: data_targets,
-            merkle_paths: slot_sample_proofs,
-        };
-
-        Ok(st)
+        Ok((data_targets, slot_sample_proofs))
     }
 
     /// calculate the cell index = H( entropy | slotRoot | counter ) `mod` nCells
@@ -344,14 +516,6 @@ impl<
         targets: &SampleTargets,
         witnesses: &SampleCircuitInput<F, D>,
     ) -> Result<()>{
-        // circuit params
-        let CircuitParams {
-            max_depth,
-            n_field_elems_per_cell,
-            n_samples,
-            ..
-        } = self.params;
-
         // assign n_cells_per_slot
         pw.set_target(targets.n_cells_per_slot, witnesses.n_cells_per_slot)
             .map_err(|e| {
@@ -390,22 +554,105 @@ impl<
             })?;
 
         // assign entropy
-        assign_hash_out_targets(pw, &targets.entropy.0, &witnesses.entropy)?;
+        self.assign_entropy(pw, &targets.entropy.0, &targets.entropy_bytes, &witnesses.entropy, &witnesses.entropy_bytes)?;
+
+        // assign the number of active samples
+        self.assign_n_samples_active(pw, targets.n_samples_active, witnesses.cell_data.len())?;
+
+        // assign the sampled cells and their Merkle paths
+        self.assign_cells(pw, &targets.cell_data, &targets.merkle_paths, &witnesses.cell_data, &witnesses.merkle_paths)?;
+
+        Ok(())
+    }
+
+    /// assigns the entropy digest, or the raw entropy bytes if the digest is derived from them in-circuit
+    pub(crate) fn assign_entropy(
+        &self,
+        pw: &mut PartialWitness<F>,
+        entropy_target: &HashOutTarget,
+        entropy_bytes_targets: &Option<Vec<Target>>,
+        entropy: &HashOut<F>,
+        entropy_bytes: &Option<[u8; ENTROPY_BYTES]>,
+    ) -> Result<()> {
+        match (entropy_bytes_targets, entropy_bytes) {
+            (None, _) => assign_hash_out_targets(pw, entropy_target, entropy),
+            (Some(targets), Some(bytes)) => {
+                for (i, (&t, &b)) in targets.iter().zip(bytes.iter()).enumerate() {
+                    pw.set_target(t, F::from_canonical_u8(b))
+                        .map_err(|e| CircuitError::ArrayTargetAssignmentError(i, e.to_string()))?;
+                }
+                Ok(())
+            }
+            (Some(_), None) => Err(CircuitError::InvalidArgument(
+                "the circuit derives the entropy from its raw bytes but no entropy bytes are given".to_string(),
+            )),
+        }
+    }
+
+    /// assigns the number of active samples, the number of sampled cells in the input.
+    /// without `variable_n_samples`, all `n_samples` samples must be in the input.
+    pub(crate) fn assign_n_samples_active(
+        &self,
+        pw: &mut PartialWitness<F>,
+        n_samples_active_target: Option<Target>,
+        n_samples_active: usize,
+    ) -> Result<()> {
+        match n_samples_active_target {
+            Some(t) => pw.set_target(t, F::from_canonical_usize(n_samples_active))
+                .map_err(|e| {
+                    CircuitError::TargetAssignmentError("n_samples_active".to_string(), e.to_string())
+                }),
+            None if n_samples_active != self.params.n_samples => Err(CircuitError::InvalidArgument(format!(
+                "Expected {} sampled cells, got {}", self.params.n_samples, n_samples_active
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// assigns the data of the sampled cells and their Merkle paths,
+    /// the samples after the given cells (inactive samples) are assigned zeros
+    pub(crate) fn assign_cells(
+        &self,
+        pw: &mut PartialWitness<F>,
+        cell_targets: &[CellTarget],
+        path_targets: &[MerkleProofTarget],
+        cell_data: &[Cell<F, D>],
+        merkle_paths: &[MerklePath<F, D>],
+    ) -> Result<()> {
+        // circuit params
+        let CircuitParams {
+            max_depth,
+            n_samples,
+            ..
+        } = self.params;
+
+        if cell_data.len() > n_samples || merkle_paths.len() != cell_data.len() {
+            return Err(CircuitError::InvalidArgument(format!(
+                "Expected at most {} sampled cells with a Merkle path each, got {} cells and {} paths",
+                n_samples, cell_data.len(), merkle_paths.len()
+            )));
+        }
 
         // do the sample N times
         for i in 0..n_samples {
-            // assign cell data
-            let leaf = witnesses.cell_data[i].data.clone();
-            for j in 0..n_field_elems_per_cell{
-                pw.set_target(targets.cell_data[i].data[j], leaf[j])
+            // assign cell data, zeros for the inactive samples
+            let cell_len = cell_targets[i].data.len();
+            let leaf = cell_data.get(i).map_or_else(|| vec![F::ZERO; cell_len], |c| c.data.clone());
+            if leaf.len() != cell_len {
+                return Err(CircuitError::InvalidArgument(format!(
+                    "Expected {} elements in sampled cell {}, got {}", cell_len, i, leaf.len()
+                )));
+            }
+            for (&target, &value) in cell_targets[i].data.iter().zip(leaf.iter()) {
+                pw.set_target(target, value)
                     .map_err(|e| {
                         CircuitError::TargetAssignmentError("cell_data".to_string(), e.to_string())
                     })?;
             }
             // assign proof for that cell
-            let cell_proof = witnesses.merkle_paths[i].path.clone();
+            let cell_proof = merkle_paths.get(i).map_or_else(|| vec![HashOut::ZERO; max_depth], |p| p.path.clone());
             for k in 0..max_depth {
-                pw.set_hash_target(targets.merkle_paths[i].path[k].0, cell_proof[k])
+                pw.set_hash_target(path_targets[i].path[k].0, cell_proof[k])
                     .map_err(|e| {
                         CircuitError::HashTargetAssignmentError("merkle_paths".to_string(), e.to_string())
                     })?;
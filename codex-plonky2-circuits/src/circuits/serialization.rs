@@ -30,3 +30,122 @@ impl<'de> Deserialize<'de> for SerializableHashOutTarget {
         Ok(SerializableHashOutTarget(HashOutTarget { elements }))
     }
 }
+
+/// serde helpers for the plonky2 targets that don't implement `Serialize`,
+/// use them on struct fields with `#[serde(with = "...")]`.
+/// the proof and verifier data targets are encoded with the plonky2 target serialization (as bytes).
+pub mod proof_with_pis_target {
+    use plonky2::plonk::proof::ProofWithPublicInputsTarget;
+    use plonky2::util::serialization::{Buffer, Read, Write};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, const D: usize>(
+        target: &ProofWithPublicInputsTarget<D>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut bytes = Vec::new();
+        bytes.write_target_proof_with_public_inputs(target)
+            .map_err(|e| serde::ser::Error::custom(format!("{:?}", e)))?;
+        bytes.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, De: Deserializer<'de>, const D: usize>(
+        deserializer: De,
+    ) -> Result<ProofWithPublicInputsTarget<D>, De::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        Buffer::new(&bytes).read_target_proof_with_public_inputs()
+            .map_err(|e| serde::de::Error::custom(format!("{:?}", e)))
+    }
+}
+
+pub mod proof_with_pis_target_vec {
+    use plonky2::plonk::proof::ProofWithPublicInputsTarget;
+    use plonky2::util::serialization::{Buffer, Read, Write};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, const D: usize>(
+        targets: &[ProofWithPublicInputsTarget<D>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut encoded = Vec::with_capacity(targets.len());
+        for target in targets {
+            let mut bytes = Vec::new();
+            bytes.write_target_proof_with_public_inputs(target)
+                .map_err(|e| serde::ser::Error::custom(format!("{:?}", e)))?;
+            encoded.push(bytes);
+        }
+        encoded.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, De: Deserializer<'de>, const D: usize>(
+        deserializer: De,
+    ) -> Result<Vec<ProofWithPublicInputsTarget<D>>, De::Error> {
+        let encoded = Vec::<Vec<u8>>::deserialize(deserializer)?;
+        encoded.iter()
+            .map(|bytes| Buffer::new(bytes).read_target_proof_with_public_inputs()
+                .map_err(|e| serde::de::Error::custom(format!("{:?}", e))))
+            .collect()
+    }
+}
+
+pub mod verifier_circuit_target {
+    use plonky2::plonk::circuit_data::VerifierCircuitTarget;
+    use plonky2::util::serialization::{Buffer, Read, Write};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        target: &VerifierCircuitTarget,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut bytes = Vec::new();
+        bytes.write_target_verifier_circuit(target)
+            .map_err(|e| serde::ser::Error::custom(format!("{:?}", e)))?;
+        bytes.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, De: Deserializer<'de>>(
+        deserializer: De,
+    ) -> Result<VerifierCircuitTarget, De::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        Buffer::new(&bytes).read_target_verifier_circuit()
+            .map_err(|e| serde::de::Error::custom(format!("{:?}", e)))
+    }
+}
+
+pub mod bool_target {
+    use plonky2::iop::target::{BoolTarget, Target};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        target: &BoolTarget,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        target.target.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, De: Deserializer<'de>>(
+        deserializer: De,
+    ) -> Result<BoolTarget, De::Error> {
+        // the target was range checked when the circuit was built
+        Ok(BoolTarget::new_unsafe(Target::deserialize(deserializer)?))
+    }
+}
+
+pub mod bool_target_vec {
+    use plonky2::iop::target::{BoolTarget, Target};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        targets: &[BoolTarget],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        targets.iter().map(|b| b.target).collect::<Vec<Target>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, De: Deserializer<'de>>(
+        deserializer: De,
+    ) -> Result<Vec<BoolTarget>, De::Error> {
+        let targets = Vec::<Target>::deserialize(deserializer)?;
+        Ok(targets.into_iter().map(BoolTarget::new_unsafe).collect())
+    }
+}
@@ -1,6 +1,6 @@
 use plonky2::hash::hash_types::{HashOutTarget, NUM_HASH_OUT_ELTS, RichField};
 use plonky2::hash::hashing::PlonkyPermutation;
-use plonky2::iop::target::Target;
+use plonky2::iop::target::{BoolTarget, Target};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 use plonky2::plonk::config::AlgebraicHasher;
 use plonky2_field::extension::Extendable;
@@ -182,4 +182,118 @@ pub fn hash_n_to_m_no_padding<
         }
         state = builder.permute::<H>(state);
     }
-}
\ No newline at end of file
+}
+/// hash n byte targets into hash digest / HashOutTarget (4 Goldilocks field elements)
+/// in-circuit version of the bytes sponge: the input is padded with 10* padding on bytes
+/// and each 62 bytes are packed into 8 field elements (see `pack_31_bytes`).
+/// the input targets are range checked to be bytes.
+pub fn hash_bytes<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    inputs: &[Target],
+) -> Result<HashOutTarget> {
+    Ok(
+        HashOutTarget::from_vec(
+            hash_bytes_to_m_with_padding::<F,D,H>(builder, inputs, NUM_HASH_OUT_ELTS)?
+        )
+    )
+}
+
+pub fn hash_bytes_to_m_with_padding<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    inputs: &[Target],
+    num_outputs: usize,
+) -> Result<Vec<Target>> {
+    let rate = H::AlgebraicPermutation::RATE;
+    let width = H::AlgebraicPermutation::WIDTH; // rate + capacity
+    if rate != 8 {
+        return Err(CircuitError::InvalidArgument(format!("the bytes sponge expects rate 8, got {}", rate)));
+    }
+    let zero = builder.zero();
+    let mut state = H::AlgebraicPermutation::new(core::iter::repeat_n(zero, width));
+
+    // Set the domain separator at index 8, the inputs are 8-bit bytes
+    let dom_sep_value = rate as u64 + 256 * 12 + 65536 * 8;
+    let dom_sep = builder.constant(F::from_canonical_u64(dom_sep_value));
+    state.set_elt(dom_sep, 8);
+
+    let felts = pack_bytes_with_padding(builder, inputs)?;
+    for chunk_felts in felts.chunks(rate) {
+        // Add the chunk to the state
+        for (j, &felt) in chunk_felts.iter().enumerate() {
+            state.set_elt(builder.add(state.as_ref()[j], felt), j);
+        }
+        // Apply permutation
+        state = builder.permute::<H>(state);
+    }
+
+    // Squeeze until we have the desired number of outputs
+    let mut outputs = Vec::with_capacity(num_outputs);
+    loop {
+        for &s in state.squeeze() {
+            outputs.push(s);
+            if outputs.len() == num_outputs {
+                return Ok(outputs);
+            }
+        }
+        state = builder.permute::<H>(state);
+    }
+}
+
+/// pads the byte targets with 10* padding to a multiple of 62 bytes
+/// and packs each 62 bytes into 8 field elements (see `pack_31_bytes`).
+/// the input targets are range checked to be bytes.
+pub fn pack_bytes_with_padding<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    bytes: &[Target],
+) -> Result<Vec<Target>> {
+    let byte_rate = 62;
+    let zero = builder.zero();
+    let one = builder.one();
+
+    // 10* padding on bytes
+    let num_chunks = (bytes.len() + byte_rate) / byte_rate;
+    let mut padded = bytes.to_vec();
+    padded.push(one);
+    padded.resize(num_chunks * byte_rate, zero);
+
+    let mut felts = Vec::with_capacity(num_chunks * 8);
+    for chunk in padded.chunks(31) {
+        felts.extend_from_slice(&pack_31_bytes(builder, chunk)?);
+    }
+    Ok(felts)
+}
+
+/// packs 31 little-endian byte targets (248 bits) into 4 field elements of 62 bits each,
+/// the same packing as the native bytes sponge.
+/// each byte is range checked by its bit decomposition.
+pub fn pack_31_bytes<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    bytes: &[Target],
+) -> Result<[Target; 4]> {
+    if bytes.len() != 31 {
+        return Err(CircuitError::InvalidArgument(format!("expected 31 bytes to pack, got {}", bytes.len())));
+    }
+    let bits: Vec<BoolTarget> = bytes
+        .iter()
+        .flat_map(|&b| builder.split_le(b, 8))
+        .collect();
+    let mut felts = [builder.zero(); 4];
+    for (k, limb_bits) in bits.chunks(62).enumerate() {
+        felts[k] = builder.le_sum(limb_bits.iter());
+    }
+    Ok(felts)
+}
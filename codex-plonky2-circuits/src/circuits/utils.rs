@@ -37,6 +37,37 @@ pub fn ceiling_log2<
     (last_bits, mask)
 }
 
+/// constrains `1 <= n <= 2^n_bits`, i.e. `n - 1` fits in `n_bits` bits
+pub fn assert_count_in_range<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    n: Target,
+    n_bits: usize,
+) {
+    let one = builder.one();
+    let last_index = builder.sub(n, one);
+    builder.range_check(last_index, n_bits);
+}
+
+/// constrains `a < b` where `a < 2^n_bits` and `b <= 2^n_bits`, i.e. `b - a - 1` fits in `n_bits` bits.
+/// if `a >= b`, `b - a - 1` wraps around the field modulus and doesn't fit.
+pub fn assert_less_than<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    a: Target,
+    b: Target,
+    n_bits: usize,
+) {
+    let one = builder.one();
+    let diff = builder.sub(b, a);
+    let gap = builder.sub(diff, one);
+    builder.range_check(gap, n_bits);
+}
+
 /// assign a vec of bool values to a vec of BoolTargets
 pub fn assign_bool_targets<
     F: RichField + Extendable<D> + Poseidon2,
@@ -74,4 +74,43 @@ pub enum CircuitError {
 
     #[error("{0}")]
     InvalidArgument(String),
+
+    #[error("Checkpoint Error: {0}")]
+    CheckpointError(String),
+
+    #[error("Public input hash mismatch: expected {0}, got {1}")]
+    PublicInputHashMismatch(String, String),
+
+    #[error("Verifier data hash mismatch: expected {0}, got {1}")]
+    VerifierDataHashMismatch(String, String),
+
+    #[error("Flag buckets mismatch: expected {0}, got {1}")]
+    FlagBucketsMismatch(String, String),
+
+    #[error("Index {0} is out of range, must be less than {1}")]
+    IndexOutOfRange(usize, usize),
+
+    #[error("Wrong number of flags: expected {0}, got {1}")]
+    FlagsLengthMismatch(usize, usize),
+
+    #[error("Wrong number of inner proofs: expected {0}, got {1}")]
+    InnerProofsLengthMismatch(usize, usize),
+
+    #[error("Invalid flag bucket {0}: {1}")]
+    InvalidFlagBucket(usize, String),
+
+    #[error("Proven indices mismatch: missing {0:?}, unexpected {1:?}")]
+    ProvenIndicesMismatch(Vec<usize>, Vec<usize>),
+
+    #[error("Inner proofs at indices {0:?} do not verify with the inner verifier data")]
+    InvalidInnerProofs(Vec<usize>),
+
+    #[error("Unsupported arity {0}, expected one of {1:?}")]
+    UnsupportedArity(usize, Vec<usize>),
+
+    #[error("Serialization Error: {0}")]
+    SerializationError(String),
+
+    #[error("Stale circuit data: {0}")]
+    StaleCircuitData(String),
 }
\ No newline at end of file
@@ -0,0 +1,196 @@
+use std::marker::PhantomData;
+use std::str::FromStr;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, VerifierCircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use plonky2_field::extension::Extendable;
+use crate::{error::CircuitError, Result};
+use crate::circuit_trait::Plonky2Circuit;
+use crate::recursion::leaf::LeafCircuit;
+use crate::recursion::node::NodeCircuit;
+
+/// the node arities the tree can be built with at runtime, see `ArityEstimator`
+pub const SUPPORTED_ARITIES: [usize; 4] = [2, 4, 8, 16];
+
+/// what the arity selection minimises
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArityTarget {
+    /// total proving time of the tree
+    Latency,
+    /// peak prover memory, i.e. the size of the largest circuit
+    Memory,
+}
+
+impl FromStr for ArityTarget {
+    type Err = CircuitError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "latency" => Ok(ArityTarget::Latency),
+            "memory" => Ok(ArityTarget::Memory),
+            _ => Err(CircuitError::InvalidArgument(format!(
+                "unknown arity target {}, expected latency or memory", s
+            ))),
+        }
+    }
+}
+
+/// the estimate for a tree with node arity `arity` and `t` leaves.
+/// - `proofs_per_level`: number of leaf proofs (level 0) and node proofs of each level up to the root.
+/// - `latency_cost`: the sum of the circuit sizes (2^degree_bits) of all proofs in the tree.
+/// - `memory_cost`: the size of the largest circuit in the tree.
+/// - `compatible`: the node verifies both leaf and node proofs with the leaf common data,
+///   so a tree with more than one node level can only be built when the leaf and node common data match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArityEstimate {
+    pub arity: usize,
+    pub leaf_degree_bits: usize,
+    pub node_degree_bits: usize,
+    pub proofs_per_level: Vec<usize>,
+    pub latency_cost: u128,
+    pub memory_cost: u128,
+    pub compatible: bool,
+}
+
+impl ArityEstimate {
+    /// total number of leaf and node proofs in the tree
+    pub fn total_proofs(&self) -> usize {
+        self.proofs_per_level.iter().sum()
+    }
+
+    fn cost(&self, target: ArityTarget) -> (u128, u128) {
+        match target {
+            ArityTarget::Latency => (self.latency_cost, self.memory_cost),
+            ArityTarget::Memory => (self.memory_cost, self.latency_cost),
+        }
+    }
+}
+
+/// returns the number of proofs on each level of a tree with arity `n` and `t` leaves,
+/// from the leaves (level 0) up to the root.
+/// `t` must be a power of `n` and at least `n`.
+pub fn proofs_per_level(t: usize, n: usize) -> Result<Vec<usize>> {
+    let mut levels = vec![t];
+    let mut current = t;
+    while n >= 2 && current > 1 && current.is_multiple_of(n) {
+        current /= n;
+        levels.push(current);
+    }
+    if n < 2 || current != 1 || levels.len() < 2 {
+        return Err(CircuitError::RecursionTreeError(format!(
+            "maximum number of proofs must be a power of {} and at least {}, got {}", n, n, t
+        )));
+    }
+    Ok(levels)
+}
+
+/// builds the candidate node circuits for a tree with `t` leaves and estimates
+/// the cost of the tree for each arity, see `ArityEstimate`.
+/// the leaf circuit does not depend on the arity, it is built once.
+pub struct ArityEstimator<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    leaf_circ_data: CircuitData<F, C, D>,
+    config: CircuitConfig,
+    t: usize,
+    phantom_data: PhantomData<H>
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> ArityEstimator<F, D, C, H> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    /// builds the leaf circuit for at most `t` inner proofs
+    pub fn new(
+        inner_verifier_data: VerifierCircuitData<F, C, D>,
+        config: CircuitConfig,
+        t: usize,
+    ) -> Result<Self> {
+        let leaf = LeafCircuit::<_,D,_,H>::new(inner_verifier_data, t);
+        let (_, leaf_circ_data) = leaf.build(config.clone())?;
+        Ok(Self{
+            leaf_circ_data,
+            config,
+            t,
+            phantom_data: PhantomData,
+        })
+    }
+
+    /// builds the node circuit with arity `N` and returns its estimate
+    pub fn estimate<const N: usize>(&self) -> Result<ArityEstimate> {
+        let proofs_per_level = proofs_per_level(self.t, N)?;
+
+        let node = NodeCircuit::<_,D,_,H,N>::new(self.leaf_circ_data.verifier_data(), self.t);
+        let (_, node_circ_data) = node.build(self.config.clone())?;
+
+        let leaf_degree_bits = self.leaf_circ_data.common.degree_bits();
+        let node_degree_bits = node_circ_data.common.degree_bits();
+        let leaf_size = 1u128 << leaf_degree_bits;
+        let node_size = 1u128 << node_degree_bits;
+        let node_proofs: usize = proofs_per_level[1..].iter().sum();
+
+        Ok(ArityEstimate{
+            arity: N,
+            leaf_degree_bits,
+            node_degree_bits,
+            latency_cost: leaf_size * proofs_per_level[0] as u128 + node_size * node_proofs as u128,
+            memory_cost: leaf_size.max(node_size),
+            compatible: proofs_per_level.len() == 2 || self.leaf_circ_data.common == node_circ_data.common,
+            proofs_per_level,
+        })
+    }
+
+    /// estimates every arity in `arities` for which `t` is a valid tree size,
+    /// returns `UnsupportedArity` for an arity not in `SUPPORTED_ARITIES`.
+    pub fn estimate_all(&self, arities: &[usize]) -> Result<Vec<ArityEstimate>> {
+        let mut estimates = vec![];
+        for &arity in arities {
+            if !SUPPORTED_ARITIES.contains(&arity) {
+                return Err(CircuitError::UnsupportedArity(arity, SUPPORTED_ARITIES.to_vec()));
+            }
+            // `t` must be a power of the arity
+            if proofs_per_level(self.t, arity).is_err() {
+                continue;
+            }
+            let estimate = match arity {
+                2 => self.estimate::<2>()?,
+                4 => self.estimate::<4>()?,
+                8 => self.estimate::<8>()?,
+                16 => self.estimate::<16>()?,
+                _ => return Err(CircuitError::UnsupportedArity(arity, SUPPORTED_ARITIES.to_vec())),
+            };
+            estimates.push(estimate);
+        }
+        Ok(estimates)
+    }
+
+    /// estimates all supported arities and returns the compatible one with the lowest cost for `target`,
+    /// ties are broken by the other cost and then by the smaller arity.
+    pub fn select(&self, target: ArityTarget) -> Result<ArityEstimate> {
+        let estimates = self.estimate_all(&SUPPORTED_ARITIES)?;
+        select_arity(&estimates, target)
+    }
+}
+
+/// returns the compatible estimate with the lowest cost for `target`,
+/// ties are broken by the other cost and then by the smaller arity.
+pub fn select_arity(estimates: &[ArityEstimate], target: ArityTarget) -> Result<ArityEstimate> {
+    estimates
+        .iter()
+        .filter(|e| e.compatible)
+        .min_by_key(|e| (e.cost(target), e.arity))
+        .cloned()
+        .ok_or_else(|| CircuitError::RecursionTreeError(
+            "no compatible arity for the given maximum number of proofs".to_string()
+        ))
+}
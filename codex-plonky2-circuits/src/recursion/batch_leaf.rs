@@ -0,0 +1,249 @@
+use std::marker::PhantomData;
+use plonky2::hash::hash_types::{HashOut, RichField};
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
+use crate::{error::CircuitError,Result};
+use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::serialization::{bool_target_vec, proof_with_pis_target_vec};
+use crate::recursion::dummy_gen::DummyProofGen;
+use crate::recursion::leaf::BUCKET_SIZE;
+use crate::recursion::utils::{bucket_count, compute_flag_buckets};
+
+/// recursion batch leaf circuit - verifies `M` inner proofs
+/// each inner proof can be real or dummy, the batch leaf at `index` holds the inner proofs
+/// with the indices [`index` * M, `index` * M + M - 1].
+/// it has the same public input as the `LeafCircuit`, so the `NodeCircuit` can be built on top of it.
+/// M: number of inner (sampling) proofs in each leaf
+/// inner_verifier_data: is the verifier data for the inner (sampling) circuit
+/// t: maximum number of inner (sampling) proofs in the tree, this sets the number of flag buckets.
+#[derive(Clone, Debug)]
+pub struct BatchLeafCircuit<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const M: usize,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    inner_verifier_data: VerifierCircuitData<F, C, D>,
+    t: usize,
+    phantom_data: PhantomData<H>
+}
+
+/// recursion batch leaf targets
+/// inner_proofs: M inner (sampling) proofs
+/// index: index of the leaf
+/// flags: boolean target for each inner proof for switching between real and dummy inner proof
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchLeafTargets <
+    const D: usize,
+>{
+    #[serde(with = "proof_with_pis_target_vec")]
+    pub inner_proofs: Vec<ProofWithPublicInputsTarget<D>>,
+    pub index: Target, // public input
+    #[serde(with = "bool_target_vec")]
+    pub flags: Vec<BoolTarget>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchLeafInput<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+>{
+    pub inner_proofs: Vec<ProofWithPublicInputs<F, C, D>>,
+    pub flags: Vec<bool>,
+    pub index: usize
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const M: usize,
+> BatchLeafCircuit<F,D,C,H,M> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    pub fn new(
+        inner_verifier_data: VerifierCircuitData<F, C, D>,
+        t: usize,
+    ) -> Self {
+        assert!(M.is_power_of_two(), "M is NOT a power of two");
+        Self {
+            inner_verifier_data,
+            t,
+            phantom_data: PhantomData::default(),
+        }
+    }
+
+    /// returns the maximum number of inner proofs `t`
+    pub fn max_proofs(&self) -> usize {
+        self.t
+    }
+
+    /// returns the number of leaves `t / M`
+    pub fn num_leaves(&self) -> usize {
+        self.t.div_ceil(M)
+    }
+
+    /// returns the verifier data of the inner (sampling) circuit
+    pub fn inner_verifier_data(&self) -> &VerifierCircuitData<F, C, D> {
+        &self.inner_verifier_data
+    }
+
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const M: usize,
+> Plonky2Circuit<F, C, D> for BatchLeafCircuit<F,D,C,H,M> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    type Targets = BatchLeafTargets<D>;
+    type Input = BatchLeafInput<F, D, C>;
+
+    /// The circuit logic:
+    /// - create M virtual proofs with public inputs and a flag for each
+    /// - hash the public inputs of each virtual proof, the hash of a dummy proof is zero
+    /// - hash the M hashes and make it public, like the node does for its N inner proofs
+    /// - add zero hash to the public inputs so that it shares the same structure as the tree node
+    /// - add virtual target for the index and only assign the index as public.
+    /// - compute the flag buckets from the index `index` * M + i and flag of each inner proof, add them up and make them public
+    /// - verify each inner proof in-circuit with the verifier data selected by its flag (either real or dummy).
+    ///
+    /// The public inputs are:
+    /// - the hash of the M inner public input hashes (4 Goldilocks).
+    /// - the zero hash (4 Goldilocks).
+    /// - the index of the leaf.
+    /// - the flag buckets = B Goldilocks where B = ceil(t/BUCKET_SIZE).
+    fn add_targets(&self, builder: &mut CircuitBuilder<F, D>, register_pi: bool) -> Result<BatchLeafTargets<D>> {
+
+        let inner_common = self.inner_verifier_data.common.clone();
+        let n_bucket: usize = bucket_count(self.t);
+
+        // M flags, one for each inner proof
+        let flags: Vec<BoolTarget> = (0..M).map(|_i| builder.add_virtual_bool_target_safe()).collect();
+
+        // the proof virtual targets - M proofs
+        let mut vir_proofs = vec![];
+        let mut pub_input = vec![];
+        for flag in &flags {
+            let vir_proof = builder.add_virtual_proof_with_pis(&inner_common);
+            // hash the public input, zero hash if the proof is dummy
+            let hash_inner_pub_input = builder.hash_n_to_hash_no_pad::<H>(vir_proof.public_inputs.clone());
+            for e in hash_inner_pub_input.elements {
+                pub_input.push(builder.mul(flag.target, e));
+            }
+            vir_proofs.push(vir_proof);
+        }
+
+        // hash the public input hashes of all M inner proofs & make it public
+        let hash_pub_input = builder.hash_n_to_hash_no_pad::<H>(pub_input);
+        if register_pi {
+            builder.register_public_inputs(&hash_pub_input.elements);
+        }
+
+        // pad the public input with constants so that it shares the same structure as the node
+        let zero_hash = builder.constant_hash(HashOut::<F>::default());
+        if register_pi {
+            builder.register_public_inputs(&zero_hash.elements);
+        }
+
+        // virtual constant target for the verifier data
+        let const_verifier_data = builder.constant_verifier_data(&self.inner_verifier_data.verifier_only);
+
+        // virtual constant target for dummy verifier data
+        let const_dummy_vd = builder.constant_verifier_data(
+            &DummyProofGen::<F,D,C>::gen_dummy_verifier_data(&inner_common)
+        );
+
+        // index: 0 <= index < t / M
+        let index = builder.add_virtual_public_input();
+
+        // compute the flag buckets of each inner proof at index `index` * M + i and add them up
+        let zero_target = builder.zero();
+        let mut flag_buckets: Vec<Target> = (0..n_bucket).map(|_i| zero_target).collect();
+        let m_const = builder.constant(F::from_canonical_u64(M as u64));
+        let first_inner_index = builder.mul(index, m_const);
+        for (i, &flag) in flags.iter().enumerate() {
+            let i_const = builder.constant(F::from_canonical_u64(i as u64));
+            let inner_index = builder.add(first_inner_index, i_const);
+            let inner_flag_buckets = compute_flag_buckets(builder, inner_index, flag, BUCKET_SIZE, n_bucket)?;
+            for j in 0..n_bucket {
+                flag_buckets[j] = builder.add(flag_buckets[j], inner_flag_buckets[j]);
+            }
+        }
+        if register_pi {
+            builder.register_public_inputs(&flag_buckets);
+        }
+
+        // verify the proofs in-circuit based on the flags
+        // true (1) -> real proof, false (0) -> dummy proof
+        for (&flag, vir_proof) in flags.iter().zip(&vir_proofs) {
+            let selected_vd = builder.select_verifier_data(flag, &const_verifier_data, &const_dummy_vd);
+            builder.verify_proof::<C>(vir_proof, &selected_vd, &inner_common);
+        }
+
+        // Make sure we have every gate to match `common_data`.
+        for g in &inner_common.gates {
+            builder.add_gate_to_gate_set(g.clone());
+        }
+
+        // return targets
+        let t = BatchLeafTargets {
+            inner_proofs: vir_proofs,
+            index,
+            flags,
+        };
+        Ok(t)
+
+    }
+
+    fn assign_targets(
+        &self, pw: &mut PartialWitness<F>,
+        targets: &Self::Targets,
+        input: &Self::Input,
+    ) -> Result<()> {
+        if input.inner_proofs.len() != M {
+            return Err(CircuitError::InnerProofsLengthMismatch(M, input.inner_proofs.len()));
+        }
+        if input.flags.len() != M {
+            return Err(CircuitError::FlagsLengthMismatch(M, input.flags.len()));
+        }
+        if input.index >= self.num_leaves() {
+            return Err(CircuitError::IndexOutOfRange(input.index, self.num_leaves()));
+        }
+
+        // assign the proofs
+        for i in 0..M {
+            pw.set_proof_with_pis_target(&targets.inner_proofs[i], &input.inner_proofs[i])
+                .map_err(|e| {
+                    CircuitError::ProofTargetAssignmentError("inner-proof".to_string(), e.to_string())
+                })?;
+        }
+
+        // Assign the global index.
+        pw.set_target(targets.index, F::from_canonical_u64(input.index as u64))
+            .map_err(|e| CircuitError::TargetAssignmentError(format!("index {}", input.index),e.to_string()))?;
+        // Assign the flags - switch between real & dummy proof
+        for i in 0..M {
+            pw.set_bool_target(targets.flags[i], input.flags[i])
+                .map_err(|e| CircuitError::TargetAssignmentError(format!("flag {}", input.flags[i]), e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::Serialize;
+use crate::{error::CircuitError, Result};
+use crate::serialization::{export_proof_with_pi, import_proof_with_pi, PROOF_JSON};
+
+/// storage for the leaf and node proofs of the recursion tree, so an aggregation can be resumed.
+/// a proof is identified by its level and index:
+/// level 0 holds the leaf proofs, level `l+1` holds the node proofs that aggregate the proofs at level `l`.
+pub trait TreeCheckpoint<
+    F: RichField + Extendable<D> + Poseidon2,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>: Sync {
+    /// returns true if the proof at `level` and `index` is stored
+    fn contains(&self, level: usize, index: usize) -> bool;
+
+    /// loads the proof at `level` and `index`, `None` if it is not stored
+    fn load(&self, level: usize, index: usize) -> Result<Option<ProofWithPublicInputs<F, C, D>>>;
+
+    /// stores the proof at `level` and `index`
+    fn store(&self, level: usize, index: usize, proof: &ProofWithPublicInputs<F, C, D>) -> Result<()>;
+}
+
+/// no checkpoints - nothing is stored
+pub(crate) struct NoCheckpoint;
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> TreeCheckpoint<F, C, D> for NoCheckpoint {
+    fn contains(&self, _level: usize, _index: usize) -> bool {
+        false
+    }
+
+    fn load(&self, _level: usize, _index: usize) -> Result<Option<ProofWithPublicInputs<F, C, D>>> {
+        Ok(None)
+    }
+
+    fn store(&self, _level: usize, _index: usize, _proof: &ProofWithPublicInputs<F, C, D>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// checkpoints in a work directory, the proof at `level` and `index` is written with `export_proof_with_pi`
+/// under `<work_dir>/level_<level>/<index>`.
+pub struct CheckpointDir<
+    F: RichField + Extendable<D> + Poseidon2,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    work_dir: PathBuf,
+    phantom_data: PhantomData<(F, C)>,
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> CheckpointDir<F, C, D> {
+    pub fn new<P: AsRef<Path>>(work_dir: P) -> Self {
+        Self {
+            work_dir: work_dir.as_ref().to_path_buf(),
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// returns the directory of the proof at `level` and `index`
+    pub fn proof_dir(&self, level: usize, index: usize) -> PathBuf {
+        self.work_dir.join(format!("level_{}", level)).join(index.to_string())
+    }
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2 + Serialize,
+    C: GenericConfig<D, F = F> + Serialize,
+    const D: usize,
+> TreeCheckpoint<F, C, D> for CheckpointDir<F, C, D> {
+    fn contains(&self, level: usize, index: usize) -> bool {
+        self.proof_dir(level, index).join(PROOF_JSON).exists()
+    }
+
+    fn load(&self, level: usize, index: usize) -> Result<Option<ProofWithPublicInputs<F, C, D>>> {
+        if !self.contains(level, index) {
+            return Ok(None);
+        }
+        import_proof_with_pi(self.proof_dir(level, index))
+            .map(Some)
+            .map_err(|e| CircuitError::CheckpointError(e.to_string()))
+    }
+
+    fn store(&self, level: usize, index: usize, proof: &ProofWithPublicInputs<F, C, D>) -> Result<()> {
+        // write to a temporary directory first and rename it,
+        // so that an interrupted write never leaves a partial checkpoint behind
+        let proof_dir = self.proof_dir(level, index);
+        let tmp_dir = proof_dir.with_extension("tmp");
+        export_proof_with_pi(proof, &tmp_dir)
+            .map_err(|e| CircuitError::CheckpointError(e.to_string()))?;
+        if proof_dir.exists() {
+            fs::remove_dir_all(&proof_dir)
+                .map_err(|e| CircuitError::CheckpointError(format!("{:?}: {}", proof_dir, e)))?;
+        }
+        fs::rename(&tmp_dir, &proof_dir)
+            .map_err(|e| CircuitError::CheckpointError(format!("{:?}: {}", proof_dir, e)))
+    }
+}
@@ -6,11 +6,15 @@ use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
 use plonky2_field::extension::Extendable;
 use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
 use crate::{error::CircuitError,Result};
 use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::serialization::proof_with_pis_target;
+use crate::recursion::utils::{assert_min_flag_count, bucket_count, compute_flag_count};
 
 /// recursion compression circuit
 /// verifies 1 inner proof and as result should shrink it
+/// optionally (see `with_proof_count`) the number of real proofs in the tree proof is added to the public input.
 #[derive(Clone, Debug)]
 pub struct CompressionCircuit<
     F: RichField + Extendable<D> + Poseidon2,
@@ -20,12 +24,23 @@ pub struct CompressionCircuit<
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
     inner_verifier_data: VerifierCircuitData<F, C, D>,
+    proof_count: Option<ProofCount>,
 }
 
-#[derive(Clone, Debug)]
+/// the real proof count output of the compression circuit
+/// t: maximum number of inner proofs in the tree, this sets the number of flag buckets.
+/// min_count: if set, the circuit checks that at least `min_count` inner proofs are real.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProofCount {
+    pub t: usize,
+    pub min_count: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CompressionTargets<
     const D: usize,
 >{
+    #[serde(with = "proof_with_pis_target")]
     pub inner_proof: ProofWithPublicInputsTarget<D>,
 }
 
@@ -50,9 +65,23 @@ impl<
     ) -> Self {
         Self{
             inner_verifier_data,
+            proof_count: None,
         }
     }
 
+    /// adds the number of real inner proofs (popcount of the flag buckets) of the tree proof
+    /// with `t` maximum inner proofs as the last public input.
+    /// if `min_count` is set, the circuit also checks that there are at least `min_count` real inner proofs.
+    pub fn with_proof_count(mut self, t: usize, min_count: Option<usize>) -> Self {
+        self.proof_count = Some(ProofCount{ t, min_count });
+        self
+    }
+
+    /// returns the proof count option, `None` if the proof count is not in the public input
+    pub fn proof_count(&self) -> Option<ProofCount> {
+        self.proof_count
+    }
+
 }
 
 impl<
@@ -82,6 +111,21 @@ impl<
         // verify the proofs in-circuit
         builder.verify_proof::<C>(&vir_proof, &const_verifier_data, &inner_common);
 
+        // count the real proofs from the flag buckets at public input [9..(9+n_bucket)]
+        if let Some(ProofCount{ t, min_count }) = self.proof_count {
+            let n_bucket = bucket_count(t);
+            if inner_common.num_public_inputs < 9 + n_bucket {
+                return Err(CircuitError::PublicInputLengthError(9 + n_bucket, inner_common.num_public_inputs));
+            }
+            let count = compute_flag_count(builder, &vir_proof.public_inputs[9..(9 + n_bucket)], t)?;
+            if let Some(min_count) = min_count {
+                assert_min_flag_count(builder, count, min_count, t)?;
+            }
+            if register_pi {
+                builder.register_public_input(count);
+            }
+        }
+
         // return targets
         let t = CompressionTargets {
             inner_proof: vir_proof,
@@ -0,0 +1,181 @@
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::witness::PartialWitness;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, VerifierCircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
+use crate::{error::CircuitError, Result};
+use crate::circuit_trait::Plonky2Circuit;
+use crate::recursion::compress::{CompressionCircuit, CompressionInput, CompressionTargets};
+
+/// when the compression chain stops, the chain stops after the first round that reaches either target
+/// and after the last round if none is reached.
+/// - `max_degree_bits`: the degree bits of the round circuit, the rounds after it are not built.
+/// - `max_proof_bytes`: the size of the round proof in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompressionTarget {
+    pub max_degree_bits: Option<usize>,
+    pub max_proof_bytes: Option<usize>,
+}
+
+/// a sequence of compression circuits, each verifying the proof of the previous one,
+/// the first one verifies the proofs of `inner_verifier_data` (e.g. the tree root or the compressed proof).
+/// each round is built with its own circuit config, e.g. a higher FRI rate and fewer query rounds
+/// in the last round give a smaller proof to wrap with `WrapCircuit`.
+/// the public input of the inner proof is passed through unchanged.
+pub struct CompressionChain<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    rounds: Vec<CompressionRound<F, D, C>>,
+    target: CompressionTarget,
+}
+
+/// one round of the compression chain with its circuit data and targets
+struct CompressionRound<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    circuit: CompressionCircuit<F, D, C>,
+    circ_data: CircuitData<F, C, D>,
+    targets: CompressionTargets<D>,
+}
+
+/// the output of the compression chain: the proof of the last applied round and its verifier data,
+/// which is the input of `WrapCircuit`, with the circuit and proof size of each applied round.
+#[derive(Clone, Debug)]
+pub struct CompressionChainOutput<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+>{
+    pub proof: ProofWithPublicInputs<F, C, D>,
+    pub verifier_data: VerifierCircuitData<F, C, D>,
+    /// number of applied rounds
+    pub rounds: usize,
+    /// the degree bits of the circuit of each applied round
+    pub round_degree_bits: Vec<usize>,
+    /// the size in bytes of the proof of each applied round
+    pub round_proof_bytes: Vec<usize>,
+}
+
+/// returns the standard recursion config with the given FRI `rate_bits` and `num_query_rounds`
+pub fn compression_config(rate_bits: usize, num_query_rounds: usize) -> CircuitConfig {
+    let mut config = CircuitConfig::standard_recursion_config();
+    config.fri_config.rate_bits = rate_bits;
+    config.fri_config.num_query_rounds = num_query_rounds;
+    config
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+> CompressionChain<F, D, C> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    /// builds a compression round for each config in `configs` in order,
+    /// stops building once a round circuit reaches `target.max_degree_bits`.
+    pub fn build(
+        inner_verifier_data: VerifierCircuitData<F, C, D>,
+        configs: Vec<CircuitConfig>,
+        target: CompressionTarget,
+    ) -> Result<Self> {
+        if configs.is_empty() {
+            return Err(CircuitError::InvalidArgument(
+                "the compression chain needs at least one round".to_string()
+            ));
+        }
+
+        let mut rounds: Vec<CompressionRound<F, D, C>> = vec![];
+        let mut verifier_data = inner_verifier_data;
+        for config in configs {
+            let circuit = CompressionCircuit::new(verifier_data);
+            let (targets, circ_data) = circuit.build(config)?;
+
+            verifier_data = circ_data.verifier_data();
+            let reached = target.max_degree_bits
+                .is_some_and(|max| circ_data.common.degree_bits() <= max);
+            rounds.push(CompressionRound{ circuit, circ_data, targets });
+            if reached {
+                break;
+            }
+        }
+
+        Ok(Self{
+            rounds,
+            target,
+        })
+    }
+
+    /// returns the number of built rounds
+    pub fn num_rounds(&self) -> usize {
+        self.rounds.len()
+    }
+
+    /// returns the degree bits of the circuit of each built round
+    pub fn degree_bits(&self) -> Vec<usize> {
+        self.rounds.iter().map(|r| r.circ_data.common.degree_bits()).collect()
+    }
+
+    /// returns the verifier data of the round with index `round`
+    pub fn get_verifier_data(&self, round: usize) -> Result<VerifierCircuitData<F, C, D>> {
+        self.rounds.get(round)
+            .map(|r| r.circ_data.verifier_data())
+            .ok_or(CircuitError::IndexOutOfRange(round, self.rounds.len()))
+    }
+
+    /// applies the rounds to `proof` in order until the proof reaches the target (see `CompressionTarget`)
+    pub fn prove(
+        &self,
+        proof: ProofWithPublicInputs<F, C, D>,
+    ) -> Result<CompressionChainOutput<F, D, C>> {
+        let mut output = None;
+        let mut round_degree_bits = vec![];
+        let mut round_proof_bytes = vec![];
+        let mut proof = proof;
+        for (i, round) in self.rounds.iter().enumerate() {
+            let mut pw = PartialWitness::<F>::new();
+            round.circuit.assign_targets(&mut pw, &round.targets, &CompressionInput{ inner_proof: proof })?;
+            proof = round.circ_data.prove(pw)
+                .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))?;
+            let proof_bytes = proof.to_bytes().len();
+            round_degree_bits.push(round.circ_data.common.degree_bits());
+            round_proof_bytes.push(proof_bytes);
+
+            let reached = self.target.max_degree_bits.is_some_and(|max| round.circ_data.common.degree_bits() <= max)
+                || self.target.max_proof_bytes.is_some_and(|max| proof_bytes <= max);
+            output = Some(CompressionChainOutput{
+                proof: proof.clone(),
+                verifier_data: round.circ_data.verifier_data(),
+                rounds: i + 1,
+                round_degree_bits: round_degree_bits.clone(),
+                round_proof_bytes: round_proof_bytes.clone(),
+            });
+            if reached {
+                break;
+            }
+        }
+        output.ok_or(CircuitError::RecursionTreeError(
+            "the compression chain has no rounds".to_string()
+        ))
+    }
+
+    /// verifies the output of the chain with the verifier data of its last applied round
+    pub fn verify(&self, output: &CompressionChainOutput<F, D, C>) -> Result<()> {
+        if output.rounds == 0 {
+            return Err(CircuitError::IndexOutOfRange(0, self.rounds.len()));
+        }
+        let verifier_data = self.get_verifier_data(output.rounds - 1)?;
+        verifier_data.verify(output.proof.clone())
+            .map_err(|e| CircuitError::InvalidProofError(e.to_string()))
+    }
+}
@@ -0,0 +1,266 @@
+use std::marker::PhantomData;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, RichField};
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
+use crate::{error::CircuitError, Result};
+use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::serialization::{bool_target_vec, proof_with_pis_target_vec};
+use crate::recursion::arity::proofs_per_level;
+use crate::recursion::dummy_gen::DummyProofGen;
+use crate::recursion::leaf::BUCKET_SIZE;
+use crate::recursion::utils::bucket_count;
+
+/// cross-prover aggregation circuit - aggregates the root proofs of `p` trees proved by different provers.
+/// the tree at position `i` covers the slots [`i` * t, (`i` + 1) * t - 1] of the global tree with `p` * t slots,
+/// its flag buckets are moved by the global index offset `i` * t.
+/// the public input has the layout of the root of one tree with arity `N` over all `p` * t slots
+/// (see `verify_tree_public_input`): the roots are combined with the same pi hash tree and
+/// the verifier data hash is the one of the aggregated roots.
+/// if t = `N` the aggregated roots are nodes of the first level, which carry the leaf verifier data hash,
+/// so `vd_hash` and the republished verifier data hash are the leaf one, not the node one of a single big tree.
+/// - `N`: arity of the aggregated trees.
+/// - inner_verifier_data: the verifier data of the root proofs, i.e. the node or compression verifier data.
+/// - vd_hash: the verifier data hash in the public input of the root proofs.
+/// - t: maximum number of inner proofs of each aggregated tree.
+/// - p: maximum number of aggregated trees, must be a power of `N`.
+#[derive(Clone, Debug)]
+pub struct CrossTreeCircuit<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    inner_verifier_data: VerifierCircuitData<F, C, D>,
+    vd_hash: HashOut<F>,
+    t: usize,
+    p: usize,
+    phantom_data: PhantomData<H>
+}
+
+/// cross-prover aggregation targets
+/// inner_proofs: the `p` tree root proofs
+/// flags: boolean target for each root proof for switching between real and dummy proof
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CrossTreeTargets<
+    const D: usize,
+>{
+    #[serde(with = "proof_with_pis_target_vec")]
+    pub inner_proofs: Vec<ProofWithPublicInputsTarget<D>>,
+    #[serde(with = "bool_target_vec")]
+    pub flags: Vec<BoolTarget>,
+}
+
+/// the `p` tree root proofs ordered by their slot range, the missing trees are dummy proofs with false flags
+/// (see `CrossTreeCircuit::dummy_proof`).
+#[derive(Clone, Debug)]
+pub struct CrossTreeInput<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+>{
+    pub inner_proofs: Vec<ProofWithPublicInputs<F, C, D>>,
+    pub flags: Vec<bool>,
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> CrossTreeCircuit<F,D,C,H,N> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    pub fn new(
+        inner_verifier_data: VerifierCircuitData<F, C, D>,
+        vd_hash: HashOut<F>,
+        t: usize,
+        p: usize,
+    ) -> Self {
+        Self {
+            inner_verifier_data,
+            vd_hash,
+            t,
+            p,
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// returns the maximum number of inner proofs of the global tree `p` * `t`
+    pub fn max_proofs(&self) -> usize {
+        self.p * self.t
+    }
+
+    /// returns a dummy proof with zero public input for a missing tree
+    pub fn dummy_proof(&self) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let dummy_circ_data = DummyProofGen::<F, D, C>::gen_dummy_circ_data(&self.inner_verifier_data.common);
+        DummyProofGen::<F, D, C>::gen_dummy_proof(&dummy_circ_data, Default::default())
+    }
+
+    /// returns `hash` if `flag` is true, the zero hash otherwise
+    fn select_or_zero(builder: &mut CircuitBuilder<F, D>, flag: BoolTarget, hash: HashOutTarget) -> HashOutTarget {
+        HashOutTarget {
+            elements: hash.elements.map(|e| builder.mul(flag.target, e)),
+        }
+    }
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> Plonky2Circuit<F, C, D> for CrossTreeCircuit<F,D,C,H,N> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    type Targets = CrossTreeTargets<D>;
+    type Input = CrossTreeInput<F, D, C>;
+
+    /// The circuit logic:
+    /// - create `p` virtual root proofs and a flag for each, verify each with the verifier data selected
+    ///   by its flag (either real or dummy).
+    /// - check that each real root has the expected verifier data hash and index 0.
+    /// - hash the pi hashes of the roots level by level with arity `N`, dummy roots and
+    ///   chunks with only dummy roots hash to zero, the same way as `get_pi_hash_levels`.
+    /// - move the flag buckets of each real root by its global index offset and add them up.
+    ///
+    /// The public inputs are:
+    /// - the combined pi hash (4 Goldilocks).
+    /// - the verifier data hash of the roots (4 Goldilocks).
+    /// - the index 0.
+    /// - the global flag buckets = B Goldilocks where B = ceil(`p` * t / BUCKET_SIZE).
+    fn add_targets(&self, builder: &mut CircuitBuilder<F, D>, register_pi: bool) -> Result<CrossTreeTargets<D>> {
+        proofs_per_level(self.p, N)?;
+        proofs_per_level(self.t, N)?;
+        let inner_common = self.inner_verifier_data.common.clone();
+        let n_bucket = bucket_count(self.t);
+        if inner_common.num_public_inputs < 9 + n_bucket {
+            return Err(CircuitError::PublicInputLengthError(9 + n_bucket, inner_common.num_public_inputs));
+        }
+        let n_global_bucket = bucket_count(self.max_proofs());
+
+        let zero_target = builder.zero();
+        let const_vd_hash = builder.constant_hash(self.vd_hash);
+
+        // constant targets for the verifier data of the roots and the dummy verifier data
+        let const_verifier_data = builder.constant_verifier_data(&self.inner_verifier_data.verifier_only);
+        let const_dummy_vd = builder.constant_verifier_data(
+            &DummyProofGen::<F,D,C>::gen_dummy_verifier_data(&inner_common)
+        );
+
+        let mut vir_proofs = vec![];
+        let mut flags = vec![];
+        let mut pi_hashes = vec![];
+        let mut flag_buckets: Vec<Target> = (0..n_global_bucket).map(|_i| zero_target).collect();
+        for i in 0..self.p {
+            let vir_proof = builder.add_virtual_proof_with_pis(&inner_common);
+            let flag = builder.add_virtual_bool_target_safe();
+
+            // verify the proof with the verifier data selected by the flag
+            // flag: true -> real, false -> dummy
+            let selected_vd = builder.select_verifier_data(flag, &const_verifier_data, &const_dummy_vd);
+            builder.verify_proof::<C>(&vir_proof, &selected_vd, &inner_common);
+
+            let inner_pub_input = vir_proof.public_inputs.clone();
+            // public input [4...8] of a real root must be the expected verifier data hash
+            for k in 0..4 {
+                let diff = builder.sub(inner_pub_input[4 + k], const_vd_hash.elements[k]);
+                let product = builder.mul(diff, flag.target);
+                builder.connect(product, zero_target);
+            }
+            // public input [8] of a real root must be the index 0
+            let product = builder.mul(inner_pub_input[8], flag.target);
+            builder.connect(product, zero_target);
+
+            // public input [0...4] contains the pi hash, zero for a dummy root
+            let pi_hash = HashOutTarget::from_vec(inner_pub_input[0..4].to_vec());
+            pi_hashes.push(Self::select_or_zero(builder, flag, pi_hash));
+
+            // public input [9..(9+n_bucket)] contains the flag buckets,
+            // the flag of local index `l` is the flag of global index `i` * t + `l`
+            for j in 0..n_bucket {
+                let offset = i * self.t + j * BUCKET_SIZE;
+                let shift = builder.constant(F::from_canonical_u64(1u64 << (offset % BUCKET_SIZE)));
+                let bucket = builder.mul(inner_pub_input[9 + j], flag.target);
+                let global_bucket = offset / BUCKET_SIZE;
+                flag_buckets[global_bucket] = builder.mul_add(bucket, shift, flag_buckets[global_bucket]);
+            }
+
+            vir_proofs.push(vir_proof);
+            flags.push(flag);
+        }
+
+        // hash the pi hashes of the roots level by level up to the global root
+        let mut is_real = flags.clone();
+        while pi_hashes.len() > 1 {
+            let mut next_pi_hashes = vec![];
+            let mut next_is_real = vec![];
+            for (chunk, chunk_is_real) in pi_hashes.chunks(N).zip(is_real.chunks(N)) {
+                // the chunk is real if any of its children is real
+                let mut pi_flat = vec![];
+                let mut chunk_real = builder._false();
+                for (h, &real) in chunk.iter().zip(chunk_is_real) {
+                    pi_flat.extend_from_slice(&h.elements);
+                    chunk_real = builder.or(chunk_real, real);
+                }
+                let pi_hash = builder.hash_n_to_hash_no_pad::<H>(pi_flat);
+                next_pi_hashes.push(Self::select_or_zero(builder, chunk_real, pi_hash));
+                next_is_real.push(chunk_real);
+            }
+            pi_hashes = next_pi_hashes;
+            is_real = next_is_real;
+        }
+
+        if register_pi {
+            builder.register_public_inputs(&pi_hashes[0].elements);
+            builder.register_public_inputs(&const_vd_hash.elements);
+            builder.register_public_input(zero_target);
+            builder.register_public_inputs(&flag_buckets);
+        }
+
+        // Make sure we have every gate to match `common_data`.
+        for g in &inner_common.gates {
+            builder.add_gate_to_gate_set(g.clone());
+        }
+
+        Ok(CrossTreeTargets {
+            inner_proofs: vir_proofs,
+            flags,
+        })
+    }
+
+    fn assign_targets(
+        &self, pw: &mut PartialWitness<F>,
+        targets: &Self::Targets,
+        input: &Self::Input,
+    ) -> Result<()> {
+        if input.inner_proofs.len() != self.p {
+            return Err(CircuitError::InnerProofsLengthMismatch(self.p, input.inner_proofs.len()));
+        }
+        if input.flags.len() != self.p {
+            return Err(CircuitError::FlagsLengthMismatch(self.p, input.flags.len()));
+        }
+
+        for i in 0..self.p {
+            pw.set_proof_with_pis_target(&targets.inner_proofs[i], &input.inner_proofs[i])
+                .map_err(|e| {
+                    CircuitError::ProofTargetAssignmentError(format!("root-proof {}", i), e.to_string())
+                })?;
+            pw.set_bool_target(targets.flags[i], input.flags[i])
+                .map_err(|e| CircuitError::TargetAssignmentError(format!("flag {}", input.flags[i]), e.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
@@ -63,11 +63,20 @@ impl<F, const D: usize, C> DummyProofGen<F, D, C>
         nonzero_public_inputs: HashMap<usize, F>,
     ) -> Result<(ProofWithPublicInputs<F, C, D>, VerifierCircuitData<F, C, D>)> {
         let circuit_data = Self::gen_dummy_circ_data(common_data);
-        let proof = dummy_proof::<F, C, D>(&circuit_data, nonzero_public_inputs)
-            .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))?;
+        let proof = Self::gen_dummy_proof(&circuit_data, nonzero_public_inputs)?;
         Ok((proof, circuit_data.verifier_data()))
     }
 
+    /// Generates a dummy proof using an already built dummy circuit (see `gen_dummy_circ_data`).
+    /// This avoids rebuilding the dummy circuit when many dummy proofs are needed.
+    pub fn gen_dummy_proof(
+        dummy_circuit_data: &CircuitData<F, C, D>,
+        nonzero_public_inputs: HashMap<usize, F>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        dummy_proof::<F, C, D>(dummy_circuit_data, nonzero_public_inputs)
+            .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))
+    }
+
     /// Generates a dummy proof and verifier data with zero public inputs.
     pub fn gen_dummy_proof_and_vd_zero_pi(
         common_data: &CommonCircuitData<F, D>,
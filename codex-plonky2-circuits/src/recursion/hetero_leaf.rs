@@ -0,0 +1,370 @@
+use std::marker::PhantomData;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, RichField, NUM_HASH_OUT_ELTS};
+use plonky2::hash::hashing::PlonkyPermutation;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CommonCircuitData, VerifierCircuitData, VerifierCircuitTarget, VerifierOnlyCircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
+use crate::{error::CircuitError, Result};
+use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::keyed_compress::key_compress_circuit;
+use crate::circuits::merkle_circuit::{KEY_BOTTOM_LAYER, KEY_NONE};
+use crate::circuits::serialization::{bool_target, proof_with_pis_target, verifier_circuit_target, SerializableHashOutTarget};
+use crate::circuits::utils::select_hash;
+use crate::recursion::dummy_gen::DummyProofGen;
+use crate::recursion::leaf::BUCKET_SIZE;
+use crate::recursion::utils::{bucket_count, compute_flag_buckets, get_hash_of_verifier_data};
+
+/// An allow-list of inner (sampling) circuits, committed with the Codex safe Merkle tree
+/// over the verifier data hashes (see `get_hash_of_verifier_data`).
+/// the leaves are padded with zero hashes to a power of two (at least 2), so the bottom layer
+/// is compressed with `KEY_BOTTOM_LAYER` and the layers above with `KEY_NONE`.
+/// all circuits must share the same `CommonCircuitData` since the leaf verifies the inner proof
+/// with a single common data, only the verifier-only data (circuit digest and constants/sigmas cap) can differ.
+#[derive(Clone, Debug)]
+pub struct VerifierKeyAllowList<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    verifier_data: Vec<VerifierCircuitData<F, C, D>>,
+    // layers[0] are the (padded) leaves, the last layer is the root
+    layers: Vec<Vec<HashOut<F>>>,
+    phantom_data: PhantomData<H>,
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> VerifierKeyAllowList<F,D,C,H> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    /// builds the allow-list from the verifier data of the allowed inner circuits
+    pub fn new(verifier_data: Vec<VerifierCircuitData<F, C, D>>) -> Result<Self> {
+        let first = verifier_data.first().ok_or_else(|| {
+            CircuitError::InvalidArgument("the allow-list must contain at least one verifier data".to_string())
+        })?;
+        if let Some(i) = verifier_data.iter().position(|vd| vd.common != first.common) {
+            return Err(CircuitError::InvalidArgument(format!(
+                "the inner circuits must share the same common circuit data, entry {} differs from entry 0", i
+            )));
+        }
+
+        let n_leaves = verifier_data.len().next_power_of_two().max(2);
+        let mut leaves: Vec<HashOut<F>> = verifier_data
+            .iter()
+            .map(|vd| get_hash_of_verifier_data::<F,D,C,H>(vd))
+            .collect();
+        leaves.resize(n_leaves, HashOut::<F>::ZERO);
+
+        let mut layers = vec![leaves];
+        let mut key = KEY_BOTTOM_LAYER;
+        while layers.last().unwrap().len() > 1 {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| Self::key_compress(pair[0], pair[1], key))
+                .collect();
+            layers.push(next);
+            key = KEY_NONE;
+        }
+
+        Ok(Self {
+            verifier_data,
+            layers,
+            phantom_data: PhantomData,
+        })
+    }
+
+    /// native keyed compression, same as `key_compress_circuit`
+    fn key_compress(x: HashOut<F>, y: HashOut<F>, key: u64) -> HashOut<F> {
+        let mut perm = <H as Hasher<F>>::Permutation::new(core::iter::repeat(F::ZERO));
+        perm.set_from_slice(&x.elements, 0);
+        perm.set_from_slice(&y.elements, NUM_HASH_OUT_ELTS);
+        perm.set_elt(F::from_canonical_u64(key), NUM_HASH_OUT_ELTS * 2);
+        perm.permute();
+        HashOut {
+            elements: perm.squeeze()[..NUM_HASH_OUT_ELTS].try_into().unwrap(),
+        }
+    }
+
+    /// returns the Merkle root of the allow-list
+    pub fn root(&self) -> HashOut<F> {
+        self.layers.last().unwrap()[0]
+    }
+
+    /// returns the depth of the Merkle tree i.e. the length of a Merkle path
+    pub fn depth(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    /// returns the number of allowed inner circuits
+    pub fn num_circuits(&self) -> usize {
+        self.verifier_data.len()
+    }
+
+    /// returns the common circuit data shared by all allowed inner circuits
+    pub fn common(&self) -> &CommonCircuitData<F, D> {
+        &self.verifier_data[0].common
+    }
+
+    /// returns the verifier data of the allowed inner circuit at `index`
+    pub fn verifier_data(&self, index: usize) -> Result<&VerifierCircuitData<F, C, D>> {
+        self.verifier_data.get(index).ok_or(CircuitError::IndexOutOfRange(index, self.num_circuits()))
+    }
+
+    /// returns the position in the allow-list of the circuit with verifier-only data `verifier_only`, if any
+    pub fn position(&self, verifier_only: &VerifierOnlyCircuitData<C, D>) -> Option<usize> {
+        self.verifier_data.iter().position(|vd| &vd.verifier_only == verifier_only)
+    }
+
+    /// returns the Merkle path (sibling hashes from the bottom layer up) of the entry at `index`
+    pub fn get_path(&self, index: usize) -> Result<Vec<HashOut<F>>> {
+        if index >= self.num_circuits() {
+            return Err(CircuitError::IndexOutOfRange(index, self.num_circuits()));
+        }
+        Ok((0..self.depth())
+            .map(|level| self.layers[level][(index >> level) ^ 1])
+            .collect())
+    }
+}
+
+/// recursion leaf circuit for several inner (sampling) circuit variants - verifies 1 inner proof
+/// the inner proof can be real or dummy, a real proof can come from any circuit in the allow-list.
+/// allow_list: the allowed inner circuits, its Merkle root is a constant of the circuit.
+/// t: maximum number of inner (sampling) proofs in the tree, this sets the number of flag buckets.
+/// the public input has the same structure as `LeafCircuit`, with the allow-list root
+/// in place of the zero hash, so it can be aggregated with `NodeCircuit`.
+#[derive(Clone, Debug)]
+pub struct HeterogeneousLeafCircuit<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    allow_list: VerifierKeyAllowList<F, D, C, H>,
+    t: usize,
+}
+
+/// heterogeneous leaf targets
+/// inner_proof: inner (sampling) proof
+/// inner_verifier_data: verifier data of the inner circuit, must be in the allow-list
+/// vd_index: position of the inner verifier data in the allow-list
+/// vd_path: Merkle path of the inner verifier data hash to the allow-list root
+/// index: index of the leaf
+/// flag: boolean target for switching between real and dummy inner proof
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HeterogeneousLeafTargets<
+    const D: usize,
+>{
+    #[serde(with = "proof_with_pis_target")]
+    pub inner_proof: ProofWithPublicInputsTarget<D>,
+    #[serde(with = "verifier_circuit_target")]
+    pub inner_verifier_data: VerifierCircuitTarget,
+    pub vd_index: Target,
+    pub vd_path: Vec<SerializableHashOutTarget>,
+    pub index: Target, // public input
+    #[serde(with = "bool_target")]
+    pub flag: BoolTarget,
+}
+
+/// input to the heterogeneous leaf
+/// vd_index: position in the allow-list of the circuit that produced `inner_proof`,
+/// for a dummy proof any position in the allow-list can be used.
+#[derive(Clone, Debug)]
+pub struct HeterogeneousLeafInput<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+>{
+    pub inner_proof: ProofWithPublicInputs<F, C, D>,
+    pub vd_index: usize,
+    pub flag: bool,
+    pub index: usize
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> HeterogeneousLeafCircuit<F,D,C,H> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    pub fn new(
+        allow_list: VerifierKeyAllowList<F, D, C, H>,
+        t: usize,
+    ) -> Self {
+        Self {
+            allow_list,
+            t,
+        }
+    }
+
+    /// returns the maximum number of inner proofs `t`
+    pub fn max_proofs(&self) -> usize {
+        self.t
+    }
+
+    /// returns the allow-list of the inner circuits
+    pub fn allow_list(&self) -> &VerifierKeyAllowList<F, D, C, H> {
+        &self.allow_list
+    }
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> Plonky2Circuit<F, C, D> for HeterogeneousLeafCircuit<F,D,C,H> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    type Targets = HeterogeneousLeafTargets<D>;
+    type Input = HeterogeneousLeafInput<F, D, C>;
+
+    /// The circuit logic:
+    /// - create a virtual proof with public inputs and a virtual verifier data for the inner circuit
+    /// - hash the public inputs of the virtual proof and make it public
+    /// - hash the inner verifier data (same as `get_hash_of_verifier_data`) and check its Merkle path
+    ///   to the constant allow-list root, the path bits are the bits of `vd_index`
+    /// - make the allow-list root public, in place of the zero hash of `LeafCircuit`
+    /// - compute the flag buckets from the index and flag and make them public
+    /// - select the inner verifier data or the dummy verifier data based on the flag,
+    ///   and verify the inner proof in-circuit using the selected verifier data.
+    ///
+    /// The public inputs are:
+    /// - the hash of the public inputs of the inner proof (4 Goldilocks).
+    /// - the allow-list root (4 Goldilocks).
+    /// - the index and the flag buckets = M Goldilocks where M = ceil(t/BUCKET_SIZE).
+    fn add_targets(&self, builder: &mut CircuitBuilder<F, D>, register_pi: bool) -> Result<Self::Targets> {
+        let inner_common = self.allow_list.common().clone();
+        let n_bucket: usize = bucket_count(self.t);
+        let depth = self.allow_list.depth();
+
+        // the proof virtual target
+        let vir_proof = builder.add_virtual_proof_with_pis(&inner_common);
+        let inner_pub_input = vir_proof.public_inputs.clone();
+
+        // hash the public input & make it public
+        let hash_inner_pub_input = builder.hash_n_to_hash_no_pad::<H>(inner_pub_input);
+        if register_pi {
+            builder.register_public_inputs(&hash_inner_pub_input.elements);
+        }
+
+        // the inner verifier data is supplied by the prover, so it must be in the allow-list
+        let inner_verifier_data = builder.add_virtual_verifier_data(inner_common.config.fri_config.cap_height);
+        let mut vd_pub_input = vec![];
+        vd_pub_input.extend_from_slice(&inner_verifier_data.circuit_digest.elements);
+        for i in 0..inner_common.config.fri_config.num_cap_elements() {
+            vd_pub_input.extend_from_slice(&inner_verifier_data.constants_sigmas_cap.0[i].elements);
+        }
+        let vd_hash = builder.hash_n_to_hash_no_pad::<H>(vd_pub_input);
+
+        // Merkle path of the verifier data hash, the allow-list is a full tree so no odd keys are needed
+        let vd_index = builder.add_virtual_target();
+        let path_bits = builder.split_le(vd_index, depth);
+        let vd_path: Vec<HashOutTarget> = (0..depth).map(|_| builder.add_virtual_hash()).collect();
+        let mut state = vd_hash;
+        for (i, (&bit, &sibling)) in path_bits.iter().zip(&vd_path).enumerate() {
+            let key_val = if i == 0 { KEY_BOTTOM_LAYER } else { KEY_NONE };
+            let key = builder.constant(F::from_canonical_u64(key_val));
+            let left = select_hash(builder, bit, sibling, state);
+            let right = select_hash(builder, bit, state, sibling);
+            state = key_compress_circuit::<F, D, H>(builder, left, right, key);
+        }
+        let allow_list_root = builder.constant_hash(self.allow_list.root());
+        builder.connect_hashes(state, allow_list_root);
+        if register_pi {
+            builder.register_public_inputs(&allow_list_root.elements);
+        }
+
+        // virtual constant target for dummy verifier data
+        let const_dummy_vd = builder.constant_verifier_data(
+            &DummyProofGen::<F,D,C>::gen_dummy_verifier_data(&inner_common)
+        );
+
+        // index: 0 <= index < t where t = maximum number of proofs
+        let index = builder.add_virtual_public_input();
+        let flag = builder.add_virtual_bool_target_safe();
+
+        // compute the flag buckets from the index and flag
+        let computed_flag_buckets = compute_flag_buckets(builder, index, flag, BUCKET_SIZE, n_bucket)?;
+        if register_pi {
+            builder.register_public_inputs(&computed_flag_buckets);
+        }
+
+        // verify the proofs in-circuit based on the
+        // true (1) -> real proof, false (0) -> dummy proof
+        let selected_vd = builder.select_verifier_data(flag, &inner_verifier_data, &const_dummy_vd);
+        builder.verify_proof::<C>(&vir_proof, &selected_vd, &inner_common);
+
+        // Make sure we have every gate to match `common_data`.
+        for g in &inner_common.gates {
+            builder.add_gate_to_gate_set(g.clone());
+        }
+
+        Ok(HeterogeneousLeafTargets {
+            inner_proof: vir_proof,
+            inner_verifier_data,
+            vd_index,
+            vd_path: vd_path.into_iter().map(SerializableHashOutTarget::from).collect(),
+            index,
+            flag,
+        })
+    }
+
+    fn assign_targets(
+        &self, pw: &mut PartialWitness<F>,
+        targets: &Self::Targets,
+        input: &Self::Input,
+    ) -> Result<()> {
+        if input.index >= self.t {
+            return Err(CircuitError::IndexOutOfRange(input.index, self.t));
+        }
+        let inner_verifier_data = self.allow_list.verifier_data(input.vd_index)?;
+        let vd_path = self.allow_list.get_path(input.vd_index)?;
+
+        // assign the proof
+        pw.set_proof_with_pis_target(&targets.inner_proof, &input.inner_proof)
+            .map_err(|e| {
+                CircuitError::ProofTargetAssignmentError("inner-proof".to_string(), e.to_string())
+            })?;
+
+        // assign the inner verifier data and its Merkle path
+        pw.set_verifier_data_target(&targets.inner_verifier_data, &inner_verifier_data.verifier_only)
+            .map_err(|e| {
+                CircuitError::VerifierDataTargetAssignmentError(e.to_string())
+            })?;
+        pw.set_target(targets.vd_index, F::from_canonical_u64(input.vd_index as u64))
+            .map_err(|e| CircuitError::TargetAssignmentError(format!("vd index {}", input.vd_index), e.to_string()))?;
+        for (i, sibling) in vd_path.iter().enumerate() {
+            pw.set_hash_target(targets.vd_path[i].0, *sibling)
+                .map_err(|e| {
+                    CircuitError::HashTargetAssignmentError(format!("vd path {}", i), e.to_string())
+                })?;
+        }
+
+        // Assign the global index.
+        pw.set_target(targets.index, F::from_canonical_u64(input.index as u64))
+            .map_err(|e| CircuitError::TargetAssignmentError(format!("index {}", input.index), e.to_string()))?;
+        // Assign the flag/condition for real/fake inner proof.
+        pw.set_bool_target(targets.flag, input.flag)
+            .map_err(|e| CircuitError::TargetAssignmentError(format!("flag {}", input.flag), e.to_string()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::{BTreeMap, BTreeSet};
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use crate::{error::CircuitError, Result};
+use crate::recursion::tree::TreeRecursion;
+
+/// incremental (streaming) tree aggregation
+/// accepts the inner proofs one by one with their leaf index:
+/// - the leaf is proved as soon as the inner proof is added.
+/// - once all `N` siblings of a subtree exist, they are merged into a node proof right away.
+/// - the tree can be finalized at any time, the missing positions are filled with dummy subtrees.
+pub struct IncrementalTree<
+    'a,
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    tree: &'a TreeRecursion<F, D, C, H, N>,
+    // number of node levels, the root is at height `height` and N^height = t
+    height: usize,
+    // proven subtrees that are not merged yet: subtrees[h] maps the index to the proof of the subtree
+    // with height h, i.e. subtrees[0] holds the leaf proofs
+    subtrees: Vec<BTreeMap<usize, ProofWithPublicInputs<F, C, D>>>,
+    // indices of the added inner proofs
+    indices: BTreeSet<usize>,
+}
+
+impl<
+    'a,
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> IncrementalTree<'a, F, D, C, H, N> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    pub fn new(tree: &'a TreeRecursion<F, D, C, H, N>) -> Self {
+        let mut height = 1;
+        let mut capacity = N;
+        while capacity < tree.get_max_proofs() {
+            capacity *= N;
+            height += 1;
+        }
+        Self {
+            tree,
+            height,
+            subtrees: vec![BTreeMap::new(); height + 1],
+            indices: BTreeSet::new(),
+        }
+    }
+
+    /// returns the indices of the inner proofs added so far
+    pub fn indices(&self) -> Vec<usize> {
+        self.indices.iter().cloned().collect()
+    }
+
+    /// returns the number of proven subtrees that are not merged yet
+    pub fn num_subtrees(&self) -> usize {
+        self.subtrees.iter().map(|s| s.len()).sum()
+    }
+
+    /// adds the inner proof at the given leaf index:
+    /// proves the leaf, then merges every complete subtree on the path to the root.
+    pub fn add_proof(
+        &mut self,
+        index: usize,
+        inner_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<()> {
+        if self.indices.contains(&index) {
+            return Err(CircuitError::RecursionTreeError(format!(
+                "a proof with index {} was already added", index
+            )));
+        }
+        let leaf_proof = self.tree.prove_leaf(index, inner_proof)?;
+        self.indices.insert(index);
+        self.subtrees[0].insert(index, leaf_proof);
+
+        // merge the siblings as long as all N of them exist
+        let mut index = index;
+        for h in 0..self.height {
+            let parent = index / N;
+            let children: Option<Vec<_>> = (0..N)
+                .map(|j| self.subtrees[h].get(&(parent * N + j)).cloned())
+                .collect();
+            let Some(children) = children else {
+                break;
+            };
+            let children: Vec<_> = children.into_iter().map(Some).collect();
+            let node_proof = self.tree.prove_node(&children, h, parent)?;
+            for j in 0..N {
+                self.subtrees[h].remove(&(parent * N + j));
+            }
+            self.subtrees[h + 1].insert(parent, node_proof);
+            index = parent;
+        }
+
+        Ok(())
+    }
+
+    /// returns the root proof of the tree with all the proofs added so far,
+    /// the missing positions are filled with dummy subtrees.
+    /// this doesn't change the state, so more proofs can be added afterwards.
+    pub fn finalize(&self) -> Result<ProofWithPublicInputs<F, C, D>> {
+        if self.indices.is_empty() {
+            return Err(CircuitError::RecursionTreeError(
+                "cannot aggregate a tree with only dummy proofs".to_string()
+            ));
+        }
+
+        let mut subtrees = self.subtrees.clone();
+        for h in 0..self.height {
+            let parents: BTreeSet<usize> = subtrees[h].keys().map(|i| i / N).collect();
+            for parent in parents {
+                let children: Vec<_> = (0..N)
+                    .map(|j| subtrees[h].remove(&(parent * N + j)))
+                    .collect();
+                let node_proof = self.tree.prove_node(&children, h, parent)?;
+                subtrees[h + 1].insert(parent, node_proof);
+            }
+        }
+
+        subtrees[self.height].remove(&0).ok_or(CircuitError::RecursionTreeError(
+            "missing root proof".to_string()
+        ))
+    }
+
+    /// finalizes the tree and compresses the root proof
+    pub fn finalize_and_compress(&self) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let root = self.finalize()?;
+        self.tree.compress(root)
+    }
+}
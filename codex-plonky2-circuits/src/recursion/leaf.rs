@@ -8,8 +8,10 @@ use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
 use plonky2_field::extension::Extendable;
 use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
 use crate::{error::CircuitError,Result};
 use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::serialization::{bool_target, proof_with_pis_target};
 use crate::recursion::dummy_gen::DummyProofGen;
 use crate::recursion::utils::{bucket_count, compute_flag_buckets};
 
@@ -17,24 +19,24 @@ use crate::recursion::utils::{bucket_count, compute_flag_buckets};
 /// bucket: is a single Goldilocks field element where only `BUCKET_SIZE` bits are used for flags.
 /// flags: is a boolean which indicates whether the inner proof is real or dummy.
 /// flag_buckets: is a vector of M buckets, where each bucket contains `BUCKET_SIZE` flags.
-/// Typically, M = ceil(T/BUCKET_SIZE) where T is the total number of inner proofs in the recursion tree.
+/// Typically, M = ceil(t/BUCKET_SIZE) where t is the maximum number of inner proofs in the recursion tree.
 pub const BUCKET_SIZE: usize = 32;
 
 /// recursion leaf circuit - verifies 1 inner proof
 /// the inner proof can be real or dummy
-/// T: total number of inner (sampling) proofs
 /// inner_verifier_data: is the verifier data for the inner (sampling) circuit
+/// t: maximum number of inner (sampling) proofs in the tree, this sets the number of flag buckets.
 #[derive(Clone, Debug)]
 pub struct LeafCircuit<
     F: RichField + Extendable<D> + Poseidon2,
     const D: usize,
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
-    const T: usize,
 > where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
     inner_verifier_data: VerifierCircuitData<F, C, D>,
+    t: usize,
     phantom_data: PhantomData<H>
 }
 
@@ -42,12 +44,14 @@ pub struct LeafCircuit<
 /// inner_proof: inner (sampling) proofs
 /// index: index of the leaf
 /// flags: boolean target for each flag/signal for switching between real and dummy inner proof
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LeafTargets <
     const D: usize,
 >{
+    #[serde(with = "proof_with_pis_target")]
     pub inner_proof: ProofWithPublicInputsTarget<D>,
     pub index: Target, // public input
+    #[serde(with = "bool_target")]
     pub flag: BoolTarget,
 }
 
@@ -67,19 +71,30 @@ impl<
     const D: usize,
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
-    const T: usize,
-> LeafCircuit<F,D,C,H,T> where
+> LeafCircuit<F,D,C,H> where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
     pub fn new(
         inner_verifier_data: VerifierCircuitData<F, C, D>,
+        t: usize,
     ) -> Self {
         Self {
             inner_verifier_data,
+            t,
             phantom_data: PhantomData::default(),
         }
     }
 
+    /// returns the maximum number of inner proofs `t`
+    pub fn max_proofs(&self) -> usize {
+        self.t
+    }
+
+    /// returns the verifier data of the inner (sampling) circuit
+    pub fn inner_verifier_data(&self) -> &VerifierCircuitData<F, C, D> {
+        &self.inner_verifier_data
+    }
+
 }
 
 impl<
@@ -87,8 +102,7 @@ impl<
     const D: usize,
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
-    const T: usize,
-> Plonky2Circuit<F, C, D> for LeafCircuit<F,D,C,H,T> where
+> Plonky2Circuit<F, C, D> for LeafCircuit<F,D,C,H> where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
     type Targets = LeafTargets<D>;
@@ -107,14 +121,14 @@ impl<
     /// The public inputs are:
     /// - the hash of the public inputs of the inner proof (4 Goldilocks).
     /// - the zero hash (4 Goldilocks).
-    /// - the flag buckets = M Goldilocks where M = ceil(T/BUCKET_SIZE).
+    /// - the flag buckets = M Goldilocks where M = ceil(t/BUCKET_SIZE).
     /// The private inputs are:
     /// - the inner proof with public inputs
     /// - the flag. We don't need this to be public since the flag_buckets (containing the flag) are public.
     fn add_targets(&self, builder: &mut CircuitBuilder<F, D>, register_pi: bool) -> Result<LeafTargets<D>> {
 
         let inner_common = self.inner_verifier_data.common.clone();
-        let n_bucket: usize = bucket_count(T);
+        let n_bucket: usize = bucket_count(self.t);
 
         // the proof virtual target
         let vir_proof = builder.add_virtual_proof_with_pis(&inner_common);
@@ -140,7 +154,7 @@ impl<
             &DummyProofGen::<F,D,C>::gen_dummy_verifier_data(&inner_common)
         );
 
-        // index: 0 <= index < T where T = total number of proofs
+        // index: 0 <= index < t where t = maximum number of proofs
         let index = builder.add_virtual_public_input();
         let flag = builder.add_virtual_bool_target_safe();
 
@@ -177,7 +191,9 @@ impl<
         targets: &Self::Targets,
         input: &Self::Input,
     ) -> Result<()> {
-        assert!(input.index <= T, "given index is not valid");
+        if input.index >= self.t {
+            return Err(CircuitError::IndexOutOfRange(input.index, self.t));
+        }
         // assign the proofs
         pw.set_proof_with_pis_target(&targets.inner_proof, &input.inner_proof)
             .map_err(|e| {
@@ -1,7 +1,16 @@
 pub mod dummy_gen;
 pub mod utils;
 pub mod leaf;
+pub mod batch_leaf;
+pub mod hetero_leaf;
 pub mod node;
 pub mod tree;
+pub mod incremental;
+pub mod checkpoint;
+pub mod verifier;
 pub mod compress;
+pub mod compress_chain;
 pub mod pi_verifier;
+pub mod pi_commitment;
+pub mod arity;
+pub mod cross_tree;
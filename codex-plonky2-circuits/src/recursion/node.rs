@@ -1,5 +1,5 @@
 use std::marker::PhantomData;
-use plonky2::hash::hash_types::RichField;
+use plonky2::hash::hash_types::{HashOut, RichField};
 use plonky2::iop::target::{BoolTarget, Target};
 use plonky2::iop::witness::{PartialWitness, WitnessWrite};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
@@ -8,14 +8,18 @@ use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
 use plonky2_field::extension::Extendable;
 use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
 use crate::{error::CircuitError,Result};
 use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::serialization::{bool_target, bool_target_vec, proof_with_pis_target_vec, verifier_circuit_target};
 use crate::recursion::dummy_gen::DummyProofGen;
 use crate::recursion::utils::bucket_count;
 
 /// recursion node circuit
 /// N: number of leaf proofs
-/// T: total number of sampling proofs
+/// t: maximum number of sampling proofs in the tree, this sets the number of flag buckets.
+/// leaf_constant: the constant public input [4..8] of the leaf, if set it is forwarded to the root
+///   (see `with_leaf_constant`).
 #[derive(Clone, Debug)]
 pub struct NodeCircuit<
     F: RichField + Extendable<D> + Poseidon2,
@@ -23,11 +27,12 @@ pub struct NodeCircuit<
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
     const N: usize,
-    const T: usize,
 > where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
     leaf_verifier_data: VerifierCircuitData<F, C, D>,
+    t: usize,
+    leaf_constant: Option<HashOut<F>>,
     phantom_data: PhantomData<H>
 }
 
@@ -37,14 +42,18 @@ pub struct NodeCircuit<
 /// condition: for switching between leaf and node verifier data
 /// index: index of the node
 /// flags: boolean target for each flag/signal for switching between real and dummy leaf proof
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NodeTargets<
     const D: usize,
 >{
+    #[serde(with = "proof_with_pis_target_vec")]
     pub inner_proofs: Vec<ProofWithPublicInputsTarget<D>>,
+    #[serde(with = "verifier_circuit_target")]
     pub inner_verifier_data: VerifierCircuitTarget,
+    #[serde(with = "bool_target")]
     pub condition: BoolTarget,
     pub index: Target,
+    #[serde(with = "bool_target_vec")]
     pub flags: Vec<BoolTarget>,
 }
 
@@ -67,21 +76,42 @@ impl<
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
     const N: usize,
-    const T: usize,
-> NodeCircuit<F,D,C,H, N,T> where
+> NodeCircuit<F,D,C,H, N> where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
 
     pub fn new(
         leaf_verifier_data: VerifierCircuitData<F, C, D>,
+        t: usize,
     ) -> Self {
         assert!(N.is_power_of_two(), "N is NOT a power of two");
         Self{
             leaf_verifier_data,
+            t,
+            leaf_constant: None,
             phantom_data:PhantomData::default(),
         }
     }
 
+    /// forwards the constant public input [4..8] of the leaf circuit (e.g. the allow-list root of
+    /// `HeterogeneousLeafCircuit`) to the root: the node checks that the real leaf proofs carry `leaf_constant`
+    /// and hashes it with the node verifier data, so the verifier data hash at public input [4..8] becomes
+    /// `get_hash_of_verifier_data_with_leaf_constant` instead of `get_hash_of_verifier_data`.
+    pub fn with_leaf_constant(mut self, leaf_constant: HashOut<F>) -> Self {
+        self.leaf_constant = Some(leaf_constant);
+        self
+    }
+
+    /// returns the maximum number of inner proofs `t`
+    pub fn max_proofs(&self) -> usize {
+        self.t
+    }
+
+    /// returns the forwarded leaf constant, `None` if it is not forwarded
+    pub fn leaf_constant(&self) -> Option<HashOut<F>> {
+        self.leaf_constant
+    }
+
 }
 
 impl<
@@ -90,8 +120,7 @@ impl<
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
     const N: usize,
-    const T: usize,
-> Plonky2Circuit<F, C, D> for NodeCircuit<F, D, C, H, N, T> where
+> Plonky2Circuit<F, C, D> for NodeCircuit<F, D, C, H, N> where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
     type Targets = NodeTargets<D>;
@@ -116,22 +145,27 @@ impl<
         let inner_common = self.leaf_verifier_data.common.clone();
         let zero_target = builder.zero();
 
-        // assert public input is of size 8 (2 hash digests) + 1 (index) + B (flag buckets)
-        let n_bucket: usize = bucket_count(T);
-        assert_eq!(inner_common.num_public_inputs, 9+n_bucket);
+        // check public input is of size 8 (2 hash digests) + 1 (index) + B (flag buckets)
+        let n_bucket: usize = bucket_count(self.t);
+        if inner_common.num_public_inputs != 9 + n_bucket {
+            return Err(CircuitError::PublicInputLengthError(9 + n_bucket, inner_common.num_public_inputs));
+        }
 
         // the proof virtual targets - N proofs
         let mut vir_proofs = vec![];
         let mut pub_input = vec![];
         let mut inner_flag_buckets = vec![];
         let mut inner_indexes = vec![];
+        let mut inner_constants = vec![];
         for _i in 0..N {
             let vir_proof = builder.add_virtual_proof_with_pis(&inner_common);
             let inner_pub_input = vir_proof.public_inputs.clone();
             vir_proofs.push(vir_proof);
             // public input [0...4] contains hash of inner proof public inputs
             pub_input.extend_from_slice(&inner_pub_input[0..4]);
-            // public input [4...8] are skipped since they contain the inner verifier data
+            // public input [4...8] are skipped since they contain the inner verifier data,
+            // or the leaf constant if the inner proof is a leaf
+            inner_constants.push(inner_pub_input[4..8].to_vec());
             // public input [8] contains the index
             inner_indexes.push(inner_pub_input[8]);
             // public input [9..(9+n_bucket)] contains the flag buckets
@@ -170,6 +204,11 @@ impl<
         for i in 0..builder.config.fri_config.num_cap_elements() {
             vd_pub_input.extend_from_slice(&node_verifier_data.constants_sigmas_cap.0[i].elements);
         }
+        // the forwarded leaf constant is hashed with the verifier data
+        let const_leaf_constant = self.leaf_constant.map(|c| builder.constant_hash(c));
+        if let Some(leaf_constant) = const_leaf_constant {
+            vd_pub_input.extend_from_slice(&leaf_constant.elements);
+        }
         let vd_hash = builder.hash_n_to_hash_no_pad::<H>(vd_pub_input);
         if register_pi {
             builder.register_public_inputs(&vd_hash.elements);
@@ -182,7 +221,7 @@ impl<
 
         // flag buckets targets
         let mut flag_buckets: Vec<Target> = (0..n_bucket).map(|_i| zero_target.clone()).collect();
-        // index: 0 <= index < T where T = total number of proofs
+        // index: 0 <= index < t where t = maximum number of proofs
         let index = builder.add_virtual_public_input();
         // N flags, one for each inner proof
         let flags: Vec<BoolTarget> = (0..N).map(|_i| builder.add_virtual_bool_target_safe()).collect();
@@ -212,16 +251,28 @@ impl<
             }
         }
 
+        // check that the real leaf proofs carry the forwarded leaf constant:
+        // (inner_constant - leaf_constant) * flag * (1 - condition) = 0
+        if let Some(leaf_constant) = const_leaf_constant {
+            let not_condition = builder.not(condition);
+            for i in 0..N {
+                let is_real_leaf = builder.and(flags[i], not_condition);
+                for (&inner, &constant) in inner_constants[i].iter().zip(leaf_constant.elements.iter()) {
+                    let diff = builder.sub(inner, constant);
+                    let product = builder.mul(diff, is_real_leaf.target);
+                    builder.connect(product, zero_target);
+                }
+            }
+        }
+
         // check inner proof indices are correct
         // we expect the inner proof indices to be in the range [`index` * N, `index` * N + N - 1]
         // e.g. if index = 0, then we expect inner proof indices to be in the range [0, N - 1]
         let m_const = builder.constant(F::from_canonical_u64(N as u64));
-        let mut expected_inner_index = builder.mul(index, m_const);
+        let first_inner_index = builder.mul(index, m_const);
         for i in 0..N {
-            if i > 0 {
-                let i_const = builder.constant(F::from_canonical_u64(i as u64));
-                expected_inner_index = builder.add(expected_inner_index, i_const);
-            }
+            let i_const = builder.constant(F::from_canonical_u64(i as u64));
+            let expected_inner_index = builder.add(first_inner_index, i_const);
             builder.connect(expected_inner_index, inner_indexes[i]);
         }
 
@@ -252,10 +303,16 @@ impl<
     }
 
     fn assign_targets(&self, pw: &mut PartialWitness<F>, targets: &Self::Targets, input: &Self::Input) -> Result<()> {
-        // assert size of vec
-        assert_eq!(input.inner_proofs.len(), N);
-        assert_eq!(input.flags.len(), N);
-        assert!(input.index <= T, "given index is not valid");
+        // check size of vec
+        if input.inner_proofs.len() != N {
+            return Err(CircuitError::InnerProofsLengthMismatch(N, input.inner_proofs.len()));
+        }
+        if input.flags.len() != N {
+            return Err(CircuitError::FlagsLengthMismatch(N, input.flags.len()));
+        }
+        if input.index >= self.t {
+            return Err(CircuitError::IndexOutOfRange(input.index, self.t));
+        }
 
         // assign the proofs
         for i in 0..N {
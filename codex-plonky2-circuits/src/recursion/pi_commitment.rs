@@ -0,0 +1,239 @@
+use std::marker::PhantomData;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, RichField};
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
+use crate::{error::CircuitError, Result};
+use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::keyed_compress::key_compress_circuit;
+use crate::circuits::merkle_circuit::{KEY_BOTTOM_LAYER, KEY_NONE};
+use crate::circuits::serialization::{proof_with_pis_target, SerializableHashOutTarget};
+use crate::recursion::leaf::BUCKET_SIZE;
+use crate::recursion::utils::bucket_count;
+
+/// A circuit that commits to the inner public inputs of a tree proof with a Merkle tree.
+/// - `N`: Number of leaf proofs aggregated at the node level.
+/// - `t`: Total Number of inner-proofs, the maximum number of proofs of the tree.
+///
+/// the verified proof is the tree root proof (or the compressed proof) with public inputs
+/// [pi_hash, vd_hash, index, flag_buckets], the dummy leaves are read from the flag buckets.
+/// unlike `PublicInputVerificationCircuit`, only the `t` inner public input hashes are given (privately),
+/// and the Codex safe Merkle tree over them (zero for the dummy leaves) replaces the pi hash in the public input.
+/// the public input of a single inner proof can then be checked with a Merkle opening of its index,
+/// without the other `t - 1` rows.
+#[derive(Clone, Debug)]
+pub struct PublicInputCommitmentCircuit<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+{
+    pub node_verifier_data: VerifierCircuitData<F, C, D>,
+    t: usize,
+    phantom: PhantomData<H>,
+}
+
+/// Holds the virtual targets for the circuit.
+/// - `inner_proof`: the proof to be verified, its pi hash is the hash tree of `inner_pi_hashes`.
+/// - `inner_pi_hashes`: the hash of the public input of each of the `t` inner proofs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PublicInputCommitmentTargets<const D: usize> {
+    #[serde(with = "proof_with_pis_target")]
+    pub inner_proof: ProofWithPublicInputsTarget<D>,
+    pub inner_pi_hashes: Vec<SerializableHashOutTarget>,
+}
+
+/// input to the circuit for the public input commitment
+/// - `inner_proof`: The tree root proof with public inputs: [pi_hash, vd_hash, ...].
+/// - `inner_pi_hashes`: the hash of the public input of each of the `t` inner proofs, zero for the dummy leaves.
+#[derive(Clone, Debug)]
+pub struct PublicInputCommitmentInput<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+>{
+    pub inner_proof: ProofWithPublicInputs<F, C, D>,
+    pub inner_pi_hashes: Vec<HashOut<F>>,
+}
+
+impl<F, const D: usize, C, H, const N: usize>
+PublicInputCommitmentCircuit<F, D, C, H, N>
+    where
+        F: RichField + Extendable<D> + Poseidon2,
+        C: GenericConfig<D, F = F>,
+        H: AlgebraicHasher<F>,
+        <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+{
+    /// Create a new instance of the circuit.
+    pub fn new(
+        node_verifier_data: VerifierCircuitData<F, C, D>,
+        t: usize,
+    ) -> Self {
+        Self {
+            node_verifier_data,
+            t,
+            phantom: PhantomData,
+        }
+    }
+
+    /// returns the maximum number of inner proofs `t`
+    pub fn max_proofs(&self) -> usize {
+        self.t
+    }
+
+    /// returns `hash` if `flag` is true, the zero hash otherwise
+    fn select_or_zero(builder: &mut CircuitBuilder<F, D>, flag: BoolTarget, hash: HashOutTarget) -> HashOutTarget {
+        HashOutTarget {
+            elements: hash.elements.map(|e| builder.mul(flag.target, e)),
+        }
+    }
+}
+
+impl<F, const D: usize, C, H, const N: usize>
+Plonky2Circuit<F, C, D> for PublicInputCommitmentCircuit<F, D, C, H, N>
+    where
+        F: RichField + Extendable<D> + Poseidon2,
+        C: GenericConfig<D, F = F>,
+        H: AlgebraicHasher<F>,
+        <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+{
+    type Targets = PublicInputCommitmentTargets<D>;
+    type Input = PublicInputCommitmentInput<F, D, C>;
+
+    /// Builds the circuit by:
+    /// 1. Verifies a proof target with public inputs (the final [pi_hash, vd_hash, index, flag_buckets]).
+    /// 2. verifies correct tree hashing of the `t` inner pi hashes to the pi hash of the proof,
+    ///    the same way as `get_pi_hash_levels`: the pi hash of a dummy leaf must be zero.
+    /// 3. builds the Codex safe Merkle tree over the `t` inner pi hashes, `t` is a power of two,
+    ///    so the bottom layer is compressed with `KEY_BOTTOM_LAYER` and the layers above with `KEY_NONE`.
+    ///
+    /// The public inputs are:
+    /// - the Merkle root of the inner pi hashes (4 Goldilocks).
+    /// - the verifier data hash (4 Goldilocks), index and flag buckets (and proof count if any) of the verified proof.
+    fn add_targets(&self, builder: &mut CircuitBuilder<F, D>, register_pi: bool) -> Result<PublicInputCommitmentTargets<D>> {
+        // we expect at least 8 (2 hash digests) + 1 (index) + B (flag buckets) public inputs from the tree root proof,
+        // the compressed proof can also have the proof count
+        let n_bucket = bucket_count(self.t);
+        let inner_common = &self.node_verifier_data.common;
+        if inner_common.num_public_inputs < 9 + n_bucket {
+            return Err(CircuitError::PublicInputLengthError(9 + n_bucket, inner_common.num_public_inputs));
+        }
+        if self.t < 2 || !self.t.is_power_of_two() {
+            return Err(CircuitError::InvalidArgument(format!(
+                "the maximum number of proofs must be a power of two and at least 2, got {}", self.t
+            )));
+        }
+
+        // verify the tree root proof with the constant verifier data
+        let inner_proof = builder.add_virtual_proof_with_pis(inner_common);
+        let const_node_vd = builder.constant_verifier_data(&self.node_verifier_data.verifier_only);
+        builder.verify_proof::<C>(&inner_proof, &const_node_vd, inner_common);
+
+        // the t inner pi hashes
+        let inner_pi_hashes: Vec<HashOutTarget> = (0..self.t)
+            .map(|_| builder.add_virtual_hash())
+            .collect();
+
+        // the flag of each leaf from the flag buckets: bit `i % BUCKET_SIZE` of bucket `i / BUCKET_SIZE`
+        let flag_buckets = &inner_proof.public_inputs[9..(9 + n_bucket)];
+        let mut flags: Vec<BoolTarget> = Vec::with_capacity(self.t);
+        for bucket in flag_buckets {
+            flags.extend(builder.split_le(*bucket, BUCKET_SIZE));
+        }
+        flags.truncate(self.t);
+
+        // the pi hash of a dummy leaf must be zero
+        let zero_target = builder.zero();
+        for i in 0..self.t {
+            let not_flag = builder.not(flags[i]);
+            for &e in &inner_pi_hashes[i].elements {
+                let product = builder.mul(e, not_flag.target);
+                builder.connect(product, zero_target);
+            }
+        }
+
+        // node level: chunks of N -> hash, zero for dummy chunks
+        let mut pub_in_hashes_t = inner_pi_hashes.clone();
+        while pub_in_hashes_t.len() > 1 {
+            let mut next_pub_in_hashes_t = vec![];
+            let mut next_flags = vec![];
+            for (chunk, chunk_flags) in pub_in_hashes_t.chunks(N).zip(flags.chunks(N)) {
+                // the chunk is real if any of its children is real
+                let mut pi_flat = vec![];
+                let mut is_real = builder._false();
+                for (h, &flag) in chunk.iter().zip(chunk_flags) {
+                    pi_flat.extend_from_slice(&h.elements);
+                    is_real = builder.or(is_real, flag);
+                }
+                let pi_hash = builder.hash_n_to_hash_no_pad::<H>(pi_flat);
+                next_pub_in_hashes_t.push(Self::select_or_zero(builder, is_real, pi_hash));
+                next_flags.push(is_real);
+            }
+            pub_in_hashes_t = next_pub_in_hashes_t;
+            flags = next_flags;
+        }
+        for i in 0..4 {
+            builder.connect(inner_proof.public_inputs[i], pub_in_hashes_t[0].elements[i]);
+        }
+
+        // the Merkle tree over the inner pi hashes, layer by layer
+        let key_bottom = builder.constant(F::from_canonical_u64(KEY_BOTTOM_LAYER));
+        let key_none = builder.constant(F::from_canonical_u64(KEY_NONE));
+        let mut layer = inner_pi_hashes.clone();
+        let mut key = key_bottom;
+        while layer.len() > 1 {
+            layer = layer
+                .chunks(2)
+                .map(|pair| key_compress_circuit::<F, D, H>(builder, pair[0], pair[1], key))
+                .collect();
+            key = key_none;
+        }
+
+        if register_pi {
+            builder.register_public_inputs(&layer[0].elements);
+            builder.register_public_inputs(&inner_proof.public_inputs[4..]);
+        }
+
+        Ok(PublicInputCommitmentTargets {
+            inner_proof,
+            inner_pi_hashes: inner_pi_hashes.into_iter().map(SerializableHashOutTarget::from).collect(),
+        })
+    }
+
+    fn assign_targets(
+        &self,
+        pw: &mut PartialWitness<F>,
+        targets: &Self::Targets,
+        input: &Self::Input,
+    ) -> Result<()> {
+        if input.inner_pi_hashes.len() != self.t {
+            return Err(CircuitError::InvalidArgument(format!(
+                "Expected t={} inner pi hashes, got {}", self.t, input.inner_pi_hashes.len()
+            )));
+        }
+
+        // Assign the tree root proof
+        pw.set_proof_with_pis_target(&targets.inner_proof, &input.inner_proof)
+            .map_err(|e| {
+                CircuitError::ProofTargetAssignmentError("final-proof".to_string(), e.to_string())
+            })?;
+
+        for (i, hash) in input.inner_pi_hashes.iter().enumerate() {
+            pw.set_hash_target(targets.inner_pi_hashes[i].0, *hash)
+                .map_err(|e| {
+                    CircuitError::HashTargetAssignmentError(format!("inner pi hash {}", i), e.to_string())
+                })?;
+        }
+
+        Ok(())
+    }
+}
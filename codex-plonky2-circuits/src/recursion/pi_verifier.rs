@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
-use plonky2::hash::hash_types::RichField;
-use plonky2::iop::target::Target;
+use plonky2::hash::hash_types::{HashOutTarget, RichField};
+use plonky2::iop::target::{BoolTarget, Target};
 use plonky2::iop::witness::{PartialWitness, WitnessWrite};
 use plonky2::plonk::circuit_builder::{CircuitBuilder};
 use plonky2::plonk::circuit_data::VerifierCircuitData;
@@ -8,13 +8,20 @@ use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
 use plonky2_field::extension::Extendable;
 use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::{Deserialize, Serialize};
 use crate::{error::CircuitError, Result};
 use crate::circuit_trait::Plonky2Circuit;
+use crate::circuits::serialization::proof_with_pis_target;
+use crate::recursion::leaf::BUCKET_SIZE;
+use crate::recursion::utils::bucket_count;
 
 /// A circuit that verifies the aggregated public inputs from inner circuits.
 /// - `N`: Number of leaf proofs aggregated at the node level.
-/// - `T`: Total Number of inner-proofs.
-/// - `K`: Number of public input field elements per inner-proof (sampling proof).
+/// - `t`: Total Number of inner-proofs, the maximum number of proofs of the tree.
+/// - `k`: Number of public input field elements per inner-proof (sampling proof).
+///
+/// the verified proof is the tree root proof (or the compressed proof) with public inputs
+/// [pi_hash, vd_hash, index, flag_buckets], the dummy leaves are read from the flag buckets.
 #[derive(Clone, Debug)]
 pub struct PublicInputVerificationCircuit<
     F: RichField + Extendable<D> + Poseidon2,
@@ -22,27 +29,28 @@ pub struct PublicInputVerificationCircuit<
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
     const N: usize,
-    const T: usize,
-    const K: usize,
 > where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
 {
     pub node_verifier_data: VerifierCircuitData<F, C, D>,
+    t: usize,
+    k: usize,
     phantom: PhantomData<H>,
 }
 
 /// Holds the virtual targets for the circuit.
 /// - `inner_proof`: the proof to be verified and contains the public input to be verified.
-/// - `inner_pub_inputs`: A nested vector of targets with dimensions T×K.
-#[derive(Clone, Debug)]
+/// - `inner_pub_inputs`: A nested vector of targets with dimensions t×k.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PublicInputVerificationTargets<const D: usize> {
+    #[serde(with = "proof_with_pis_target")]
     pub inner_proof: ProofWithPublicInputsTarget<D>,
     pub inner_pub_inputs: Vec<Vec<Target>>,
 }
 
 /// input to the circuit for public input verification
 /// - `inner_proof`: The tree root proof with public inputs: [pi_hash, vd_hash, ...].
-/// - `inner_pub_inputs_vals`: T×K public input values from inner proofs.
+/// - `inner_pub_inputs_vals`: t×k public input values from inner proofs, the rows of dummy leaves are zero.
 #[derive(Clone, Debug)]
 pub struct PublicInputVerificationInput<
     F: RichField + Extendable<D> + Poseidon2,
@@ -53,8 +61,8 @@ pub struct PublicInputVerificationInput<
     pub inner_pub_inputs_vals: Vec<Vec<F>>,
 }
 
-impl<F, const D: usize, C, H, const N: usize, const T: usize, const K: usize>
-PublicInputVerificationCircuit<F, D, C, H, N, T, K>
+impl<F, const D: usize, C, H, const N: usize>
+PublicInputVerificationCircuit<F, D, C, H, N>
     where
         F: RichField + Extendable<D> + Poseidon2,
         C: GenericConfig<D, F = F>,
@@ -64,19 +72,32 @@ PublicInputVerificationCircuit<F, D, C, H, N, T, K>
     /// Create a new instance of the circuit.
     pub fn new(
         node_verifier_data: VerifierCircuitData<F, C, D>,
+        t: usize,
+        k: usize,
     ) -> Self {
-        // we expect at least 4 public inputs from the tree root proof
-        // 1 hash digest (4 Goldilocks) for the final aggregated public-input hash
-        assert!(node_verifier_data.common.num_public_inputs >= 4);
-
         Self {
             node_verifier_data,
+            t,
+            k,
             phantom: PhantomData,
         }
     }
+
+    /// returns the maximum number of inner proofs `t`
+    pub fn max_proofs(&self) -> usize {
+        self.t
+    }
+
+    /// returns `hash` if `flag` is true, the zero hash otherwise
+    fn select_or_zero(builder: &mut CircuitBuilder<F, D>, flag: BoolTarget, hash: HashOutTarget) -> HashOutTarget {
+        HashOutTarget {
+            elements: hash.elements.map(|e| builder.mul(flag.target, e)),
+        }
+    }
 }
-impl<F, const D: usize, C, H, const N: usize, const T: usize, const K: usize>
-Plonky2Circuit<F, C, D> for PublicInputVerificationCircuit<F, D, C, H, N, T, K>
+
+impl<F, const D: usize, C, H, const N: usize>
+Plonky2Circuit<F, C, D> for PublicInputVerificationCircuit<F, D, C, H, N>
     where
         F: RichField + Extendable<D> + Poseidon2,
         C: GenericConfig<D, F = F>,
@@ -87,25 +108,38 @@ Plonky2Circuit<F, C, D> for PublicInputVerificationCircuit<F, D, C, H, N, T, K>
     type Input = PublicInputVerificationInput<F, D, C>;
 
     /// Builds the circuit by:
-    /// 1. Verifies a proof target with public inputs (the final [pi_hash, vd_hash, ...]).
-    /// 2. verifies correct tree hashing of all T×K targets to represent all inner public inputs.
+    /// 1. Verifies a proof target with public inputs (the final [pi_hash, vd_hash, index, flag_buckets]).
+    /// 2. verifies correct tree hashing of all t×k targets to represent all inner public inputs,
+    ///    the same way as `get_pi_hash_levels`: dummy leaves and subtrees with only dummy leaves hash to zero.
     /// 3. register the un-hashed inner public input as this circuit public input + the rest of the inner public input
+    ///
+    /// The public inputs are:
+    /// - the t×k inner public inputs, zero for the dummy leaves.
+    /// - the verifier data hash (4 Goldilocks), index and flag buckets (and proof count if any) of the verified proof.
     fn add_targets(&self, builder: &mut CircuitBuilder<F, D>, register_pi: bool) -> Result<PublicInputVerificationTargets<D>> {
-        // Add a virtual proof with 8 public inputs. This is the final root proof whose
+        // we expect at least 8 (2 hash digests) + 1 (index) + B (flag buckets) public inputs from the tree root proof,
+        // the compressed proof can also have the proof count
+        let n_bucket = bucket_count(self.t);
+        let inner_common = &self.node_verifier_data.common;
+        if inner_common.num_public_inputs < 9 + n_bucket {
+            return Err(CircuitError::PublicInputLengthError(9 + n_bucket, inner_common.num_public_inputs));
+        }
+
+        // Add a virtual proof with the public inputs of the root. This is the final root proof whose
         // public inputs we want to check in-circuit.
-        let inner_proof = builder.add_virtual_proof_with_pis(&self.node_verifier_data.common);
+        let inner_proof = builder.add_virtual_proof_with_pis(inner_common);
 
         // Create a constant VerifierCircuitTarget for the node's verifier data.
         let const_node_vd = builder.constant_verifier_data(&self.node_verifier_data.verifier_only);
 
         // verify the proof
-        builder.verify_proof::<C>(&inner_proof, &const_node_vd, &self.node_verifier_data.common);
+        builder.verify_proof::<C>(&inner_proof, &const_node_vd, inner_common);
 
-        // create T×K targets for all inner public inputs from the base level.
-        let mut inner_pub_inputs = Vec::with_capacity(T);
-        for _ in 0..T {
-            let mut row = Vec::with_capacity(K);
-            for _ in 0..K {
+        // create t×k targets for all inner public inputs from the base level.
+        let mut inner_pub_inputs = Vec::with_capacity(self.t);
+        for _ in 0..self.t {
+            let mut row = Vec::with_capacity(self.k);
+            for _ in 0..self.k {
                 if register_pi {
                     row.push(builder.add_virtual_public_input()); // public input
                 } else{
@@ -120,8 +154,8 @@ Plonky2Circuit<F, C, D> for PublicInputVerificationCircuit<F, D, C, H, N, T, K>
         //
         //   let final_pi = proof.public_inputs[0..4];
         //   ...
-        //   leaf-level pub inputs tree hashing
-        //   node-level pub inputs tree hashing: chunks of N -> hash
+        //   leaf-level pub inputs tree hashing, zero for dummy leaves
+        //   node-level pub inputs tree hashing: chunks of N -> hash, zero for dummy chunks
         //   ...
         //   check final result matches final_pi
         // ------------------------------------------------------------------
@@ -129,40 +163,63 @@ Plonky2Circuit<F, C, D> for PublicInputVerificationCircuit<F, D, C, H, N, T, K>
         // Extract the final 4 field elements for the public-input hash & the rest for the verifier-data hash, index, and flags.
         let final_pi_hash_target = &inner_proof.public_inputs[0..4];
         let rest_of_inner_pi = &inner_proof.public_inputs[4..];
-        builder.register_public_inputs(&rest_of_inner_pi); // public input
+        if register_pi {
+            builder.register_public_inputs(rest_of_inner_pi); // public input
+        }
 
-        let mut pub_in_hashes_t = Vec::new();
+        // the flag of each leaf from the flag buckets: bit `i % BUCKET_SIZE` of bucket `i / BUCKET_SIZE`
+        let flag_buckets = &inner_proof.public_inputs[9..(9 + n_bucket)];
+        let mut flags: Vec<BoolTarget> = Vec::with_capacity(self.t);
+        for bucket in flag_buckets {
+            flags.extend(builder.split_le(*bucket, BUCKET_SIZE));
+        }
+        flags.truncate(self.t);
+
+        let zero_target = builder.zero();
+        let mut pub_in_hashes_t = Vec::with_capacity(self.t);
 
-        // Leaf level hashing - hash each row i = 0..T of inner_pub_inputs matrix
-        for i in 0..T {
-            // hash
+        // Leaf level hashing - hash each row i = 0..t of inner_pub_inputs matrix
+        for i in 0..self.t {
+            // the public input of a dummy leaf must be zero
+            let not_flag = builder.not(flags[i]);
+            for &pi in &inner_pub_inputs[i] {
+                let product = builder.mul(pi, not_flag.target);
+                builder.connect(product, zero_target);
+            }
+            // hash, zero hash for a dummy leaf
             let pi_hash_chunk = builder.hash_n_to_hash_no_pad::<H>(inner_pub_inputs[i].clone());
             // track these in hash digests
-            pub_in_hashes_t.push(pi_hash_chunk);
+            pub_in_hashes_t.push(Self::select_or_zero(builder, flags[i], pi_hash_chunk));
         }
 
         // Now at the node level:
-        let mut current_len = 0;
+        let mut current_len = self.t;
         while current_len > 1 {
 
             let next_len = (current_len + (N - 1)) / N;
 
             let mut next_pub_in_hashes_t = Vec::with_capacity(next_len);
+            let mut next_flags = Vec::with_capacity(next_len);
 
             for i in 0..next_len {
                 let start_idx = i * N;
                 let end_idx = (start_idx + N).min(current_len);
 
                 // flatten all pub_in_hashes in [start_idx..end_idx]
+                // the chunk is real if any of its children is real
                 let mut pi_flat = Vec::with_capacity((end_idx - start_idx) * 4);
+                let mut is_real = builder._false();
                 for j in start_idx..end_idx {
                     pi_flat.extend_from_slice(&pub_in_hashes_t[j].elements);
+                    is_real = builder.or(is_real, flags[j]);
                 }
                 let pi_hash = builder.hash_n_to_hash_no_pad::<H>(pi_flat);
-                next_pub_in_hashes_t.push(pi_hash);
+                next_pub_in_hashes_t.push(Self::select_or_zero(builder, is_real, pi_hash));
+                next_flags.push(is_real);
             }
 
             pub_in_hashes_t = next_pub_in_hashes_t;
+            flags = next_flags;
             current_len = next_len;
         }
 
@@ -193,24 +250,24 @@ Plonky2Circuit<F, C, D> for PublicInputVerificationCircuit<F, D, C, H, N, T, K>
                 CircuitError::ProofTargetAssignmentError("final-proof".to_string(), e.to_string())
             })?;
 
-        // Assign T×K inner public inputs
-        if input.inner_pub_inputs_vals.len() != T {
+        // Assign t×k inner public inputs
+        if input.inner_pub_inputs_vals.len() != self.t {
             return Err(CircuitError::InvalidArgument(format!(
-                "Expected T={} rows of inner_pub_inputs_vals, got {}",
-                T,
+                "Expected t={} rows of inner_pub_inputs_vals, got {}",
+                self.t,
                 input.inner_pub_inputs_vals.len()
             )));
         }
         for (i, row_vals) in input.inner_pub_inputs_vals.iter().enumerate() {
-            if row_vals.len() != K {
+            if row_vals.len() != self.k {
                 return Err(CircuitError::InvalidArgument(format!(
-                    "Expected K={} values in row {}, got {}",
-                    K,
+                    "Expected k={} values in row {}, got {}",
+                    self.k,
                     i,
                     row_vals.len()
                 )));
             }
-            for (j, &val) in row_vals.into_iter().enumerate() {
+            for (j, &val) in row_vals.iter().enumerate() {
                 pw.set_target(targets.inner_pub_inputs[i][j], val).map_err(|e| {
                     CircuitError::TargetAssignmentError(format!("inner public input index [{}][{}]", i,j), e.to_string())
                 })?;
@@ -1,84 +1,466 @@
 use std::marker::PhantomData;
-use plonky2::hash::hash_types::RichField;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use hashbrown::HashMap;
+use plonky2::hash::hash_types::{HashOut, RichField};
 use plonky2::iop::witness::PartialWitness;
-use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitData, VerifierOnlyCircuitData};
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitData};
 use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2::plonk::proof::ProofWithPublicInputs;
 use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
 use plonky2_field::extension::Extendable;
 use crate::{error::CircuitError, Result};
 use crate::bundle::Bundle;
+use crate::circuits::sample_cells::SampleCircuitInput;
 use crate::circuit_trait::Plonky2Circuit;
 use crate::recursion::{leaf::{LeafTargets, LeafCircuit}, node::{NodeTargets, NodeCircuit}};
-use crate::recursion::compress::{CompressionCircuit, CompressionInput, CompressionTargets};
+use crate::recursion::arity::proofs_per_level;
+use crate::recursion::batch_leaf::{BatchLeafCircuit, BatchLeafInput, BatchLeafTargets};
+use crate::recursion::hetero_leaf::{HeterogeneousLeafCircuit, HeterogeneousLeafInput, HeterogeneousLeafTargets, VerifierKeyAllowList};
+use crate::recursion::checkpoint::{CheckpointDir, NoCheckpoint, TreeCheckpoint};
+use crate::recursion::compress::{CompressionCircuit, CompressionInput, CompressionTargets, ProofCount};
+use crate::recursion::dummy_gen::DummyProofGen;
 use crate::recursion::leaf::LeafInput;
 use crate::recursion::node::NodeInput;
-use crate::recursion::utils::get_hash_of_verifier_data;
+use crate::recursion::pi_verifier::{PublicInputVerificationCircuit, PublicInputVerificationInput, PublicInputVerificationTargets};
+use crate::recursion::pi_commitment::{PublicInputCommitmentCircuit, PublicInputCommitmentInput, PublicInputCommitmentTargets};
+use crate::recursion::utils::{get_batch_pi_hash_levels, get_hash_of_verifier_data, get_hash_of_verifier_data_with_leaf_constant, get_pi_hash_levels, get_proven_indices, verify_batch_tree_public_input, verify_proven_indices};
+use crate::serialization::{export_full_circuit_data, import_full_circuit_data, import_targets, ensure_parent_directory_exists};
+use plonky2_maybe_rayon::{MaybeIntoParIter, MaybeParIter};
+#[cfg(feature = "parallel")]
+use plonky2_maybe_rayon::{rayon, ParallelIterator};
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+
+/// sub-directories of the exported tree circuits, see `TreeRecursion::export_circuits`
+pub const LEAF_CIRC_DIR: &str = "leaf";
+pub const NODE_CIRC_DIR: &str = "node";
+pub const COMPRESSION_CIRC_DIR: &str = "compression";
+pub const PI_VERIFIER_CIRC_DIR: &str = "pi_verifier";
+pub const PI_COMMITMENT_CIRC_DIR: &str = "pi_commitment";
+/// parameters the exported tree circuits were built with
+pub const TREE_PARAMS_JSON: &str = "tree_params.json";
+
+/// the key of the exported tree circuits: the circuits are only valid for
+/// the same inner (sampling) circuit, maximum number of proofs and arity.
+/// the proof count option of the exported compression circuit is not part of the key, it is restored on load.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct TreeCircuitParams {
+    inner_circuit_digest: Vec<u64>,
+    max_proofs: usize,
+    arity: usize,
+    #[serde(default)]
+    proof_count: Option<ProofCount>,
+}
+
+/// returns the arity `N` of the tree circuits exported under `base_path` with `TreeRecursion::export_circuits`,
+/// so they can be loaded with the arity they were built with.
+pub fn exported_arity<P: AsRef<Path>>(base_path: P) -> Result<usize> {
+    let params_path = base_path.as_ref().join(TREE_PARAMS_JSON);
+    let params_str = std::fs::read_to_string(&params_path)
+        .map_err(|e| CircuitError::SerializationError(format!("{:?}: {}", params_path, e)))?;
+    let params: TreeCircuitParams = serde_json::from_str(&params_str)
+        .map_err(|e| CircuitError::SerializationError(format!("{:?}: {}", params_path, e)))?;
+    Ok(params.arity)
+}
 
 /// tree recursion
 /// - `N`: Number of leaf proofs aggregated at the node level. set to 2 for 2-to-1 tree
+///   see `ArityEstimator` to choose `N` for a given `t`.
+/// - `t`: maximum number of inner proofs the tree aggregates, given at build time.
+///   must be a power of `N` and at least `N`.
+///
+/// the tree can aggregate any number of inner proofs from 1 to `t`,
+/// the missing leaves and node slots are filled with dummy proofs and false flags.
+/// with `build_with_batch_leaf` each leaf verifies `M` inner proofs (see `BatchLeafCircuit`),
+/// so the nodes aggregate `t / M` leaves.
+/// with `build_with_allow_list` each leaf verifies an inner proof of any circuit in a `VerifierKeyAllowList`
+/// (see `HeterogeneousLeafCircuit`), and the allow-list root is forwarded to the root proof.
+///
+/// the number of real inner proofs and the minimum count check are only in the compression circuit
+/// (see `build_compression_with_proof_count`), the root node proof doesn't have them,
+/// so a tree proof must be compressed for its proof count to be enforced.
 pub struct TreeRecursion<
     F: RichField + Extendable<D> + Poseidon2,
     const D: usize,
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
     const N: usize,
-    const T: usize,
 > where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
-    leaf: LeafCircuit<F, D, C, H, T>,
-    node: NodeCircuit<F, D, C, H, N, T>,
+    leaf: TreeLeaf<F, D, C, H>,
+    node: NodeCircuit<F, D, C, H, N>,
     compression: CompressionCircuit<F, D, C>,
     leaf_circ_data: CircuitData<F, C, D>,
     node_circ_data: CircuitData<F, C, D>,
     compression_circ_data: CircuitData<F, C, D>,
-    leaf_targets: LeafTargets<D>,
     node_targets: NodeTargets<D>,
     compression_targets: CompressionTargets<D>,
+    // dummy circuit matching the inner common data of the node, used to fill the missing node slots
+    dummy_node_circ_data: CircuitData<F, C, D>,
+    // optional stage after compression, see `build_public_input_verification`
+    pi_verifier: Option<PublicInputVerificationStage<F, D, C, H, N>>,
+    // optional stage after compression, see `build_public_input_commitment`
+    pi_commitment: Option<PublicInputCommitmentStage<F, D, C, H, N>>,
+    // thread pool for proving, built once in `set_num_threads`, only with the `parallel` feature
+    #[cfg(feature = "parallel")]
+    thread_pool: Option<Arc<rayon::ThreadPool>>,
+    // if true, the inner proofs that fail the pre-verification become dummy leaves, see `set_quarantine_invalid_proofs`
+    quarantine_invalid_proofs: bool,
     phantom_data: PhantomData<H>
 }
 
+/// the leaf circuit of the tree with its targets, there is a single one in each tree
+#[allow(clippy::large_enum_variant)]
+enum TreeLeaf<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    /// one inner proof per leaf, see `LeafCircuit`
+    Single(LeafCircuit<F, D, C, H>, LeafTargets<D>),
+    /// a batch of inner proofs per leaf, see `BatchLeafCircuit`
+    Batch(Box<dyn BatchLeafStage<F, D, C> + Send + Sync>),
+    /// one inner proof of any circuit in the allow-list per leaf, see `HeterogeneousLeafCircuit`
+    Heterogeneous(HeterogeneousLeafCircuit<F, D, C, H>, HeterogeneousLeafTargets<D>),
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+> TreeLeaf<F, D, C, H> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    /// returns the number of inner proofs in each leaf
+    fn batch_size(&self) -> usize {
+        match self {
+            TreeLeaf::Single(..) | TreeLeaf::Heterogeneous(..) => 1,
+            TreeLeaf::Batch(batch) => batch.batch_size(),
+        }
+    }
+
+    /// returns the verifier data of the inner (sampling) circuits, the allow-list for the heterogeneous leaf
+    fn inner_verifier_data(&self) -> Vec<&VerifierCircuitData<F, C, D>> {
+        match self {
+            TreeLeaf::Single(leaf, _) => vec![leaf.inner_verifier_data()],
+            TreeLeaf::Batch(batch) => vec![batch.inner_verifier_data()],
+            TreeLeaf::Heterogeneous(leaf, _) => {
+                let allow_list = leaf.allow_list();
+                (0..allow_list.num_circuits()).filter_map(|i| allow_list.verifier_data(i).ok()).collect()
+            }
+        }
+    }
+
+    /// returns the common circuit data shared by the inner (sampling) circuits
+    fn inner_common(&self) -> &CommonCircuitData<F, D> {
+        match self {
+            TreeLeaf::Single(leaf, _) => &leaf.inner_verifier_data().common,
+            TreeLeaf::Batch(batch) => &batch.inner_verifier_data().common,
+            TreeLeaf::Heterogeneous(leaf, _) => leaf.allow_list().common(),
+        }
+    }
+
+    /// returns the constant public input [4..8] of the leaf that the nodes forward to the root,
+    /// the allow-list root for the heterogeneous leaf (see `NodeCircuit::with_leaf_constant`)
+    fn leaf_constant(&self) -> Option<HashOut<F>> {
+        match self {
+            TreeLeaf::Single(..) | TreeLeaf::Batch(_) => None,
+            TreeLeaf::Heterogeneous(leaf, _) => Some(leaf.allow_list().root()),
+        }
+    }
+
+    /// assigns the witness of the leaf at `index` for its inner proofs, `None` marks a dummy inner proof.
+    /// `vd_indices` are the positions of the inner circuits of the proofs, see `PreVerificationReport::vd_indices`
+    fn assign(
+        &self,
+        pw: &mut PartialWitness<F>,
+        index: usize,
+        inner_proofs: &[Option<ProofWithPublicInputs<F, C, D>>],
+        vd_indices: &[usize],
+    ) -> Result<()> {
+        match self {
+            TreeLeaf::Single(leaf, targets) => {
+                let [Some(inner_proof)] = inner_proofs else {
+                    return Err(CircuitError::RecursionTreeError(format!(
+                        "leaf {} expects a single real inner proof", index
+                    )));
+                };
+                let leaf_input = LeafInput{
+                    inner_proof: inner_proof.clone(),
+                    flag: true,
+                    index,
+                };
+                leaf.assign_targets(pw, targets, &leaf_input)
+            }
+            TreeLeaf::Batch(batch) => batch.assign(pw, index, inner_proofs),
+            TreeLeaf::Heterogeneous(leaf, targets) => {
+                let ([Some(inner_proof)], &[vd_index]) = (inner_proofs, vd_indices) else {
+                    return Err(CircuitError::RecursionTreeError(format!(
+                        "leaf {} expects a single real inner proof with its allow-list position", index
+                    )));
+                };
+                let leaf_input = HeterogeneousLeafInput{
+                    inner_proof: inner_proof.clone(),
+                    vd_index,
+                    flag: true,
+                    index,
+                };
+                leaf.assign_targets(pw, targets, &leaf_input)
+            }
+        }
+    }
+}
+
+/// the batch leaf circuit of the tree, the batch size `M` is a const generic of `BatchLeafCircuit`
+/// so the tree only sees it through this trait, see `TreeRecursion::build_with_batch_leaf`
+trait BatchLeafStage<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+> {
+    /// returns the number of inner proofs in each leaf `M`
+    fn batch_size(&self) -> usize;
+
+    /// returns the verifier data of the inner (sampling) circuit
+    fn inner_verifier_data(&self) -> &VerifierCircuitData<F, C, D>;
+
+    /// assigns the witness of the batch leaf at `index` for its `M` inner proofs,
+    /// the `None` inner proofs are filled with a dummy proof and false flags.
+    fn assign(
+        &self,
+        pw: &mut PartialWitness<F>,
+        index: usize,
+        inner_proofs: &[Option<ProofWithPublicInputs<F, C, D>>],
+    ) -> Result<()>;
+}
+
+/// the batch leaf circuit with its targets and the dummy inner proof for the missing inner proofs
+struct BatchLeafCircuitStage<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const M: usize,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    circuit: BatchLeafCircuit<F, D, C, H, M>,
+    targets: BatchLeafTargets<D>,
+    dummy_inner_proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const M: usize,
+> BatchLeafStage<F, D, C> for BatchLeafCircuitStage<F, D, C, H, M> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    fn batch_size(&self) -> usize {
+        M
+    }
+
+    fn inner_verifier_data(&self) -> &VerifierCircuitData<F, C, D> {
+        self.circuit.inner_verifier_data()
+    }
+
+    fn assign(
+        &self,
+        pw: &mut PartialWitness<F>,
+        index: usize,
+        inner_proofs: &[Option<ProofWithPublicInputs<F, C, D>>],
+    ) -> Result<()> {
+        let input = BatchLeafInput{
+            inner_proofs: inner_proofs.iter()
+                .map(|p| p.clone().unwrap_or_else(|| self.dummy_inner_proof.clone()))
+                .collect(),
+            flags: inner_proofs.iter().map(|p| p.is_some()).collect(),
+            index,
+        };
+        self.circuit.assign_targets(pw, &self.targets, &input)
+    }
+}
+
+/// the result of the native pre-verification of the inner proofs, see `TreeRecursion::pre_verify`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PreVerificationReport {
+    /// the number of verified inner proofs
+    pub num_proofs: usize,
+    /// the index and the verification error of every inner proof that does not verify, by index
+    pub failed: Vec<(usize, String)>,
+    /// for every inner proof, the position of the inner circuit it verifies with
+    /// (in the allow-list for a tree built with `build_with_allow_list`, otherwise 0), `None` if it does not verify
+    pub vd_indices: Vec<Option<usize>>,
+}
+
+impl PreVerificationReport {
+    /// returns true if every inner proof verifies
+    pub fn is_valid(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// returns the indices of the inner proofs that do not verify
+    pub fn failed_indices(&self) -> Vec<usize> {
+        self.failed.iter().map(|(i, _)| *i).collect()
+    }
+}
+
+/// the public input verification circuit over the compressed proof with its circuit data and targets
+struct PublicInputVerificationStage<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    circuit: PublicInputVerificationCircuit<F, D, C, H, N>,
+    circ_data: CircuitData<F, C, D>,
+    targets: PublicInputVerificationTargets<D>,
+}
+
+/// the public input commitment circuit over the compressed proof with its circuit data and targets
+struct PublicInputCommitmentStage<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    circuit: PublicInputCommitmentCircuit<F, D, C, H, N>,
+    circ_data: CircuitData<F, C, D>,
+    targets: PublicInputCommitmentTargets<D>,
+}
+
 impl<
     F: RichField + Extendable<D> + Poseidon2,
     const D: usize,
     C: GenericConfig<D, F = F>,
     H: AlgebraicHasher<F>,
     const N: usize,
-    const T: usize,
-> TreeRecursion<F, D, C, H, N, T> where
+> TreeRecursion<F, D, C, H, N> where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
     /// build with standard recursion config
     pub fn build_with_standard_config(
         inner_verifier_data: VerifierCircuitData<F, C, D>,
+        t: usize,
     ) -> Result<Self> {
         Self::build(
             inner_verifier_data,
-            CircuitConfig::standard_recursion_config()
+            CircuitConfig::standard_recursion_config(),
+            t,
         )
     }
 
-    /// build the tree with given config
+    /// build the tree with given config for at most `t` inner proofs
     pub fn build(
         inner_verifier_data: VerifierCircuitData<F, C, D>,
         config: CircuitConfig,
+        t: usize,
     ) -> Result<Self> {
+        Self::check_max_proofs(t, 1)?;
+
         // build leaf with standard recursion config
-        let leaf = LeafCircuit::<_,D,_,_,T>::new(inner_verifier_data);
+        let leaf = LeafCircuit::<_,D,_,_>::new(inner_verifier_data, t);
         let (leaf_targets, leaf_circ_data) = leaf.build(config.clone())?;
         println!("leaf circuit size = {:?}", leaf_circ_data.common.degree_bits());
 
+        Self::build_with_leaf(TreeLeaf::Single(leaf, leaf_targets), leaf_circ_data, config, t)
+    }
+
+    /// build the tree with given config for at most `t` inner proofs, where each leaf verifies
+    /// `M` inner proofs with `BatchLeafCircuit` and the nodes aggregate the `t / M` leaves.
+    /// `t / M` must be a power of `N` and at least `N`.
+    /// the node verifies both leaf and node proofs with the leaf common data, so with more than one
+    /// node level this fails if the batch leaf and node common data do not match (see `ArityEstimate::compatible`).
+    pub fn build_with_batch_leaf<const M: usize>(
+        inner_verifier_data: VerifierCircuitData<F, C, D>,
+        config: CircuitConfig,
+        t: usize,
+    ) -> Result<Self> where
+        C: 'static,
+        H: Send + Sync + 'static,
+    {
+        Self::check_max_proofs(t, M)?;
+
+        // dummy inner proof for the missing inner proofs of a batch
+        let (dummy_inner_proof, _) = DummyProofGen::<F, D, C>::gen_dummy_proof_and_vd_zero_pi(&inner_verifier_data.common)?;
+
+        let circuit = BatchLeafCircuit::<_,D,_,H,M>::new(inner_verifier_data, t);
+        let (targets, leaf_circ_data) = circuit.build(config.clone())?;
+        println!("batch leaf circuit size = {:?}", leaf_circ_data.common.degree_bits());
+
+        let leaf = TreeLeaf::Batch(Box::new(BatchLeafCircuitStage{
+            circuit,
+            targets,
+            dummy_inner_proof,
+        }));
+        Self::build_with_leaf(leaf, leaf_circ_data, config, t)
+    }
+
+    /// build the tree with given config for at most `t` inner proofs of any of the circuits in `allow_list`,
+    /// each leaf verifies one inner proof with `HeterogeneousLeafCircuit`.
+    /// the nodes forward the allow-list root (see `NodeCircuit::with_leaf_constant`), so the verifier data hash
+    /// in the public input of the root commits to the allow-list and is checked by `verify_public_input`.
+    pub fn build_with_allow_list(
+        allow_list: VerifierKeyAllowList<F, D, C, H>,
+        config: CircuitConfig,
+        t: usize,
+    ) -> Result<Self> {
+        Self::check_max_proofs(t, 1)?;
+
+        let leaf = HeterogeneousLeafCircuit::<_,D,_,H>::new(allow_list, t);
+        let (leaf_targets, leaf_circ_data) = leaf.build(config.clone())?;
+        println!("heterogeneous leaf circuit size = {:?}", leaf_circ_data.common.degree_bits());
+
+        Self::build_with_leaf(TreeLeaf::Heterogeneous(leaf, leaf_targets), leaf_circ_data, config, t)
+    }
+
+    /// builds the node and compression circuits on top of the given leaf circuit
+    fn build_with_leaf(
+        leaf: TreeLeaf<F, D, C, H>,
+        leaf_circ_data: CircuitData<F, C, D>,
+        config: CircuitConfig,
+        t: usize,
+    ) -> Result<Self> {
         // build node with standard recursion config
-        let node = NodeCircuit::<_,D,_,_,N, T>::new(leaf_circ_data.verifier_data());
+        let mut node = NodeCircuit::<_,D,_,_,N>::new(leaf_circ_data.verifier_data(), t);
+        if let Some(leaf_constant) = leaf.leaf_constant() {
+            node = node.with_leaf_constant(leaf_constant);
+        }
         let (node_targets, node_circ_data) = node.build(config.clone())?;
         println!("node circuit size = {:?}", node_circ_data.common.degree_bits());
 
+        // the nodes above the first node level verify node proofs with the leaf common data
+        let num_node_levels = proofs_per_level(t / leaf.batch_size(), N)?.len() - 1;
+        if num_node_levels > 1 && leaf_circ_data.common != node_circ_data.common {
+            return Err(CircuitError::RecursionTreeError(format!(
+                "the leaf and node common data do not match (degree bits {} and {}), \
+                so a tree with {} node levels can't be built with arity {}",
+                leaf_circ_data.common.degree_bits(), node_circ_data.common.degree_bits(), num_node_levels, N
+            )));
+        }
+
         // compression build
         let compression_circ = CompressionCircuit::new(node_circ_data.verifier_data());
         let (compression_targets, compression_circ_data) = compression_circ.build(config.clone())?;
         println!("compress circuit size = {:?}", compression_circ_data.common.degree_bits());
 
+        // dummy circuit for the missing node slots, the node verifies both leaf and node proofs with the leaf common data
+        let dummy_node_circ_data = DummyProofGen::<F, D, C>::gen_dummy_circ_data(&leaf_circ_data.common);
+
         Ok(Self{
             leaf,
             node,
@@ -86,13 +468,80 @@ impl<
             leaf_circ_data,
             node_circ_data,
             compression_circ_data,
-            leaf_targets,
             node_targets,
             compression_targets,
+            dummy_node_circ_data,
+            pi_verifier: None,
+            pi_commitment: None,
+            #[cfg(feature = "parallel")]
+            thread_pool: None,
+            quarantine_invalid_proofs: false,
             phantom_data: Default::default(),
         })
     }
 
+    /// checks that the number of leaves `t / batch_size` is a power of `N` and at least `N`
+    fn check_max_proofs(t: usize, batch_size: usize) -> Result<()> {
+        if batch_size == 0 || !t.is_multiple_of(batch_size) {
+            return Err(CircuitError::RecursionTreeError(format!(
+                "maximum number of proofs {} is not a multiple of the batch size {}", t, batch_size
+            )));
+        }
+        proofs_per_level(t / batch_size, N).map(|_| ())
+    }
+
+    /// sets the number of threads used to prove the leaves and nodes of each level,
+    /// the thread pool is built once here and reused by every proving call.
+    /// only has an effect with the `parallel` feature, by default the global rayon thread pool is used.
+    #[cfg(feature = "parallel")]
+    pub fn set_num_threads(&mut self, num_threads: usize) -> Result<()> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|e| CircuitError::RecursionTreeError(e.to_string()))?;
+        self.thread_pool = Some(Arc::new(pool));
+        Ok(())
+    }
+
+    /// without the `parallel` feature everything is sequential, so the number of threads is ignored.
+    #[cfg(not(feature = "parallel"))]
+    pub fn set_num_threads(&mut self, _num_threads: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// sets whether the inner proofs that fail the pre-verification in `prove_tree` (and `prove_tree_with_checkpoints`)
+    /// are aggregated as dummy leaves instead of failing the aggregation with `CircuitError::InvalidInnerProofs`.
+    /// the aggregated indices can be read from the tree proof with `get_proven_indices`.
+    pub fn set_quarantine_invalid_proofs(&mut self, quarantine: bool) {
+        self.quarantine_invalid_proofs = quarantine;
+    }
+
+    /// returns the maximum number of inner proofs the tree was built for
+    pub fn get_max_proofs(&self) -> usize {
+        self.node.max_proofs()
+    }
+
+    /// returns the number of inner proofs in each leaf, `M` for a tree built with `build_with_batch_leaf`, otherwise 1
+    pub fn get_batch_size(&self) -> usize {
+        self.leaf.batch_size()
+    }
+
+    /// returns the number of leaves `t / M`
+    fn num_leaves(&self) -> usize {
+        self.get_max_proofs() / self.get_batch_size()
+    }
+
+    /// the public input verification and commitment circuits, the incremental aggregation and the export
+    /// rebuild the pi hash tree with one inner proof per leaf, so they are not supported with batch leaves
+    fn check_single_leaf(&self, operation: &str) -> Result<()> {
+        if self.get_batch_size() != 1 {
+            return Err(CircuitError::RecursionTreeError(format!(
+                "{} is not supported for a tree with batch leaves", operation
+            )));
+        }
+        Ok(())
+    }
+
     pub fn get_leaf_verifier_data(&self) -> VerifierCircuitData<F, C, D>{
         self.leaf_circ_data.verifier_data()
     }
@@ -113,8 +562,244 @@ impl<
         self.compression_circ_data.verifier_data()
     }
 
-    pub fn prove_bundle(_bundle: Bundle<F, C, D, H>){
-        todo!()
+    /// rebuilds the compression circuit so that the compressed proof has the number of real inner proofs
+    /// as its last public input (see `CompressionCircuit::with_proof_count`).
+    /// if `min_count` is set, only a tree proof with at least `min_count` real inner proofs can be compressed.
+    /// the public input verification and commitment circuits are rebuilt as well if they were built,
+    /// since they verify the compressed proof.
+    pub fn build_compression_with_proof_count(&mut self, min_count: Option<usize>) -> Result<()> {
+        let compression = CompressionCircuit::new(self.node_circ_data.verifier_data())
+            .with_proof_count(self.get_max_proofs(), min_count);
+        let (compression_targets, compression_circ_data) = compression.build(self.compression_circ_data.common.config.clone())?;
+        println!("compress circuit size = {:?}", compression_circ_data.common.degree_bits());
+
+        self.compression = compression;
+        self.compression_targets = compression_targets;
+        self.compression_circ_data = compression_circ_data;
+        if self.pi_verifier.is_some() {
+            self.build_public_input_verification()?;
+        }
+        if self.pi_commitment.is_some() {
+            self.build_public_input_commitment()?;
+        }
+        Ok(())
+    }
+
+    /// returns the number of real inner proofs from the public input of a compressed proof,
+    /// only if the compression circuit was built with `build_compression_with_proof_count`.
+    pub fn get_proof_count(&self, compressed_public_input: &[F]) -> Result<usize> {
+        if self.compression.proof_count().is_none() {
+            return Err(CircuitError::RecursionTreeError(
+                "the compression circuit is built without the proof count".to_string()
+            ));
+        }
+        let count = compressed_public_input.last().ok_or(CircuitError::PublicInputLengthError(
+            self.compression_circ_data.common.num_public_inputs, 0
+        ))?;
+        Ok(count.to_canonical_u64() as usize)
+    }
+
+    /// builds the public input verification circuit (see `PublicInputVerificationCircuit`)
+    /// as an optional stage after compression. its proof has the raw public input of all `t` inner proofs
+    /// and the verifier data hash as public input, so the pi hash tree doesn't have to be rebuilt to check it.
+    pub fn build_public_input_verification(&mut self) -> Result<()> {
+        self.check_single_leaf("the public input verification circuit")?;
+        let circuit = PublicInputVerificationCircuit::<F, D, C, H, N>::new(
+            self.compression_circ_data.verifier_data(),
+            self.get_max_proofs(),
+            self.leaf.inner_common().num_public_inputs,
+        );
+        let (targets, circ_data) = circuit.build(self.compression_circ_data.common.config.clone())?;
+        println!("public input verification circuit size = {:?}", circ_data.common.degree_bits());
+
+        self.pi_verifier = Some(PublicInputVerificationStage{
+            circuit,
+            circ_data,
+            targets,
+        });
+        Ok(())
+    }
+
+    /// returns true if the public input verification circuit is built
+    pub fn has_public_input_verification(&self) -> bool {
+        self.pi_verifier.is_some()
+    }
+
+    fn get_pi_verifier(&self) -> Result<&PublicInputVerificationStage<F, D, C, H, N>> {
+        self.pi_verifier.as_ref().ok_or(CircuitError::RecursionTreeError(
+            "the public input verification circuit is not built".to_string()
+        ))
+    }
+
+    pub fn get_public_input_verification_verifier_data(&self) -> Result<VerifierCircuitData<F, C, D>> {
+        Ok(self.get_pi_verifier()?.circ_data.verifier_data())
+    }
+
+    /// proves the public input verification circuit for the compressed proof.
+    /// `inner_public_input[i]` is the public input of the inner proof at leaf `i`, or `None` if the leaf is dummy,
+    /// the leaves after `inner_public_input.len()` are dummy. the public input of the dummy leaves is zero.
+    pub fn prove_public_input_verification(
+        &self,
+        compressed_proof: ProofWithPublicInputs<F, C, D>,
+        inner_public_input: Vec<Option<Vec<F>>>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let pi_verifier = self.get_pi_verifier()?;
+        let t = self.get_max_proofs();
+        if inner_public_input.len() > t {
+            return Err(CircuitError::RecursionTreeError(format!(
+                "at most {} inner public inputs expected, got {}", t, inner_public_input.len()
+            )));
+        }
+        let k = self.leaf.inner_common().num_public_inputs;
+        let mut inner_pub_inputs_vals: Vec<Vec<F>> = inner_public_input.into_iter()
+            .map(|pi| pi.unwrap_or(vec![F::ZERO; k]))
+            .collect();
+        inner_pub_inputs_vals.resize(t, vec![F::ZERO; k]);
+
+        let mut pw = PartialWitness::<F>::new();
+        pi_verifier.circuit.assign_targets(
+            &mut pw,
+            &pi_verifier.targets,
+            &PublicInputVerificationInput{
+                inner_proof: compressed_proof,
+                inner_pub_inputs_vals,
+            },
+        )?;
+
+        pi_verifier.circ_data.prove(pw)
+            .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))
+    }
+
+    /// verifies the proof of the public input verification circuit
+    pub fn verify_public_input_verification_proof(
+        &self,
+        proof: ProofWithPublicInputs<F, C, D>,
+    ) -> Result<()> {
+        self.get_pi_verifier()?.circ_data.verify(proof)
+            .map_err(|e| CircuitError::InvalidProofError(e.to_string()))
+    }
+
+    /// builds the public input commitment circuit (see `PublicInputCommitmentCircuit`)
+    /// as an optional stage after compression. its proof has the Merkle root of the `t` inner pi hashes
+    /// instead of the pi hash tree, so the public input of a single inner proof can be checked with a Merkle opening.
+    pub fn build_public_input_commitment(&mut self) -> Result<()> {
+        self.check_single_leaf("the public input commitment circuit")?;
+        let circuit = PublicInputCommitmentCircuit::<F, D, C, H, N>::new(
+            self.compression_circ_data.verifier_data(),
+            self.get_max_proofs(),
+        );
+        let (targets, circ_data) = circuit.build(self.compression_circ_data.common.config.clone())?;
+        println!("public input commitment circuit size = {:?}", circ_data.common.degree_bits());
+
+        self.pi_commitment = Some(PublicInputCommitmentStage{
+            circuit,
+            circ_data,
+            targets,
+        });
+        Ok(())
+    }
+
+    /// returns true if the public input commitment circuit is built
+    pub fn has_public_input_commitment(&self) -> bool {
+        self.pi_commitment.is_some()
+    }
+
+    fn get_pi_commitment(&self) -> Result<&PublicInputCommitmentStage<F, D, C, H, N>> {
+        self.pi_commitment.as_ref().ok_or(CircuitError::RecursionTreeError(
+            "the public input commitment circuit is not built".to_string()
+        ))
+    }
+
+    pub fn get_public_input_commitment_verifier_data(&self) -> Result<VerifierCircuitData<F, C, D>> {
+        Ok(self.get_pi_commitment()?.circ_data.verifier_data())
+    }
+
+    /// proves the public input commitment circuit for the compressed proof.
+    /// `inner_public_input[i]` is the public input of the inner proof at leaf `i`, or `None` if the leaf is dummy,
+    /// the leaves after `inner_public_input.len()` are dummy. the pi hash of the dummy leaves is zero.
+    pub fn prove_public_input_commitment(
+        &self,
+        compressed_proof: ProofWithPublicInputs<F, C, D>,
+        inner_public_input: Vec<Option<Vec<F>>>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let pi_commitment = self.get_pi_commitment()?;
+        let t = self.get_max_proofs();
+        if inner_public_input.len() > t {
+            return Err(CircuitError::RecursionTreeError(format!(
+                "at most {} inner public inputs expected, got {}", t, inner_public_input.len()
+            )));
+        }
+        // level 0 of the pi hash tree is the pi hash of each leaf
+        let inner_pi_hashes: Vec<HashOut<F>> = get_pi_hash_levels::<F, H>(&inner_public_input, t, N)[0]
+            .iter()
+            .map(|h| h.unwrap_or(HashOut::<F>::ZERO))
+            .collect();
+
+        let mut pw = PartialWitness::<F>::new();
+        pi_commitment.circuit.assign_targets(
+            &mut pw,
+            &pi_commitment.targets,
+            &PublicInputCommitmentInput{
+                inner_proof: compressed_proof,
+                inner_pi_hashes,
+            },
+        )?;
+
+        pi_commitment.circ_data.prove(pw)
+            .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))
+    }
+
+    /// verifies the proof of the public input commitment circuit
+    pub fn verify_public_input_commitment_proof(
+        &self,
+        proof: ProofWithPublicInputs<F, C, D>,
+    ) -> Result<()> {
+        self.get_pi_commitment()?.circ_data.verify(proof)
+            .map_err(|e| CircuitError::InvalidProofError(e.to_string()))
+    }
+
+    /// proves the sampling circuit inputs with the given bundle and aggregates them
+    /// into a single tree proof (or compressed proof if `compress` is true).
+    /// the bundle is cleared first, so only the proofs of `circ_inputs` are aggregated
+    /// and the bundle can be reused for the next job; afterwards it holds the proofs of this call.
+    /// each proof is placed at the leaf with its index in `circ_inputs`,
+    /// the missing indices become dummy leaves.
+    pub fn prove_bundle(
+        &mut self,
+        bundle: &mut Bundle<F, C, D, H>,
+        circ_inputs: HashMap<usize, SampleCircuitInput<F, D>>,
+        compress: bool,
+    ) -> Result<ProofWithPublicInputs<F, C, D>>
+    {
+        let t = self.get_max_proofs();
+
+        // the bundle must prove with an inner circuit of this tree
+        let vd_index = self.leaf.inner_verifier_data().iter()
+            .position(|vd| vd.verifier_only == bundle.verifier_data.verifier_only)
+            .ok_or(CircuitError::RecursionTreeError(
+                "bundle sampling circuit does not match the tree inner circuit".to_string()
+            ))?;
+        if let Some(index) = circ_inputs.keys().find(|&&i| i >= t) {
+            return Err(CircuitError::RecursionTreeError(format!(
+                "bundle index {} is out of range, the tree aggregates at most {} proofs", index, t
+            )));
+        }
+
+        // drop the proofs of earlier calls and prove the sampling circuit inputs
+        bundle.bundle_proofs.clear();
+        bundle.prove_indexed(circ_inputs)?;
+
+        let mut inner_proofs = vec![None; t];
+        for (&index, proof) in bundle.bundle_proofs.iter() {
+            inner_proofs[index] = Some(proof.clone());
+        }
+
+        let proof = self.prove_tree_with_dummies(inner_proofs, vec![vd_index; t], &NoCheckpoint)?;
+        if compress {
+            self.compress(proof)
+        } else {
+            Ok(proof)
+        }
     }
 
     pub fn prove_tree_and_compress(
@@ -124,6 +809,15 @@ impl<
     {
         let proof =
             self.prove_tree(proofs_with_pi)?;
+        self.compress(proof)
+    }
+
+    /// compresses the tree proof with the compression circuit
+    pub fn compress(
+        &self,
+        proof: ProofWithPublicInputs<F, C, D>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>>
+    {
         let mut pw = PartialWitness::<F>::new();
         self.compression.assign_targets(
             &mut pw,
@@ -136,97 +830,425 @@ impl<
         )
     }
 
+    /// aggregates the given inner proofs into a single tree proof.
+    /// the number of proofs can be anything from 1 to the maximum number of proofs `t`,
+    /// the proof at position `i` gets the leaf index `i` and the remaining leaves are dummy.
+    /// the inner proofs are pre-verified natively first, see `set_quarantine_invalid_proofs`.
     pub fn prove_tree
     (
         &mut self,
         proofs_with_pi: &[ProofWithPublicInputs<F, C, D>],
     ) -> Result<ProofWithPublicInputs<F, C, D>>
     {
-        if proofs_with_pi.len() % 2 != 0 {
+        let t = self.get_max_proofs();
+        if proofs_with_pi.is_empty() || proofs_with_pi.len() > t {
             return
                 Err(CircuitError::RecursionTreeError(format!(
-                    "input proofs must be divisible by {}, got {}", 2, proofs_with_pi.len())
+                    "number of input proofs must be between 1 and {}, got {}", t, proofs_with_pi.len())
                 ))
         }
-        // process leaves
-        let leaf_proofs = self.get_leaf_proofs(
-            &proofs_with_pi,
-        )?;
+
+        let (inner_proofs, vd_indices) = self.pre_verified_inner_proofs(proofs_with_pi)?;
+
+        self.prove_tree_with_dummies(inner_proofs, vd_indices, &NoCheckpoint)
+    }
+
+    /// verifies every inner proof natively with the inner verifier data before aggregation
+    /// (concurrently with the `parallel` feature), and reports the ones that do not verify.
+    pub fn pre_verify(
+        &self,
+        proofs_with_pi: &[ProofWithPublicInputs<F, C, D>],
+    ) -> Result<PreVerificationReport> {
+        let inner_verifier_data = self.leaf.inner_verifier_data();
+        // a proof is valid if it verifies with any of the inner circuits, the first one is recorded
+        let results: Vec<std::result::Result<usize, String>> = self.run_parallel(|| {
+            Ok((0..proofs_with_pi.len()).into_par_iter()
+                .map(|i| {
+                    let mut errors = vec![];
+                    for (vd_index, vd) in inner_verifier_data.iter().enumerate() {
+                        match vd.verify(proofs_with_pi[i].clone()) {
+                            Ok(()) => return Ok(vd_index),
+                            Err(e) => errors.push(e.to_string()),
+                        }
+                    }
+                    Err(errors.join("; "))
+                })
+                .collect())
+        })?;
+
+        Ok(PreVerificationReport {
+            num_proofs: proofs_with_pi.len(),
+            failed: results.iter().enumerate()
+                .filter_map(|(i, r)| r.as_ref().err().map(|e| (i, e.clone())))
+                .collect(),
+            vd_indices: results.into_iter().map(|r| r.ok()).collect(),
+        })
+    }
+
+    /// pre-verifies the inner proofs (see `pre_verify`) and returns them as the leaves of the tree
+    /// with the positions of their inner circuits.
+    /// the invalid proofs become dummy leaves if `quarantine_invalid_proofs` is set,
+    /// otherwise they fail with `CircuitError::InvalidInnerProofs`.
+    #[allow(clippy::type_complexity)]
+    fn pre_verified_inner_proofs(
+        &self,
+        proofs_with_pi: &[ProofWithPublicInputs<F, C, D>],
+    ) -> Result<(Vec<Option<ProofWithPublicInputs<F, C, D>>>, Vec<usize>)> {
+        let report = self.pre_verify(proofs_with_pi)?;
+        if !report.is_valid() && !self.quarantine_invalid_proofs {
+            return Err(CircuitError::InvalidInnerProofs(report.failed_indices()));
+        }
+
+        let mut inner_proofs: Vec<Option<ProofWithPublicInputs<F, C, D>>> =
+            proofs_with_pi.iter().cloned().map(Some).collect();
+        for i in report.failed_indices() {
+            inner_proofs[i] = None;
+        }
+        // the position is unused for the dummy leaves
+        let vd_indices = report.vd_indices.iter().map(|i| i.unwrap_or(0)).collect();
+        Ok((inner_proofs, vd_indices))
+    }
+
+    /// aggregates the inner proofs where `None` marks a dummy leaf,
+    /// `vd_indices[i]` is the position of the inner circuit of `inner_proofs[i]` (see `PreVerificationReport::vd_indices`).
+    /// `inner_proofs` is padded with dummy leaves up to the maximum number of proofs `t`.
+    /// the aggregation resumes from the highest level that is complete in `checkpoint`,
+    /// if no level is complete, the stored leaf proofs are reused and the missing leaves are proved.
+    fn prove_tree_with_dummies
+    (
+        &self,
+        mut inner_proofs: Vec<Option<ProofWithPublicInputs<F, C, D>>>,
+        mut vd_indices: Vec<usize>,
+        checkpoint: &dyn TreeCheckpoint<F, C, D>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>>
+    {
+        inner_proofs.resize(self.get_max_proofs(), None);
+        vd_indices.resize(self.get_max_proofs(), 0);
+
+        // the expected pi hash of every proof in the tree from the leaves up, `None` for dummy
+        let inner_public_input: Vec<Option<Vec<F>>> = inner_proofs.iter()
+            .map(|p| p.as_ref().map(|p| p.public_inputs.clone()))
+            .collect();
+        let pi_hashes = self.get_tree_pi_hash_levels(&inner_public_input);
+
+        // find the highest level with all real proofs stored
+        let complete_level = (0..pi_hashes.len()).rev().find(|&level| {
+            pi_hashes[level].iter().enumerate()
+                .all(|(i, h)| h.is_none() || checkpoint.contains(level, i))
+        });
+
+        let (proofs, level) = match complete_level {
+            Some(level) => {
+                let mut proofs = vec![];
+                for (i, h) in pi_hashes[level].iter().enumerate() {
+                    proofs.push(match h {
+                        Some(h) => Some(self.load_checkpoint(checkpoint, level, i, h)?),
+                        None => None,
+                    });
+                }
+                (proofs, level)
+            }
+            // process leaves
+            None => (self.get_leaf_proofs(&inner_proofs, &vd_indices, &pi_hashes[0], checkpoint)?, 0),
+        };
 
         // process nodes
-        let (root_proof, _vd) =
-            self.prove(&leaf_proofs,&self.leaf_circ_data.verifier_only, 0)?;
+        let root_proof =
+            self.prove(&proofs, level, checkpoint)?;
+
+        root_proof.ok_or(CircuitError::RecursionTreeError(
+            "cannot aggregate a tree with only dummy proofs".to_string()
+        ))
+    }
 
-        Ok(root_proof)
+    /// the pi hash of every leaf and node proof, level by level from the leaves (level 0) up to the root.
+    /// with batch leaves, the pi hashes of the inner proofs below the leaves are dropped.
+    fn get_tree_pi_hash_levels(&self, inner_public_input: &[Option<Vec<F>>]) -> Vec<Vec<Option<HashOut<F>>>> {
+        let batch_size = self.get_batch_size();
+        let mut levels = get_batch_pi_hash_levels::<F, H>(inner_public_input, self.get_max_proofs(), batch_size, N);
+        if batch_size > 1 {
+            levels.remove(0);
+        }
+        levels
     }
 
+    /// loads the proof at `level` and `index` from `checkpoint` and checks that it is
+    /// a valid proof for this tree with the expected pi hash and index.
+    fn load_checkpoint(
+        &self,
+        checkpoint: &dyn TreeCheckpoint<F, C, D>,
+        level: usize,
+        index: usize,
+        expected_pi_hash: &HashOut<F>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let proof = checkpoint.load(level, index)?.ok_or(CircuitError::CheckpointError(
+            format!("missing proof at level {} index {}", level, index)
+        ))?;
+        let circ_data = if level == 0 { &self.leaf_circ_data } else { &self.node_circ_data };
+        circ_data.verify(proof.clone()).map_err(|e| CircuitError::CheckpointError(
+            format!("proof at level {} index {} does not verify: {}", level, index, e)
+        ))?;
+        if proof.public_inputs[0..4] != expected_pi_hash.elements
+            || proof.public_inputs[8] != F::from_canonical_u64(index as u64) {
+            return Err(CircuitError::CheckpointError(format!(
+                "proof at level {} index {} does not match the inputs of this aggregation", level, index
+            )));
+        }
+        Ok(proof)
+    }
+
+    /// proves a leaf for each chunk of `M` inner proofs with a real proof (`M` = 1 without batch leaves),
+    /// dummy leaves are kept as `None`.
+    /// the leaves stored in `checkpoint` are loaded instead of proved, the new ones are stored.
     fn get_leaf_proofs
     (
-        &mut self,
-        proofs_with_pi: &[ProofWithPublicInputs<F, C, D>],
-    ) -> Result<Vec<ProofWithPublicInputs<F, C, D>>> {
+        &self,
+        inner_proofs: &[Option<ProofWithPublicInputs<F, C, D>>],
+        vd_indices: &[usize],
+        pi_hashes: &[Option<HashOut<F>>],
+        checkpoint: &dyn TreeCheckpoint<F, C, D>,
+    ) -> Result<Vec<Option<ProofWithPublicInputs<F, C, D>>>> {
 
-        let mut leaf_proofs = vec![];
+        let mut leaf_proofs = vec![None; self.num_leaves()];
 
-        for (i, proof) in proofs_with_pi.iter().enumerate(){
-            let leaf_input = LeafInput{
-                inner_proof: proof.clone(),
-                flag: true,
-                index: i,
+        // assign the witness of each real leaf
+        let mut leaf_indices = vec![];
+        let mut witnesses = vec![];
+        let batch_size = self.get_batch_size();
+        for (i, (proofs, vd_indices)) in inner_proofs.chunks(batch_size).zip(vd_indices.chunks(batch_size)).enumerate(){
+            let Some(pi_hash) = &pi_hashes[i] else {
+                continue;
             };
+            if checkpoint.contains(0, i) {
+                leaf_proofs[i] = Some(self.load_checkpoint(checkpoint, 0, i, pi_hash)?);
+                continue;
+            }
+            leaf_indices.push(i);
+            witnesses.push(self.assign_leaf(i, proofs, vd_indices)?);
+        }
 
-            let mut pw = PartialWitness::<F>::new();
+        // prove the leaves - all leaves are independent
+        let proofs = self.prove_witnesses(&self.leaf_circ_data, &leaf_indices, witnesses, 0, checkpoint)?;
 
-            self.leaf.assign_targets(&mut pw,&self.leaf_targets,&leaf_input)?;
-            let proof = self.leaf_circ_data.prove(pw).unwrap();
-            leaf_proofs.push(proof);
+        for (i, proof) in leaf_indices.into_iter().zip(proofs) {
+            leaf_proofs[i] = Some(proof);
         }
 
         Ok(leaf_proofs)
     }
 
+    /// assigns the witness of the leaf at `index` for its inner proofs, `None` marks a dummy inner proof
+    fn assign_leaf(
+        &self,
+        index: usize,
+        inner_proofs: &[Option<ProofWithPublicInputs<F, C, D>>],
+        vd_indices: &[usize],
+    ) -> Result<PartialWitness<F>> {
+        let mut pw = PartialWitness::<F>::new();
+        self.leaf.assign(&mut pw, index, inner_proofs, vd_indices)?;
+        Ok(pw)
+    }
+
+    /// assigns the witness of the node at `index` on `level` (level 0 verifies leaves).
+    /// the `None` children are filled with the next proofs from `dummy_proofs` and false flags.
+    fn assign_node(
+        &self,
+        children: &[Option<ProofWithPublicInputs<F, C, D>>],
+        dummy_proofs: &mut impl Iterator<Item = ProofWithPublicInputs<F, C, D>>,
+        level: usize,
+        index: usize,
+    ) -> Result<PartialWitness<F>> {
+        // condition: true -> node, false -> leaf
+        let condition = level != 0;
+        let verifier_only_data = if condition {
+            &self.node_circ_data.verifier_only
+        } else {
+            &self.leaf_circ_data.verifier_only
+        };
+
+        // fill the dummy slots with dummy proofs and false flags
+        let mut inner_proofs = vec![];
+        let mut flags = vec![];
+        for proof in children.iter() {
+            match proof {
+                Some(p) => {
+                    inner_proofs.push(p.clone());
+                    flags.push(true);
+                }
+                None => {
+                    inner_proofs.push(dummy_proofs.next().ok_or(CircuitError::RecursionTreeError(
+                        "missing dummy proof".to_string()
+                    ))?);
+                    flags.push(false);
+                }
+            }
+        }
+
+        let mut inner_pw = PartialWitness::new();
+
+        let node_input = NodeInput{
+            inner_proofs,
+            verifier_only_data: verifier_only_data.clone(),
+            condition,
+            flags,
+            index,
+        };
+
+        self.node.assign_targets(
+            &mut inner_pw,
+            &self.node_targets,
+            &node_input
+        )?;
+        Ok(inner_pw)
+    }
+
+    /// proves the leaf for the real inner proof at `index`
+    pub(crate) fn prove_leaf(
+        &self,
+        index: usize,
+        inner_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        self.check_single_leaf("the incremental aggregation")?;
+        if index >= self.get_max_proofs() {
+            return Err(CircuitError::RecursionTreeError(format!(
+                "leaf index {} is out of range, the tree aggregates at most {} proofs", index, self.get_max_proofs()
+            )));
+        }
+        // the position of the inner circuit is only needed (and pre-verified) with several inner circuits
+        let vd_index = if self.leaf.inner_verifier_data().len() > 1 {
+            self.pre_verify(std::slice::from_ref(inner_proof))?.vd_indices[0]
+                .ok_or(CircuitError::InvalidInnerProofs(vec![index]))?
+        } else {
+            0
+        };
+        let pw = self.assign_leaf(index, &[Some(inner_proof.clone())], &[vd_index])?;
+        self.leaf_circ_data.prove(pw)
+            .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))
+    }
+
+    /// proves the node at `index` on `level` (level 0 verifies leaves) from its `N` children,
+    /// the `None` children are dummy.
+    pub(crate) fn prove_node(
+        &self,
+        children: &[Option<ProofWithPublicInputs<F, C, D>>],
+        level: usize,
+        index: usize,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let mut dummy_proofs = vec![];
+        for (j, child) in children.iter().enumerate() {
+            if child.is_none() {
+                dummy_proofs.push(Self::gen_dummy_node_proof(&self.dummy_node_circ_data, index * N + j)?);
+            }
+        }
+        let pw = self.assign_node(children, &mut dummy_proofs.into_iter(), level, index)?;
+        self.node_circ_data.prove(pw)
+            .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))
+    }
+
+    /// generates a dummy proof for the node slot with the given index.
+    /// only the index is set in the public input, so the pi hash and flag buckets are zero.
+    fn gen_dummy_node_proof(
+        dummy_node_circ_data: &CircuitData<F, C, D>,
+        index: usize,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        // public input [8] contains the index
+        let mut nonzero_public_inputs = HashMap::new();
+        nonzero_public_inputs.insert(8, F::from_canonical_u64(index as u64));
+        DummyProofGen::<F, D, C>::gen_dummy_proof(dummy_node_circ_data, nonzero_public_inputs)
+    }
+
+    /// proves the given witnesses with `circ_data`, the proofs are returned in the same order.
+    /// each proof is stored in `checkpoint` at `level` and its index from `indices` as soon as it is done.
+    /// with the `parallel` feature, the proofs are generated concurrently.
+    fn prove_witnesses(
+        &self,
+        circ_data: &CircuitData<F, C, D>,
+        indices: &[usize],
+        witnesses: Vec<PartialWitness<F>>,
+        level: usize,
+        checkpoint: &dyn TreeCheckpoint<F, C, D>,
+    ) -> Result<Vec<ProofWithPublicInputs<F, C, D>>> {
+        let witnesses: Vec<(usize, PartialWitness<F>)> = indices.iter().cloned().zip(witnesses).collect();
+        self.run_parallel(|| {
+            witnesses.into_par_iter()
+                .map(|(index, pw)| {
+                    let proof = circ_data.prove(pw)
+                        .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))?;
+                    checkpoint.store(level, index, &proof)?;
+                    Ok(proof)
+                })
+                .collect()
+        })
+    }
+
+    /// runs `op` in the thread pool from `set_num_threads` if set, otherwise in the global rayon pool.
+    #[cfg(feature = "parallel")]
+    fn run_parallel<R: Send>(&self, op: impl FnOnce() -> Result<R> + Send) -> Result<R> {
+        match &self.thread_pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// runs `op`, without the `parallel` feature everything is sequential.
+    #[cfg(not(feature = "parallel"))]
+    fn run_parallel<R>(&self, op: impl FnOnce() -> Result<R>) -> Result<R> {
+        op()
+    }
+
     /// generates a proof
+    /// `None` marks a dummy proof, a chunk of dummy proofs results in a dummy proof at the next level.
+    /// all nodes in a level are independent, so they are proved together (concurrently with the `parallel` feature).
+    /// the node proofs are stored in `checkpoint` at `level + 1`.
     fn prove(
         &self,
-        proofs_with_pi: &[ProofWithPublicInputs<F, C, D>],
-        verifier_only_data: &VerifierOnlyCircuitData<C, D>,
+        proofs_with_pi: &[Option<ProofWithPublicInputs<F, C, D>>],
         level: usize,
-    ) -> Result<(ProofWithPublicInputs<F, C, D>, VerifierOnlyCircuitData<C, D>)> where
+        checkpoint: &dyn TreeCheckpoint<F, C, D>,
+    ) -> Result<Option<ProofWithPublicInputs<F, C, D>>> where
         <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
     {
 
         if proofs_with_pi.len() == 1 {
-            return Ok((proofs_with_pi[0].clone(), verifier_only_data.clone()));
+            return Ok(proofs_with_pi[0].clone());
         }
 
-        let mut new_proofs = vec![];
+        // no real proof in a chunk -> no need to prove the node
+        let is_real_chunk: Vec<bool> = proofs_with_pi.chunks(N)
+            .map(|chunk| chunk.iter().any(|p| p.is_some()))
+            .collect();
 
-        let condition = if level == 0 {false} else {true};
+        // dummy proofs for the dummy slots in the chunks to prove
+        let dummy_indices: Vec<usize> = proofs_with_pi.iter().enumerate()
+            .filter(|(k, p)| p.is_none() && is_real_chunk[k / N])
+            .map(|(k, _)| k)
+            .collect();
+        let dummy_node_circ_data = &self.dummy_node_circ_data;
+        let mut dummy_proofs = self.run_parallel(|| {
+            dummy_indices.par_iter()
+                .map(|&k| Self::gen_dummy_node_proof(dummy_node_circ_data, k))
+                .collect::<Result<Vec<_>>>()
+        })?.into_iter();
 
+        // assign the witness of each node
+        let mut node_indices = vec![];
+        let mut witnesses = vec![];
         for (i, chunk) in proofs_with_pi.chunks(N).enumerate() {
-
-            let mut inner_pw = PartialWitness::new();
-
-            let node_input = NodeInput{
-                inner_proofs: chunk.to_vec().clone(),
-                verifier_only_data: verifier_only_data.clone(),
-                condition,
-                flags: [true; N].to_vec(),
-                index: i,
-            };
-
-            self.node.assign_targets(
-                &mut inner_pw,
-                &self.node_targets,
-                &node_input
-            )?;
-
-            let proof = self.node_circ_data.prove(inner_pw)
-                .map_err(|e| CircuitError::ProofGenerationError(e.to_string()))?;
-            new_proofs.push(proof);
+            if !is_real_chunk[i] {
+                continue;
+            }
+            node_indices.push(i);
+            witnesses.push(self.assign_node(chunk, &mut dummy_proofs, level, i)?);
         }
 
-        self.prove(&new_proofs, &self.node_circ_data.verifier_only, level+1)
+        let mut proofs = self.prove_witnesses(
+            &self.node_circ_data, &node_indices, witnesses, level + 1, checkpoint
+        )?.into_iter();
+        let new_proofs: Vec<Option<ProofWithPublicInputs<F, C, D>>> = is_real_chunk.iter()
+            .map(|&is_real| if is_real { proofs.next() } else { None })
+            .collect();
+
+        self.prove(&new_proofs, level+1, checkpoint)
     }
 
     pub fn verify_proof(
@@ -261,43 +1283,259 @@ impl<
         }
     }
 
+    /// verifies the public input of the tree proof against the public input of the inner proofs.
+    /// `inner_public_input` holds the public input of the real inner proofs at leaf index 0..len,
+    /// the remaining leaves (up to `t`) are dummy.
     pub fn verify_public_input(
         &self,
         public_input: Vec<F>,
         inner_public_input: Vec<Vec<F>>,
     ) -> Result<()>{
-        assert!(public_input.len() >= 8);
+        let inner_public_input = inner_public_input.into_iter().map(Some).collect();
+        self.verify_public_input_with_dummies(public_input, inner_public_input)
+    }
+
+    /// same as `verify_public_input` but `inner_public_input[i]` is `None` if the leaf `i` is dummy,
+    /// e.g. for a proof from `prove_bundle`. the leaves after `inner_public_input.len()` are dummy.
+    pub fn verify_public_input_with_dummies(
+        &self,
+        public_input: Vec<F>,
+        inner_public_input: Vec<Option<Vec<F>>>,
+    ) -> Result<()>{
+        let t = self.get_max_proofs();
+        // the root is the node at level 0 if there are N leaves, which carries the leaf verifier data
+        let vd = if self.num_leaves() == N {
+            self.leaf_circ_data.verifier_data()
+        } else {
+            self.node_circ_data.verifier_data()
+        };
+        // the nodes hash the forwarded leaf constant (e.g. the allow-list root) with the verifier data
+        let vd_hash = match self.leaf.leaf_constant() {
+            Some(leaf_constant) => get_hash_of_verifier_data_with_leaf_constant::<F,D,C,H>(&vd, leaf_constant),
+            None => get_hash_of_verifier_data::<F,D,C,H>(&vd),
+        };
 
-        let given_input_hash = &public_input[0..4];
-        let given_vd_hash = &public_input[4..8];
+        verify_batch_tree_public_input::<F, H>(&public_input, &inner_public_input, vd_hash, t, self.get_batch_size(), N)
+    }
 
-        let node_hash = get_hash_of_verifier_data::<F,D,C,H>(&self.node_circ_data.verifier_data());
+    /// returns the indices of the real inner proofs from the flag buckets of the tree (or compressed) proof public input
+    pub fn get_proven_indices(&self, public_input: &[F]) -> Result<Vec<usize>> {
+        get_proven_indices(public_input, self.get_max_proofs())
+    }
 
-        let mut pub_in_hashes = vec![];
-        for pub_in in inner_public_input{
-            let hash = H::hash_no_pad(&pub_in);
-            pub_in_hashes.push(hash);
+    /// checks that the tree (or compressed) proof covers exactly the inner proofs at the `expected` indices
+    pub fn verify_proven_indices(&self, public_input: &[F], expected: &[usize]) -> Result<()> {
+        verify_proven_indices(public_input, self.get_max_proofs(), expected)
+    }
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2 + Serialize,
+    const D: usize,
+    C: GenericConfig<D, F = F> + Default + Serialize + 'static,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> TreeRecursion<F, D, C, H, N> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    /// same as `prove_tree` but every leaf and node proof is written to `work_dir` as soon as it is done
+    /// (see `CheckpointDir`). if the aggregation is interrupted, running it again with the same inputs
+    /// and `work_dir` continues from the highest completed level.
+    pub fn prove_tree_with_checkpoints<P: AsRef<Path>>
+    (
+        &mut self,
+        proofs_with_pi: &[ProofWithPublicInputs<F, C, D>],
+        work_dir: P,
+    ) -> Result<ProofWithPublicInputs<F, C, D>>
+    {
+        let t = self.get_max_proofs();
+        if proofs_with_pi.is_empty() || proofs_with_pi.len() > t {
+            return
+                Err(CircuitError::RecursionTreeError(format!(
+                    "number of input proofs must be between 1 and {}, got {}", t, proofs_with_pi.len())
+                ))
+        }
+
+        let (inner_proofs, vd_indices) = self.pre_verified_inner_proofs(proofs_with_pi)?;
+
+        let checkpoint = CheckpointDir::<F, C, D>::new(work_dir);
+        self.prove_tree_with_dummies(inner_proofs, vd_indices, &checkpoint)
+    }
+
+    /// exports the leaf, node and compression circuit data and targets under `base_path`:
+    /// `{base_path}/leaf`, `{base_path}/node` and `{base_path}/compression`,
+    /// and `{base_path}/pi_verifier` (`{base_path}/pi_commitment`) if the public input verification (commitment)
+    /// circuit is built,
+    /// each with the layout of `export_full_circuit_data`.
+    /// the parameters file is written last, so an interrupted export is not loaded.
+    pub fn export_circuits<P: AsRef<Path>>(&self, base_path: P) -> Result<()> {
+        let TreeLeaf::Single(leaf, leaf_targets) = &self.leaf else {
+            return Err(CircuitError::RecursionTreeError(
+                "exporting the circuits is only supported for a tree built with `build`".to_string()
+            ));
+        };
+        let base_path = base_path.as_ref();
+        export_full_circuit_data(&self.leaf_circ_data, leaf_targets, base_path.join(LEAF_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        export_full_circuit_data(&self.node_circ_data, &self.node_targets, base_path.join(NODE_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        export_full_circuit_data(&self.compression_circ_data, &self.compression_targets, base_path.join(COMPRESSION_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        if let Some(pi_verifier) = &self.pi_verifier {
+            export_full_circuit_data(&pi_verifier.circ_data, &pi_verifier.targets, base_path.join(PI_VERIFIER_CIRC_DIR))
+                .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        }
+        if let Some(pi_commitment) = &self.pi_commitment {
+            export_full_circuit_data(&pi_commitment.circ_data, &pi_commitment.targets, base_path.join(PI_COMMITMENT_CIRC_DIR))
+                .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
         }
 
-        while pub_in_hashes.len() > 1 {
-            let mut next_level_pi_hashes = Vec::new();
-            for pi_chunk in pub_in_hashes.chunks(N) {
-                // collect field elements
-                let pi_chunk_f: Vec<F> = pi_chunk.iter()
-                    .flat_map(|h| h.elements.iter().cloned())
-                    .collect();
-                // Compute hash of the concatenated chunk
-                let pi_hash = H::hash_no_pad(&pi_chunk_f);
-                next_level_pi_hashes.push(pi_hash);
+        let params = Self::circuit_params(leaf.inner_verifier_data(), self.get_max_proofs(), self.compression.proof_count());
+        let params_path = base_path.join(TREE_PARAMS_JSON);
+        let bytes = serde_json::to_vec(&params)
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        ensure_parent_directory_exists(&params_path)
+            .and_then(|_| Ok(std::fs::write(&params_path, bytes)?))
+            .map_err(|e| CircuitError::SerializationError(format!("{:?}: {}", params_path, e)))?;
+        Ok(())
+    }
+
+    /// loads the tree circuits exported with `export_circuits` instead of building them.
+    /// returns `StaleCircuitData` if the exported circuits were built for a different inner circuit,
+    /// maximum number of proofs `t`, arity `N` or circuit config.
+    pub fn load<P: AsRef<Path>>(
+        inner_verifier_data: VerifierCircuitData<F, C, D>,
+        config: CircuitConfig,
+        t: usize,
+        base_path: P,
+    ) -> Result<Self> {
+        Self::check_max_proofs(t, 1)?;
+        let base_path = base_path.as_ref();
+
+        let params_path = base_path.join(TREE_PARAMS_JSON);
+        let params_str = std::fs::read_to_string(&params_path)
+            .map_err(|e| CircuitError::SerializationError(format!("{:?}: {}", params_path, e)))?;
+        let params: TreeCircuitParams = serde_json::from_str(&params_str)
+            .map_err(|e| CircuitError::SerializationError(format!("{:?}: {}", params_path, e)))?;
+        let expected_params = Self::circuit_params(&inner_verifier_data, t, params.proof_count);
+        if params != expected_params {
+            return Err(CircuitError::StaleCircuitData(format!(
+                "circuits in {:?} were built for {:?}, expected {:?}", base_path, params, expected_params
+            )));
+        }
+
+        let leaf_circ_data = import_full_circuit_data::<F, C, D, _>(base_path.join(LEAF_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        let node_circ_data = import_full_circuit_data::<F, C, D, _>(base_path.join(NODE_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        let compression_circ_data = import_full_circuit_data::<F, C, D, _>(base_path.join(COMPRESSION_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        let leaf_targets: LeafTargets<D> = import_targets(base_path.join(LEAF_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        let node_targets: NodeTargets<D> = import_targets(base_path.join(NODE_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        let compression_targets: CompressionTargets<D> = import_targets(base_path.join(COMPRESSION_CIRC_DIR))
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+
+        for common in [&leaf_circ_data.common, &node_circ_data.common, &compression_circ_data.common] {
+            if common.config != config {
+                return Err(CircuitError::StaleCircuitData(format!(
+                    "circuits in {:?} were built with a different circuit config", base_path
+                )));
             }
-            pub_in_hashes = next_level_pi_hashes;
         }
 
-        //check expected hash
-        let expected_pi_hash = pub_in_hashes[0];
+        let dummy_node_circ_data = DummyProofGen::<F, D, C>::gen_dummy_circ_data(&leaf_circ_data.common);
 
-        assert_eq!(given_input_hash, expected_pi_hash.elements);
-        assert_eq!(given_vd_hash, node_hash.elements);
-        Ok(())
+        // the public input verification and commitment circuits are only exported if they were built
+        let pi_verifier_path = base_path.join(PI_VERIFIER_CIRC_DIR);
+        let pi_verifier = if pi_verifier_path.exists() {
+            let circ_data = import_full_circuit_data::<F, C, D, _>(&pi_verifier_path)
+                .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+            let targets: PublicInputVerificationTargets<D> = import_targets(&pi_verifier_path)
+                .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+            let circuit = PublicInputVerificationCircuit::new(
+                compression_circ_data.verifier_data(),
+                t,
+                inner_verifier_data.common.num_public_inputs,
+            );
+            Some(PublicInputVerificationStage{ circuit, circ_data, targets })
+        } else {
+            None
+        };
+        let pi_commitment_path = base_path.join(PI_COMMITMENT_CIRC_DIR);
+        let pi_commitment = if pi_commitment_path.exists() {
+            let circ_data = import_full_circuit_data::<F, C, D, _>(&pi_commitment_path)
+                .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+            let targets: PublicInputCommitmentTargets<D> = import_targets(&pi_commitment_path)
+                .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+            let circuit = PublicInputCommitmentCircuit::new(compression_circ_data.verifier_data(), t);
+            Some(PublicInputCommitmentStage{ circuit, circ_data, targets })
+        } else {
+            None
+        };
+
+        let leaf = TreeLeaf::Single(LeafCircuit::<_,D,_,_>::new(inner_verifier_data, t), leaf_targets);
+        let node = NodeCircuit::<_,D,_,_,N>::new(leaf_circ_data.verifier_data(), t);
+        let mut compression = CompressionCircuit::new(node_circ_data.verifier_data());
+        if let Some(ProofCount{ t, min_count }) = params.proof_count {
+            compression = compression.with_proof_count(t, min_count);
+        }
+
+        Ok(Self{
+            leaf,
+            node,
+            compression,
+            leaf_circ_data,
+            node_circ_data,
+            compression_circ_data,
+            node_targets,
+            compression_targets,
+            dummy_node_circ_data,
+            pi_verifier,
+            pi_commitment,
+            #[cfg(feature = "parallel")]
+            thread_pool: None,
+            quarantine_invalid_proofs: false,
+            phantom_data: Default::default(),
+        })
+    }
+
+    /// loads the tree circuits from `base_path` if they were exported for the same inner circuit and parameters,
+    /// otherwise builds them and exports them to `base_path` for the next run.
+    pub fn load_or_build<P: AsRef<Path>>(
+        inner_verifier_data: VerifierCircuitData<F, C, D>,
+        config: CircuitConfig,
+        t: usize,
+        base_path: P,
+    ) -> Result<Self> {
+        let base_path = base_path.as_ref();
+        if base_path.join(TREE_PARAMS_JSON).exists() {
+            match Self::load(inner_verifier_data.clone(), config.clone(), t, base_path) {
+                Ok(tree) => return Ok(tree),
+                Err(CircuitError::StaleCircuitData(e)) => println!("rebuilding the tree circuits: {}", e),
+                Err(e) => return Err(e),
+            }
+        }
+        let tree = Self::build(inner_verifier_data, config, t)?;
+        tree.export_circuits(base_path)?;
+        Ok(tree)
+    }
+
+    fn circuit_params(
+        inner_verifier_data: &VerifierCircuitData<F, C, D>,
+        t: usize,
+        proof_count: Option<ProofCount>,
+    ) -> TreeCircuitParams {
+        TreeCircuitParams {
+            inner_circuit_digest: inner_verifier_data.verifier_only.circuit_digest
+                .elements
+                .iter()
+                .map(|e| e.to_canonical_u64())
+                .collect(),
+            max_proofs: t,
+            arity: N,
+            proof_count,
+        }
     }
 }
@@ -6,6 +6,7 @@ use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2_field::extension::Extendable;
 use plonky2_poseidon2::Poseidon2;
 use crate::recursion::leaf::BUCKET_SIZE;
+use crate::{error::CircuitError, Result};
 
 /// Splits a target `index` which is known to lie in the range [0, T)
 /// where T = bucket_size * num_buckets
@@ -136,6 +137,66 @@ pub fn compute_flag_buckets<
     Ok(buckets)
 }
 
+/// Computes the number of real inner proofs from the flag buckets (In-Circuit).
+///
+/// Given:
+///   - `flag_buckets` the `ceil(t / BUCKET_SIZE)` flag buckets of a tree proof,
+///   - `t` the maximum number of inner proofs in the tree,
+/// this function returns the number of set flags (popcount) in the first `t` flags.
+/// every bucket is split into `BUCKET_SIZE` bits, so this also checks that each bucket holds only `BUCKET_SIZE` flags.
+pub fn compute_flag_count<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    flag_buckets: &[Target],
+    t: usize,
+) -> crate::Result<Target>
+{
+    if flag_buckets.len() != bucket_count(t) {
+        return Err(CircuitError::FlagsLengthMismatch(bucket_count(t), flag_buckets.len()));
+    }
+
+    let mut count = builder.zero();
+    for (i, bucket) in flag_buckets.iter().enumerate() {
+        let bits = builder.split_le(*bucket, BUCKET_SIZE);
+        for (j, bit) in bits.into_iter().enumerate() {
+            if i * BUCKET_SIZE + j < t {
+                count = builder.add(count, bit.target);
+            } else {
+                // the flags after `t` must not be set
+                builder.assert_zero(bit.target);
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Checks that `count` (e.g. from `compute_flag_count`) is at least `min_count` (In-Circuit).
+/// assumes that `count` is in the range [0, t], so `count - min_count` is in [0, t] iff `count >= min_count`
+/// and it is range-checked with the number of bits of `t`.
+pub fn assert_min_flag_count<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    count: Target,
+    min_count: usize,
+    t: usize,
+) -> crate::Result<()>
+{
+    if min_count > t {
+        return Err(CircuitError::InvalidArgument(format!(
+            "minimum number of proofs {} is more than the maximum number of proofs {}", min_count, t
+        )));
+    }
+    let min_count_t = builder.constant(F::from_canonical_u64(min_count as u64));
+    let diff = builder.sub(count, min_count_t);
+    let n_bits = (usize::BITS - t.leading_zeros()) as usize;
+    builder.range_check(diff, n_bits);
+    Ok(())
+}
+
 /// Returns the number of buckets required to hold `t` flags,
 /// where each bucket can hold up to BUCKET_SIZE flags.
 /// bucket_count = ceil(t / BUCKET_SIZE)
@@ -143,6 +204,63 @@ pub fn bucket_count(t: usize) -> usize {
     (t + BUCKET_SIZE -1) / BUCKET_SIZE
 }
 
+/// decodes natively the flag buckets of a tree (or compressed) proof public input
+/// into the indices of the real inner proofs, in increasing order.
+/// bit `i % BUCKET_SIZE` of bucket `i / BUCKET_SIZE` is set iff the inner proof at leaf `i` is real,
+/// so a bucket with a bit set above `BUCKET_SIZE` or at an index `>= t` is invalid.
+pub fn get_proven_indices<
+    F: RichField,
+>(
+    public_input: &[F],
+    t: usize,
+) -> Result<Vec<usize>> {
+    let n_bucket = bucket_count(t);
+    if public_input.len() < 9 + n_bucket {
+        return Err(CircuitError::PublicInputLengthError(9 + n_bucket, public_input.len()));
+    }
+
+    let mut indices = vec![];
+    for (b, bucket) in public_input[9..(9 + n_bucket)].iter().enumerate() {
+        let bucket = bucket.to_canonical_u64();
+        if bucket >> BUCKET_SIZE != 0 {
+            return Err(CircuitError::InvalidFlagBucket(b, format!(
+                "{} does not fit in {} flags", bucket, BUCKET_SIZE
+            )));
+        }
+        for r in (0..BUCKET_SIZE).filter(|r| (bucket >> r) & 1 == 1) {
+            let index = b * BUCKET_SIZE + r;
+            if index >= t {
+                return Err(CircuitError::InvalidFlagBucket(b, format!(
+                    "flag of index {} is set, the tree aggregates at most {} proofs", index, t
+                )));
+            }
+            indices.push(index);
+        }
+    }
+    Ok(indices)
+}
+
+/// checks natively that the flag buckets of a tree (or compressed) proof public input
+/// have exactly the flags of the `expected` indices set.
+/// returns `ProvenIndicesMismatch` with the expected indices that are not proven and the proven indices that are not expected.
+pub fn verify_proven_indices<
+    F: RichField,
+>(
+    public_input: &[F],
+    t: usize,
+    expected: &[usize],
+) -> Result<()> {
+    let proven = get_proven_indices(public_input, t)?;
+    let mut missing: Vec<usize> = expected.iter().cloned().filter(|i| !proven.contains(i)).collect();
+    missing.sort();
+    missing.dedup();
+    let unexpected: Vec<usize> = proven.into_iter().filter(|i| !expected.contains(i)).collect();
+    if !missing.is_empty() || !unexpected.is_empty() {
+        return Err(CircuitError::ProvenIndicesMismatch(missing, unexpected));
+    }
+    Ok(())
+}
+
 /// helper fn to generate hash of verifier data (outside the circuit)
 pub fn get_hash_of_verifier_data<
     F: RichField + Extendable<D> + Poseidon2,
@@ -151,6 +269,32 @@ pub fn get_hash_of_verifier_data<
     H: AlgebraicHasher<F>,
 >(verifier_data: &VerifierCircuitData<F, C, D>) -> HashOut<F> where
     <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    H::hash_no_pad(&verifier_data_elements(verifier_data))
+}
+
+/// helper fn to generate the verifier data hash of a node that forwards the leaf constant
+/// (see `NodeCircuit::with_leaf_constant`) outside the circuit: the hash of the verifier data and `leaf_constant`
+pub fn get_hash_of_verifier_data_with_leaf_constant<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+>(verifier_data: &VerifierCircuitData<F, C, D>, leaf_constant: HashOut<F>) -> HashOut<F> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    let mut vd = verifier_data_elements(verifier_data);
+    vd.extend_from_slice(&leaf_constant.elements);
+    H::hash_no_pad(&vd)
+}
+
+/// the circuit digest and the constants/sigmas cap of the verifier data as field elements
+fn verifier_data_elements<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+>(verifier_data: &VerifierCircuitData<F, C, D>) -> Vec<F> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
 {
     let mut vd = vec![];
     let digest: &HashOut<F> = &verifier_data.verifier_only.circuit_digest;
@@ -160,8 +304,157 @@ pub fn get_hash_of_verifier_data<
         let cap_hash = caps.0[i] as HashOut<F>;
         vd.extend_from_slice(&cap_hash.elements);
     }
+    vd
+}
 
-    H::hash_no_pad(&vd)
+/// computes natively the pi hash of every proof in a tree with arity `n` and `t` leaves, level by level
+/// from the leaves (level 0) up to the root.
+/// `inner_public_input[i]` is the public input of the inner proof at leaf `i`, or `None` if the leaf is dummy,
+/// the leaves after `inner_public_input.len()` are dummy.
+/// dummy leaves and subtrees with only dummy leaves are `None`, their pi hash is zero.
+pub fn get_pi_hash_levels<
+    F: RichField,
+    H: AlgebraicHasher<F>,
+>(
+    inner_public_input: &[Option<Vec<F>>],
+    t: usize,
+    n: usize,
+) -> Vec<Vec<Option<HashOut<F>>>> {
+    get_batch_pi_hash_levels::<F, H>(inner_public_input, t, 1, n)
 }
 
+/// same as `get_pi_hash_levels` but for a tree with batch leaves (see `BatchLeafCircuit`),
+/// each holding `m` inner proofs: level 0 is the pi hash of the inner proofs, level 1 the pi hash
+/// of the batch leaves, and the levels above are the nodes with arity `n`.
+/// for `m` = 1 this is the same as `get_pi_hash_levels`.
+pub fn get_batch_pi_hash_levels<
+    F: RichField,
+    H: AlgebraicHasher<F>,
+>(
+    inner_public_input: &[Option<Vec<F>>],
+    t: usize,
+    m: usize,
+    n: usize,
+) -> Vec<Vec<Option<HashOut<F>>>> {
+    let mut pub_in_hashes: Vec<Option<HashOut<F>>> = (0..t)
+        .map(|i| inner_public_input.get(i).and_then(|pub_in| pub_in.as_ref()).map(|pub_in| H::hash_no_pad(pub_in)))
+        .collect();
+    let mut levels = vec![];
+
+    // the batch leaves hash the pi hash of their `m` inner proofs
+    if m > 1 {
+        let next_level_pi_hashes = hash_pi_chunks::<F, H>(&pub_in_hashes, m);
+        levels.push(pub_in_hashes);
+        pub_in_hashes = next_level_pi_hashes;
+    }
+
+    while pub_in_hashes.len() > 1 {
+        let next_level_pi_hashes = hash_pi_chunks::<F, H>(&pub_in_hashes, n);
+        levels.push(pub_in_hashes);
+        pub_in_hashes = next_level_pi_hashes;
+    }
+    levels.push(pub_in_hashes);
 
+    levels
+}
+
+/// hashes the pi hashes in chunks of `n`, chunks with only `None` (dummy) are `None`.
+fn hash_pi_chunks<
+    F: RichField,
+    H: AlgebraicHasher<F>,
+>(
+    pub_in_hashes: &[Option<HashOut<F>>],
+    n: usize,
+) -> Vec<Option<HashOut<F>>> {
+    let mut next_level_pi_hashes = Vec::new();
+    for pi_chunk in pub_in_hashes.chunks(n) {
+        if pi_chunk.iter().all(|h| h.is_none()) {
+            next_level_pi_hashes.push(None);
+            continue;
+        }
+        // collect field elements
+        let pi_chunk_f: Vec<F> = pi_chunk.iter()
+            .flat_map(|h| h.unwrap_or(HashOut::<F>::ZERO).elements)
+            .collect();
+        // Compute hash of the concatenated chunk
+        let pi_hash = H::hash_no_pad(&pi_chunk_f);
+        next_level_pi_hashes.push(Some(pi_hash));
+    }
+    next_level_pi_hashes
+}
+
+/// verifies natively the public input of a tree root proof (tree with arity `n` and `t` leaves):
+/// - the pi hash is the root of the pi hash tree of `inner_public_input` (see `get_pi_hash_levels`).
+/// - the verifier data hash is `vd_hash`.
+/// - exactly the flags of the real leaves are set in the flag buckets.
+pub fn verify_tree_public_input<
+    F: RichField,
+    H: AlgebraicHasher<F>,
+>(
+    public_input: &[F],
+    inner_public_input: &[Option<Vec<F>>],
+    vd_hash: HashOut<F>,
+    t: usize,
+    n: usize,
+) -> Result<()> {
+    verify_batch_tree_public_input::<F, H>(public_input, inner_public_input, vd_hash, t, 1, n)
+}
+
+/// same as `verify_tree_public_input` but for a tree with batch leaves of `m` inner proofs each
+/// (see `get_batch_pi_hash_levels`).
+pub fn verify_batch_tree_public_input<
+    F: RichField,
+    H: AlgebraicHasher<F>,
+>(
+    public_input: &[F],
+    inner_public_input: &[Option<Vec<F>>],
+    vd_hash: HashOut<F>,
+    t: usize,
+    m: usize,
+    n: usize,
+) -> Result<()> {
+    let n_bucket = bucket_count(t);
+    if public_input.len() < 9 + n_bucket {
+        return Err(CircuitError::PublicInputLengthError(9 + n_bucket, public_input.len()));
+    }
+    if inner_public_input.len() > t {
+        return Err(CircuitError::RecursionTreeError(format!(
+            "at most {} inner public inputs expected, got {}", t, inner_public_input.len()
+        )));
+    }
+
+    let given_input_hash = &public_input[0..4];
+    let given_vd_hash = &public_input[4..8];
+    let given_flag_buckets = &public_input[9..(9 + n_bucket)];
+
+    let pi_hash_levels = get_batch_pi_hash_levels::<F, H>(inner_public_input, t, m, n);
+
+    //check expected hash
+    let expected_pi_hash = pi_hash_levels[pi_hash_levels.len() - 1][0].unwrap_or(HashOut::<F>::ZERO);
+
+    // the flag of each real leaf is set in the flag buckets
+    let mut expected_flag_buckets = vec![0u64; n_bucket];
+    for i in (0..inner_public_input.len()).filter(|&i| inner_public_input[i].is_some()) {
+        expected_flag_buckets[i / BUCKET_SIZE] |= 1 << (i % BUCKET_SIZE);
+    }
+    let expected_flag_buckets: Vec<F> = expected_flag_buckets.into_iter()
+        .map(F::from_canonical_u64)
+        .collect();
+
+    if given_input_hash != expected_pi_hash.elements {
+        return Err(CircuitError::PublicInputHashMismatch(
+            format!("{:?}", expected_pi_hash.elements), format!("{:?}", given_input_hash)
+        ));
+    }
+    if given_vd_hash != vd_hash.elements {
+        return Err(CircuitError::VerifierDataHashMismatch(
+            format!("{:?}", vd_hash.elements), format!("{:?}", given_vd_hash)
+        ));
+    }
+    if given_flag_buckets != expected_flag_buckets {
+        return Err(CircuitError::FlagBucketsMismatch(
+            format!("{:?}", expected_flag_buckets), format!("{:?}", given_flag_buckets)
+        ));
+    }
+    Ok(())
+}
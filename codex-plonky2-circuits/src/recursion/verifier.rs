@@ -0,0 +1,162 @@
+use std::marker::PhantomData;
+use std::path::Path;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use serde::Serialize;
+use crate::{error::CircuitError, Result};
+use crate::recursion::utils::{get_hash_of_verifier_data, get_proven_indices, verify_proven_indices, verify_tree_public_input};
+use crate::serialization::import_verifier_circuit_data;
+
+/// standalone verifier for the tree (and compressed tree) proofs.
+/// only holds the verifier data of the node and compression circuits, so it can be used
+/// with the exported `verifier_data` directories without building the tree circuits.
+/// - `N`: arity of the tree, must match the tree the proofs come from.
+/// - `t`: maximum number of inner proofs the tree was built for.
+///
+/// if `t = N` the root is a node at level 0 which carries the hash of the leaf verifier data,
+/// so the leaf verifier data must be set with `with_leaf_verifier_data` to check the public input.
+pub struct TreeVerifier<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    node_verifier_data: VerifierCircuitData<F, C, D>,
+    compression_verifier_data: VerifierCircuitData<F, C, D>,
+    leaf_verifier_data: Option<VerifierCircuitData<F, C, D>>,
+    t: usize,
+    phantom_data: PhantomData<H>
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    C: GenericConfig<D, F = F>,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> TreeVerifier<F, D, C, H, N> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    pub fn new(
+        node_verifier_data: VerifierCircuitData<F, C, D>,
+        compression_verifier_data: VerifierCircuitData<F, C, D>,
+        t: usize,
+    ) -> Self {
+        Self {
+            node_verifier_data,
+            compression_verifier_data,
+            leaf_verifier_data: None,
+            t,
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// sets the leaf verifier data, only needed if `t = N`
+    pub fn with_leaf_verifier_data(mut self, leaf_verifier_data: VerifierCircuitData<F, C, D>) -> Self {
+        self.leaf_verifier_data = Some(leaf_verifier_data);
+        self
+    }
+
+    /// returns the maximum number of inner proofs `t`
+    pub fn max_proofs(&self) -> usize {
+        self.t
+    }
+
+    /// verifies the tree proof (or the compressed proof if `is_compressed`)
+    pub fn verify_proof(
+        &self,
+        proof: ProofWithPublicInputs<F, C, D>,
+        is_compressed: bool,
+    ) -> Result<()> {
+        let verifier_data = if is_compressed {
+            &self.compression_verifier_data
+        } else {
+            &self.node_verifier_data
+        };
+        verifier_data.verify(proof)
+            .map_err(|e| CircuitError::InvalidProofError(e.to_string()))
+    }
+
+    /// verifies the tree proof and its public input against the public input of the inner proofs.
+    /// `inner_public_input` holds the public input of the real inner proofs at leaf index 0..len,
+    /// the remaining leaves (up to `t`) are dummy.
+    pub fn verify_proof_and_public_input(
+        &self,
+        proof: ProofWithPublicInputs<F, C, D>,
+        inner_public_input: Vec<Vec<F>>,
+        is_compressed: bool,
+    ) -> Result<()> {
+        let public_input = proof.public_inputs.clone();
+        self.verify_proof(proof, is_compressed)?;
+        self.verify_public_input(public_input, inner_public_input)
+    }
+
+    /// same as `TreeRecursion::verify_public_input`
+    pub fn verify_public_input(
+        &self,
+        public_input: Vec<F>,
+        inner_public_input: Vec<Vec<F>>,
+    ) -> Result<()> {
+        let inner_public_input = inner_public_input.into_iter().map(Some).collect();
+        self.verify_public_input_with_dummies(public_input, inner_public_input)
+    }
+
+    /// same as `TreeRecursion::verify_public_input_with_dummies`
+    pub fn verify_public_input_with_dummies(
+        &self,
+        public_input: Vec<F>,
+        inner_public_input: Vec<Option<Vec<F>>>,
+    ) -> Result<()> {
+        let vd_hash = if self.t == N {
+            let leaf_verifier_data = self.leaf_verifier_data.as_ref().ok_or(CircuitError::InvalidArgument(
+                "the leaf verifier data is required to verify a tree with t = N".to_string()
+            ))?;
+            get_hash_of_verifier_data::<F,D,C,H>(leaf_verifier_data)
+        } else {
+            get_hash_of_verifier_data::<F,D,C,H>(&self.node_verifier_data)
+        };
+
+        verify_tree_public_input::<F, H>(&public_input, &inner_public_input, vd_hash, self.t, N)
+    }
+
+    /// same as `TreeRecursion::get_proven_indices`
+    pub fn get_proven_indices(&self, public_input: &[F]) -> Result<Vec<usize>> {
+        get_proven_indices(public_input, self.t)
+    }
+
+    /// same as `TreeRecursion::verify_proven_indices`
+    pub fn verify_proven_indices(&self, public_input: &[F], expected: &[usize]) -> Result<()> {
+        verify_proven_indices(public_input, self.t, expected)
+    }
+}
+
+impl<
+    F: RichField + Extendable<D> + Poseidon2 + Serialize,
+    const D: usize,
+    C: GenericConfig<D, F = F> + Serialize,
+    H: AlgebraicHasher<F>,
+    const N: usize,
+> TreeVerifier<F, D, C, H, N> where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>
+{
+    /// loads the node and compression verifier data exported with `export_verifier_circuit_data`
+    /// (`{path}/verifier_data/verifier_circuit_data.bin`), no prover data is read.
+    pub fn load<P: AsRef<Path>>(
+        node_base_path: P,
+        compression_base_path: P,
+        t: usize,
+    ) -> Result<Self> {
+        let node_verifier_data = import_verifier_circuit_data::<F, C, D, _>(node_base_path)
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        let compression_verifier_data = import_verifier_circuit_data::<F, C, D, _>(compression_base_path)
+            .map_err(|e| CircuitError::SerializationError(e.to_string()))?;
+        Ok(Self::new(node_verifier_data, compression_verifier_data, t))
+    }
+}
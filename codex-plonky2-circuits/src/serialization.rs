@@ -19,6 +19,9 @@ pub const TARGETS_JSON: &str = "prover_data/targets.json";
 pub const VERIFIER_CIRC_DATA_JSON: &str = "verifier_data/verifier_circuit_data.bin";
 pub const PROOF_JSON: &str = "verifier_data/proof_with_public_inputs.json";
 
+/// File constants paths - full circuit data (prover and verifier)
+pub const CIRC_DATA_BIN: &str = "prover_data/circuit_data.bin";
+
 // --------------------- helper fn --------------------------
 
 /// Writes the provided bytes to the specified file path using `std::fs::write`.
@@ -167,6 +170,49 @@ pub fn export_circuit_data<
     Ok(())
 }
 
+/// Export the full `CircuitData` by reference to `{base_path}/prover_data/circuit_data.bin`,
+/// together with the verifier data and the targets (same layout as `export_circuit_data`).
+/// unlike `export_circuit_data`, the circuit data can still be used after exporting.
+pub fn export_full_circuit_data<
+    F: RichField + Extendable<D> + Poseidon2 + Serialize,
+    C: GenericConfig<D, F = F> + Default + Serialize + 'static,
+    const D: usize,
+    P: AsRef<Path>,
+>(
+    circ_data: &CircuitData<F, C, D>,
+    targets: &impl Serialize,
+    base_path: P,
+) -> anyhow::Result<()>
+    where
+        <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+{
+    let gate_serializer = DefaultGateSerializer;
+    let generator_serializer = DefaultGeneratorSerializer::<C, D>::default();
+
+    // Serialize circ_data → Vec<u8>
+    let bytes = circ_data
+        .to_bytes(&gate_serializer, &generator_serializer)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize circuit data: {:?}", e))?;
+
+    // Build output path: `{base_path}/prover_data/circuit_data.bin`
+    let out_path = base_path.as_ref().join(CIRC_DATA_BIN);
+
+    // Ensure parent directory exists
+    ensure_parent_directory_exists(&out_path)
+        .with_context(|| format!("Could not create directory for {:?}", out_path))?;
+
+    // Write file
+    write_bytes_to_file(&bytes, &out_path)
+        .with_context(|| format!("Failed to write circuit data to {:?}", out_path))?;
+
+    export_verifier_circuit_data(circ_data.verifier_data(), &base_path)
+        .context("export_verifier_circuit_data failed")?;
+    export_circuit_targets(targets, &base_path)
+        .context("export_circuit_targets failed")?;
+
+    Ok(())
+}
+
 /// Serialize `proof_with_pis` into JSON and write it under a base directory
 pub fn export_proof_with_pi<F, C, const D: usize, P: AsRef<Path>>(
     proof_with_pis: &ProofWithPublicInputs<F, C, D>,
@@ -212,6 +258,32 @@ pub fn import_circuit_data<F, C, const D: usize, P: AsRef<Path> + Clone>(
     })
 }
 
+/// Import the full `CircuitData<F, C, D>` written by `export_full_circuit_data` under `base_path`.
+pub fn import_full_circuit_data<F, C, const D: usize, P: AsRef<Path>>(
+    base_path: P,
+) -> anyhow::Result<CircuitData<F, C, D>>
+    where
+        F: RichField + Extendable<D> + Poseidon2 + Serialize,
+        C: GenericConfig<D, F = F> + Default + Serialize + 'static,
+        <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+{
+    let gate_serializer = DefaultGateSerializer;
+    let generator_serializer = DefaultGeneratorSerializer::<C, D>::default();
+
+    let full_path = base_path.as_ref().join(CIRC_DATA_BIN);
+    let bytes = read_bytes_from_file(&full_path)
+        .with_context(|| format!("Failed to read circuit data from {:?}", full_path))?;
+
+    let circ_data = CircuitData::<F, C, D>::from_bytes(
+        &bytes,
+        &gate_serializer,
+        &generator_serializer,
+    )
+        .map_err(|e| anyhow::anyhow!("Failed to deserialize circuit data from {:?}: {:?}", full_path, e))?;
+
+    Ok(circ_data)
+}
+
 /// Import `ProverCircuitData<F, C, D>` from disk under the given `base_path`.
 pub fn import_prover_circuit_data<F, C, const D: usize, P: AsRef<Path>>(
     base_path: P,
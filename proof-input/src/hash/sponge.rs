@@ -191,8 +191,8 @@ pub fn hash_bytes_with_padding<
     let zero = F::ZERO;
     let mut perm = P::new(core::iter::repeat(zero).take(width));
 
-    // Set the domain separator at index 8
-    let domsep_value = F::from_canonical_u64(rate as u64 + 256 * 12 + 65536 * 63);
+    // Set the domain separator at index 8, the inputs are 8-bit bytes
+    let domsep_value = F::from_canonical_u64(rate as u64 + 256 * 12 + 65536 * 8);
     perm.set_elt(domsep_value, 8);
 
     let byte_rate = 62;
@@ -289,4 +289,18 @@ pub fn convert_bytes_to_field_rate8<F: RichField + Extendable<D> + Poseidon2, co
     felts[0..4].copy_from_slice(&a_felts);
     felts[4..8].copy_from_slice(&b_felts);
     felts
-}
\ No newline at end of file
+}
+
+/// pads the bytes with 10* padding to a multiple of 62 bytes and packs each 62 bytes
+/// into 8 field elements, same as the absorbed chunks of `hash_bytes_with_padding`
+pub fn pack_bytes_with_padding<F: RichField + Extendable<D> + Poseidon2, const D: usize>(bytes: &[u8]) -> Vec<F> {
+    let byte_rate = 62;
+    let num_chunks = (bytes.len() + byte_rate) / byte_rate;
+    let mut padded = bytes.to_vec();
+    padded.push(1u8);
+    padded.resize(num_chunks * byte_rate, 0u8);
+    padded
+        .chunks(byte_rate)
+        .flat_map(convert_bytes_to_field_rate8::<F, D>)
+        .collect()
+}
@@ -1,5 +1,6 @@
 // Data structure used to generate the proof input
 
+use anyhow::{anyhow, ensure};
 use plonky2::hash::hash_types::{HashOut, RichField};
 use plonky2::plonk::config::Hasher;
 use plonky2_field::extension::Extendable;
@@ -7,7 +8,7 @@ use codex_plonky2_circuits::circuits::sample_cells::Cell;
 use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
 use crate::merkle_tree::merkle_safe::{MerkleProof, MerkleTree};
 use crate::params::InputParams;
-use crate::hash::sponge::hash_n_no_padding;
+use crate::hash::sponge::{hash_n_no_padding, pack_bytes_with_padding};
 use crate::input_generator::utils::{bits_le_padded_to_usize, calculate_cell_index_bits, usize_to_bits_le};
 
 // ----------------- slot tree -----------------
@@ -19,7 +20,7 @@ pub struct SlotTree<
 > {
     pub tree: MerkleTree<F, D, H>,         // slot tree
     pub block_trees: Vec<MerkleTree<F,D, H>>, // vec of block trees
-    pub cell_data: Vec<Cell<F, D>>,  // cell data as field elements
+    pub cell_data: Vec<Cell<F, D>>,  // cell data as field elements, raw bytes with `cells_from_bytes`
     pub params: InputParams,              // parameters
 }
 
@@ -29,7 +30,7 @@ impl<
     H: Hasher<F>,
 > SlotTree<F, D, H> {
     /// Create a slot tree with fake data, for testing only
-    pub fn new_default(params: &InputParams) -> Self {
+    pub fn new_default(params: &InputParams) -> anyhow::Result<Self> {
         // generate fake cell data
         let cell_data = (0..params.n_cells)
             .map(|_| new_random_cell(params))
@@ -37,12 +38,13 @@ impl<
         Self::new(cell_data, params.clone())
     }
 
-    /// Create a new slot tree with the supplied cell data and parameters
-    pub fn new(cells: Vec<Cell<F, D>>, params: InputParams) -> Self {
+    /// Create a new slot tree with the supplied cell data and parameters,
+    /// fails if a cell doesn't match the cell layout in `params`
+    pub fn new(cells: Vec<Cell<F, D>>, params: InputParams) -> anyhow::Result<Self> {
         let leaves: Vec<HashOut<F>> = cells
             .iter()
-            .map(|element| hash_n_no_padding::<F,D,H>(&element.data))
-            .collect();
+            .map(|element| Ok(hash_n_no_padding::<F,D,H>(&cell_field_elems(element, &params)?)))
+            .collect::<anyhow::Result<_>>()?;
 
         let n_blocks = params.n_blocks_test();
         let n_cells_in_blocks = params.n_cells_in_blocks();
@@ -56,15 +58,15 @@ impl<
             .collect::<Vec<_>>();
         let block_roots = block_trees
             .iter()
-            .map(|t| t.root().unwrap())
-            .collect::<Vec<_>>();
-        let slot_tree = MerkleTree::<F,D, H>::new(&block_roots).unwrap();
-        Self {
+            .map(|t| t.root())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let slot_tree = MerkleTree::<F,D, H>::new(&block_roots)?;
+        Ok(Self {
             tree: slot_tree,
             block_trees,
             cell_data: cells,
             params,
-        }
+        })
     }
 
     /// Generates a proof for the given leaf index
@@ -126,17 +128,22 @@ impl<
     H: Hasher<F>,
 > DatasetTree<F, D, H> {
     /// Dataset tree with fake data, for testing only
-    pub fn new_default(params: &InputParams) -> Self {
+    pub fn new_default(params: &InputParams) -> anyhow::Result<Self> {
         let mut slot_trees = vec![];
         let n_slots = 1 << params.dataset_depth_test();
         for _ in 0..n_slots {
-            slot_trees.push(SlotTree::<F, D, H>::new_default(params));
+            slot_trees.push(SlotTree::<F, D, H>::new_default(params)?);
         }
-        Self::new(slot_trees, params.clone())
+        Ok(Self::new(slot_trees, params.clone()))
     }
 
     /// Create data for only the specified slot index in params
-    pub fn new_for_testing(params: &InputParams) -> Self {
+    pub fn new_for_testing(params: &InputParams) -> anyhow::Result<Self> {
+        Self::new_for_testing_slots(params, &[params.testing_slot_index])
+    }
+
+    /// Create data for only the given slot indices, the other slots are zero
+    pub fn new_for_testing_slots(params: &InputParams, slot_indices: &[usize]) -> anyhow::Result<Self> {
         let mut slot_trees = vec![];
         // let n_slots = 1 << params.dataset_depth();
         let n_slots = params.n_slots;
@@ -145,14 +152,14 @@ impl<
             elements: [F::ZERO; 4],
         };
         let zero_slot = SlotTree::<F, D, H> {
-            tree: MerkleTree::<F,D,H>::new(&[zero.clone()]).unwrap(),
+            tree: MerkleTree::<F,D,H>::new(&[zero.clone()])?,
             block_trees: vec![],
             cell_data: vec![],
             params: params.clone(),
         };
         for i in 0..n_slots {
-            if i == params.testing_slot_index {
-                slot_trees.push(SlotTree::<F, D, H>::new_default(params));
+            if slot_indices.contains(&i) {
+                slot_trees.push(SlotTree::<F, D, H>::new_default(params)?);
             } else {
                 slot_trees.push(zero_slot.clone());
             }
@@ -160,14 +167,14 @@ impl<
         // get the roots of slot trees
         let slot_roots = slot_trees
             .iter()
-            .map(|t| t.tree.root().unwrap())
-            .collect::<Vec<_>>();
-        let dataset_tree = MerkleTree::<F,D,H>::new(&slot_roots).unwrap();
-        Self {
+            .map(|t| t.tree.root())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let dataset_tree = MerkleTree::<F,D,H>::new(&slot_roots)?;
+        Ok(Self {
             tree: dataset_tree,
             slot_trees,
             params: params.clone(),
-        }
+        })
     }
 
     /// Same as default but with supplied slot trees
@@ -187,9 +194,9 @@ impl<
     }
 
     /// Generates a proof for the given slot index
-    /// Also takes entropy so it can use it to sample the slot
+    /// Also takes the entropy digest so it can use it to sample the slot
     /// note: proofs are padded based on the params in self
-    pub fn sample_slot(&self, index: usize, entropy: usize) -> DatasetProof<F,D,H> {
+    pub fn sample_slot(&self, index: usize, entropy: HashOut<F>) -> DatasetProof<F,D,H> {
         let mut dataset_proof = self.tree.get_proof(index).unwrap();
         Self::pad_proof(&mut dataset_proof, self.params.dataset_max_depth());
 
@@ -197,15 +204,12 @@ impl<
         let slot_root = slot.tree.root().unwrap();
         let mut slot_proofs = vec![];
         let mut cell_data = vec![];
-        let entropy_field = F::from_canonical_u64(entropy as u64);
-        let mut entropy_as_digest = HashOut::<F>::ZERO;
-        entropy_as_digest.elements[0] = entropy_field;
 
         // get the index for cell from H(slot_root|counter|entropy)
         let mask_bits = usize_to_bits_le(self.params.n_cells-1, self.params.max_depth+1);
         for i in 0..self.params.n_samples {
             let cell_index_bits = calculate_cell_index_bits::<F,D,H>(
-                &entropy_as_digest.elements.to_vec(),
+                &entropy.elements.to_vec(),
                 slot_root,
                 i + 1,
                 self.params.max_depth,
@@ -224,7 +228,7 @@ impl<
 
         DatasetProof {
             slot_index: F::from_canonical_u64(index as u64),
-            entropy: entropy_as_digest,
+            entropy,
             dataset_proof,
             slot_proofs,
             cell_data,
@@ -241,14 +245,41 @@ impl<
 // ------------ helper functions -------------
 
 /// Create a new cell with random data, using the parameters from `Params`
+/// with `cells_from_bytes` the cell data are `cell_size` random bytes
 pub fn new_random_cell<
     F: RichField + Extendable<D> + Poseidon2,
     const D: usize,
 >(params: &InputParams) -> Cell<F,D> {
-    let data = (0..params.n_field_elems_per_cell())
-        .map(|_| F::rand())
-        .collect::<Vec<_>>();
+    let data = if params.cells_from_bytes {
+        (0..params.cell_size)
+            .map(|_| F::from_canonical_u64(F::rand().to_canonical_u64() & 0xff))
+            .collect::<Vec<_>>()
+    } else {
+        (0..params.n_field_elems_per_cell())
+            .map(|_| F::rand())
+            .collect::<Vec<_>>()
+    };
     Cell::<F,D> {
         data,
     }
 }
+
+/// the field elements of the cell that are hashed into the leaf,
+/// with `cells_from_bytes` the raw bytes packed with `pack_bytes_with_padding`
+pub fn cell_field_elems<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+>(cell: &Cell<F,D>, params: &InputParams) -> anyhow::Result<Vec<F>> {
+    if !params.cells_from_bytes {
+        return Ok(cell.data.clone());
+    }
+    ensure!(
+        cell.data.len() == params.cell_size,
+        "expected a cell of {} bytes, got {}", params.cell_size, cell.data.len()
+    );
+    let bytes = cell.data
+        .iter()
+        .map(|b| u8::try_from(b.to_canonical_u64()).map_err(|_| anyhow!("cell data element {} is not a byte", b)))
+        .collect::<anyhow::Result<Vec<u8>>>()?;
+    Ok(pack_bytes_with_padding::<F, D>(&bytes))
+}
@@ -0,0 +1,74 @@
+// entropy (external randomness, e.g. a block hash) as raw bytes and its encoding into a digest
+//
+// encoding of the 32 raw bytes into the 4 Goldilocks elements of the entropy digest:
+//   element i = u64::from_le_bytes(bytes[8*i..8*i+8]) mod p,  for i in 0..4
+// i.e. the bytes are read as four 64-bit little-endian limbs, each reduced modulo the Goldilocks prime.
+// a hex string is read in byte order (the first two hex digits are bytes[0]), as a block hash is printed.
+// a small decimal entropy n is the bytes of n as u64 little-endian followed by zeros,
+// so its digest is [n, 0, 0, 0].
+//
+// optionally the digest is derived from the raw bytes with the bytes sponge instead (see `hash_bytes`),
+// the sampling circuit does the same derivation in-circuit with `entropy_from_bytes`.
+
+use anyhow::{anyhow, ensure, Context};
+use plonky2::hash::hash_types::{HashOut, RichField};
+use plonky2::plonk::config::Hasher;
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use codex_plonky2_circuits::circuits::sample_cells::ENTROPY_BYTES;
+use crate::hash::sponge::hash_bytes;
+
+/// the raw entropy bytes of a small integer: its u64 little-endian bytes followed by zeros
+pub fn entropy_bytes_from_u64(entropy: u64) -> [u8; ENTROPY_BYTES] {
+    let mut bytes = [0u8; ENTROPY_BYTES];
+    bytes[0..8].copy_from_slice(&entropy.to_le_bytes());
+    bytes
+}
+
+/// parses the raw entropy bytes from a hex string of 32 bytes (with or without `0x`)
+/// or from a decimal integer (see `entropy_bytes_from_u64`)
+pub fn parse_entropy(s: &str) -> anyhow::Result<[u8; ENTROPY_BYTES]> {
+    let s = s.trim();
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"));
+    if hex.is_none() && s.len() != 2 * ENTROPY_BYTES {
+        let n = s.parse::<u64>().context("entropy must be a decimal integer or 32 bytes in hex")?;
+        return Ok(entropy_bytes_from_u64(n));
+    }
+    let hex = hex.unwrap_or(s);
+    ensure!(
+        hex.len() == 2 * ENTROPY_BYTES,
+        "entropy in hex must be {} bytes, got {} hex digits", ENTROPY_BYTES, hex.len()
+    );
+    let mut bytes = [0u8; ENTROPY_BYTES];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .map_err(|e| anyhow!("invalid entropy hex digit at byte {}: {}", i, e))?;
+    }
+    Ok(bytes)
+}
+
+/// the raw entropy bytes as a `0x` prefixed hex string, in byte order
+pub fn entropy_to_hex(bytes: &[u8; ENTROPY_BYTES]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", hex)
+}
+
+/// encodes the raw entropy bytes into the entropy digest:
+/// four 64-bit little-endian limbs, each reduced modulo the field order
+pub fn entropy_to_digest<F: RichField>(bytes: &[u8; ENTROPY_BYTES]) -> HashOut<F> {
+    let mut digest = HashOut::<F>::ZERO;
+    for (e, limb) in digest.elements.iter_mut().zip(bytes.chunks_exact(8)) {
+        *e = F::from_noncanonical_u64(u64::from_le_bytes(limb.try_into().unwrap()));
+    }
+    digest
+}
+
+/// derives the entropy digest from the raw entropy bytes with the bytes sponge,
+/// same as the in-circuit derivation with `entropy_from_bytes`
+pub fn derive_entropy_digest<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: Hasher<F>,
+>(bytes: &[u8; ENTROPY_BYTES]) -> HashOut<F> {
+    hash_bytes::<F, D, H>(bytes)
+}
@@ -1,14 +1,17 @@
 use std::marker::PhantomData;
 use std::path::Path;
-use plonky2::hash::hash_types::RichField;
+use plonky2::hash::hash_types::{HashOut, RichField};
 use plonky2_field::extension::Extendable;
 use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
 use crate::params::{Params,InputParams};
 use crate::input_generator::utils::{bits_le_padded_to_usize, calculate_cell_index_bits, ceiling_log2, usize_to_bits_le};
 use crate::merkle_tree::merkle_safe::MerkleProof;
-use codex_plonky2_circuits::circuits::sample_cells::{MerklePath, SampleCircuitInput};
+use anyhow::ensure;
+use codex_plonky2_circuits::circuits::sample_cells::{MerklePath, SampleCircuitInput, ENTROPY_BYTES};
+use codex_plonky2_circuits::circuits::multi_slot_sample_cells::{MultiSlotSampleCircuitInput, SlotSampleInput};
 use plonky2::plonk::config::Hasher;
-use crate::input_generator::data_structs::DatasetTree;
+use crate::input_generator::data_structs::{cell_field_elems, DatasetTree};
+use crate::input_generator::entropy::{derive_entropy_digest, entropy_to_digest};
 use crate::input_generator::serialization::export_circ_input_to_json;
 use crate::hash::sponge::hash_n_no_padding;
 
@@ -72,13 +75,25 @@ impl<
         todo!()
     }
 
+    /// the entropy digest from the raw entropy bytes in the params (see `input_generator::entropy`),
+    /// with `entropy_from_bytes` it is derived with the bytes sponge and the raw bytes are returned for the circuit input.
+    fn entropy_digest(&self) -> (HashOut<F>, Option<[u8; ENTROPY_BYTES]>) {
+        let bytes = &self.input_params.entropy;
+        if self.input_params.entropy_from_bytes {
+            (derive_entropy_digest::<F, D, H>(bytes), Some(*bytes))
+        } else {
+            (entropy_to_digest(bytes), None)
+        }
+    }
+
     /// generates circuit input (SampleCircuitInput) from fake data for testing
     pub fn gen_testing_circuit_input(&self) -> SampleCircuitInput<F,D>{
         let params = &self.input_params;
-        let dataset_t = DatasetTree::<F, D, H>::new_for_testing(params);
+        let dataset_t = DatasetTree::<F, D, H>::new_for_testing(params)
+            .expect("the testing params must match the generated cells");
 
         let slot_index = params.testing_slot_index; // samples the specified slot
-        let entropy = params.entropy; // Use the entropy from Params
+        let (entropy, entropy_bytes) = self.entropy_digest();
 
         let proof = dataset_t.sample_slot(slot_index, entropy);
         let slot_root = dataset_t.slot_trees[slot_index].tree.root().unwrap();
@@ -94,6 +109,7 @@ impl<
 
         SampleCircuitInput::<F, D> {
             entropy: proof.entropy,
+            entropy_bytes,
             dataset_root: dataset_t.tree.root().unwrap(),
             slot_index: proof.slot_index.clone(),
             slot_root,
@@ -105,12 +121,83 @@ impl<
         }
     }
 
+    /// generates circuit input (MultiSlotSampleCircuitInput) from fake data for testing,
+    /// the slots at `slot_indices` (strictly increasing) are sampled from the same dataset with the same entropy.
+    pub fn gen_testing_multi_slot_circuit_input(&self, slot_indices: &[usize]) -> anyhow::Result<MultiSlotSampleCircuitInput<F,D>>{
+        let params = &self.input_params;
+        ensure!(!slot_indices.is_empty(), "at least one slot index is expected");
+        ensure!(
+            slot_indices.windows(2).all(|w| w[0] < w[1]),
+            "slot indices must be strictly increasing, got {:?}", slot_indices
+        );
+        ensure!(
+            slot_indices.iter().all(|&i| i < params.n_slots),
+            "slot indices must be less than the number of slots {}, got {:?}", params.n_slots, slot_indices
+        );
+        let dataset_t = DatasetTree::<F, D, H>::new_for_testing_slots(params, slot_indices)?;
+        let (entropy, entropy_bytes) = self.entropy_digest();
+
+        let mut slots = vec![];
+        for &slot_index in slot_indices {
+            let proof = dataset_t.sample_slot(slot_index, entropy);
+            let slot_root = dataset_t.slot_trees[slot_index].tree.root()?;
+
+            let merkle_paths = proof.slot_proofs
+                .iter()
+                .map(|p| MerklePath::<F,D>{ path: p.path.clone() })
+                .collect();
+
+            slots.push(SlotSampleInput::<F, D> {
+                slot_index: proof.slot_index,
+                slot_root,
+                slot_proof: proof.dataset_proof.path.clone(),
+                cell_data: proof.cell_data.clone(),
+                merkle_paths,
+            });
+        }
+
+        Ok(MultiSlotSampleCircuitInput::<F, D> {
+            entropy,
+            entropy_bytes,
+            dataset_root: dataset_t.tree.root()?,
+            n_cells_per_slot: F::from_canonical_usize(params.n_cells),
+            n_slots_per_dataset: F::from_canonical_usize(params.n_slots),
+            slots,
+        })
+    }
+
+    /// verifies the given multi slot circuit input, i.e. the input of each slot (see `verify_circuit_input`).
+    /// this is non circuit version for sanity check
+    pub fn verify_multi_slot_circuit_input(&self, circ_input: &MultiSlotSampleCircuitInput<F,D>) -> anyhow::Result<bool>{
+        for i in 0..circ_input.slots.len() {
+            if !self.verify_circuit_input(circ_input.slot_input(i)?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     /// verifies the given circuit input.
     /// this is non circuit version for sanity check
     pub fn verify_circuit_input<
     >(&self, circ_input: SampleCircuitInput<F,D>) -> bool{
         let params = self.input_params.clone();
+        // with raw entropy bytes, the entropy must be derived from them
+        if let Some(bytes) = &circ_input.entropy_bytes {
+            if derive_entropy_digest::<F, D, H>(bytes) != circ_input.entropy {
+                return false;
+            }
+        }
         let slot_index = circ_input.slot_index.to_canonical_u64();
+        let n_slots_per_dataset = circ_input.n_slots_per_dataset.to_canonical_u64();
+        let n_cells_per_slot = circ_input.n_cells_per_slot.to_canonical_u64();
+        // same bounds as the circuit: slot_index < n_slots_per_dataset <= max_slots and 1 <= n_cells_per_slot <= 2^max_depth
+        if slot_index >= n_slots_per_dataset
+            || n_slots_per_dataset > params.max_slots as u64
+            || n_cells_per_slot == 0
+            || n_cells_per_slot > 1u64 << params.max_depth {
+            return false;
+        }
         let slot_root = circ_input.slot_root.clone();
         // check dataset level proof
         let slot_proof = circ_input.slot_proof.clone();
@@ -164,7 +251,11 @@ impl<
         let slot_path_bits = block_path_bits.split_off(split_point);
 
         // pub type HP = <PoseidonHash as Hasher<F>>::Permutation;
-        let leaf_hash = hash_n_no_padding::<F,D,H>(&circ_input.cell_data[ctr].data);
+        // with raw cell bytes, the bytes must pack into the leaf elements
+        let Ok(leaf_elems) = cell_field_elems(&circ_input.cell_data[ctr], &params) else {
+            return Ok(false);
+        };
+        let leaf_hash = hash_n_no_padding::<F,D,H>(&leaf_elems);
 
         let mut block_path = circ_input.merkle_paths[ctr].path.clone();
         let slot_path = block_path.split_off(split_point);
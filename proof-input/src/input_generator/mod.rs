@@ -2,5 +2,6 @@ pub mod gen_input;
 pub mod utils;
 pub mod data_structs;
 pub mod serialization;
+pub mod entropy;
 
 pub use gen_input::InputGenerator;
\ No newline at end of file
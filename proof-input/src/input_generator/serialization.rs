@@ -8,6 +8,7 @@ use std::fs::File;
 use std::io::{BufReader, Write};
 use std::path::Path;
 use codex_plonky2_circuits::serialization::ensure_parent_directory_exists;
+use crate::input_generator::entropy::{entropy_to_digest, entropy_to_hex, parse_entropy};
 
 pub const CIRC_INPUT_JSON: &str = "prover_data/input.json";
 
@@ -18,7 +19,9 @@ pub const CIRC_INPUT_JSON: &str = "prover_data/input.json";
 struct SerializableCircuitInput<
 > {
     dataSetRoot: Vec<String>,
-    entropy: Vec<String>,
+    entropy: SerializableEntropy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entropyBytes: Option<String>,
     nCellsPerSlot: usize,
     nSlotsPerDataSet: usize,
     slotIndex: u64,
@@ -28,6 +31,15 @@ struct SerializableCircuitInput<
     merklePaths: Vec<Vec<String>>,
 }
 
+/// the entropy in the json input, either the 4 field elements of the entropy digest as decimal strings
+/// or the raw entropy as one string of 32 bytes in hex (or a decimal integer), encoded with `entropy_to_digest`
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerializableEntropy {
+    Elements(Vec<String>),
+    Raw(String),
+}
+
 impl<
 > SerializableCircuitInput {
     /// from the circuit input to serializable circuit input
@@ -42,12 +54,15 @@ impl<
                 .iter()
                 .map(|e| e.to_canonical_u64().to_string())
                 .collect(),
-            entropy: circ_input
-                .entropy
-                .elements
-                .iter()
-                .map(|e| e.to_canonical_u64().to_string())
-                .collect(),
+            entropy: SerializableEntropy::Elements(
+                circ_input
+                    .entropy
+                    .elements
+                    .iter()
+                    .map(|e| e.to_canonical_u64().to_string())
+                    .collect(),
+            ),
+            entropyBytes: circ_input.entropy_bytes.as_ref().map(entropy_to_hex),
             nCellsPerSlot: circ_input.n_cells_per_slot.to_canonical_u64() as usize,
             nSlotsPerDataSet: circ_input.n_slots_per_dataset.to_canonical_u64() as usize,
             slotIndex: circ_input.slot_index.to_canonical_u64(),
@@ -94,19 +109,28 @@ impl<> SerializableCircuitInput {
         const D: usize
     >(&self) -> anyhow::Result<SampleCircuitInput<F, D>> {
         // Convert entropy
-        let entropy_elements = self
-            .entropy
-            .iter()
-            .map(|s| -> anyhow::Result<F, Error> {
-                let n = s.parse::<u64>()?;
-                Ok(F::from_canonical_u64(n))
-            })
-            .collect::<anyhow::Result<Vec<F>, Error>>()?;
-        let entropy = HashOut {
-            elements: entropy_elements
-                .try_into()
-                .map_err(|_| anyhow!("Invalid entropy length"))?,
+        let entropy = match &self.entropy {
+            SerializableEntropy::Elements(elements) => {
+                let entropy_elements = elements
+                    .iter()
+                    .map(|s| -> anyhow::Result<F, Error> {
+                        let n = s.parse::<u64>()?;
+                        Ok(F::from_canonical_u64(n))
+                    })
+                    .collect::<anyhow::Result<Vec<F>, Error>>()?;
+                HashOut {
+                    elements: entropy_elements
+                        .try_into()
+                        .map_err(|_| anyhow!("Invalid entropy length"))?,
+                }
+            }
+            SerializableEntropy::Raw(s) => entropy_to_digest(&parse_entropy(s)?),
         };
+        let entropy_bytes = self
+            .entropyBytes
+            .as_deref()
+            .map(parse_entropy)
+            .transpose()?;
 
         // Convert dataset_root
         let dataset_root_elements = self
@@ -223,6 +247,7 @@ impl<> SerializableCircuitInput {
 
         Ok(SampleCircuitInput {
             entropy,
+            entropy_bytes,
             dataset_root,
             slot_index,
             slot_root,
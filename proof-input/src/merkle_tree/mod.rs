@@ -1,2 +1,3 @@
 pub mod merkle_safe;
 pub mod merkle_circuit;
+pub mod pi_commitment;
@@ -0,0 +1,119 @@
+// Native helpers for the Merkle commitment to the inner public inputs of a tree proof
+// see `codex_plonky2_circuits::recursion::pi_commitment::PublicInputCommitmentCircuit`
+
+use anyhow::{ensure, Result};
+use plonky2::hash::hash_types::{HashOut, RichField};
+use plonky2::plonk::config::AlgebraicHasher;
+use plonky2_field::extension::Extendable;
+use plonky2_poseidon2::poseidon2_hash::poseidon2::Poseidon2;
+use crate::merkle_tree::merkle_safe::{MerkleProof, MerkleTree, zero};
+
+/// Builds the Merkle tree over the pi hashes of the `t` inner proofs of a tree.
+/// `inner_public_input[i]` is the public input of the inner proof at leaf `i`, or `None` if the leaf is dummy,
+/// the leaves after `inner_public_input.len()` are dummy. the leaf of a dummy proof is the zero hash.
+pub fn public_input_merkle_tree<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>,
+>(
+    inner_public_input: &[Option<Vec<F>>],
+    t: usize,
+) -> Result<MerkleTree<F, D, H>> {
+    ensure!(
+        inner_public_input.len() <= t,
+        "at most {} inner public inputs expected, got {}", t, inner_public_input.len()
+    );
+    let leaves: Vec<HashOut<F>> = (0..t)
+        .map(|i| match inner_public_input.get(i) {
+            Some(Some(pub_in)) => H::hash_no_pad(pub_in),
+            _ => zero::<F, D>(),
+        })
+        .collect();
+    MerkleTree::<F, D, H>::new(&leaves)
+}
+
+/// Generates the opening proof of the inner proof at leaf `index`
+/// against the Merkle root in the public input of the commitment proof.
+pub fn get_public_input_opening<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>,
+>(
+    inner_public_input: &[Option<Vec<F>>],
+    t: usize,
+    index: usize,
+) -> Result<MerkleProof<F, D, H>> {
+    ensure!(
+        inner_public_input.get(index).is_some_and(|pub_in| pub_in.is_some()),
+        "the inner proof at leaf {} is dummy", index
+    );
+    public_input_merkle_tree::<F, D, H>(inner_public_input, t)?.get_proof(index)
+}
+
+/// The public input of the sampling proof for slot `slot_index` of the dataset with root `dataset_root`
+/// and entropy `entropy`: [slot_index, dataset_root, entropy].
+/// `entropy_bytes` are appended for a circuit with `entropy_from_bytes`,
+/// then `n_samples_active` for a circuit with `variable_n_samples`.
+pub fn sampling_public_input<
+    F: RichField,
+>(
+    slot_index: usize,
+    dataset_root: HashOut<F>,
+    entropy: HashOut<F>,
+    entropy_bytes: Option<&[u8]>,
+    n_samples_active: Option<usize>,
+) -> Vec<F> {
+    let mut pub_in = vec![F::from_canonical_u64(slot_index as u64)];
+    pub_in.extend_from_slice(&dataset_root.elements);
+    pub_in.extend_from_slice(&entropy.elements);
+    if let Some(bytes) = entropy_bytes {
+        pub_in.extend(bytes.iter().map(|&b| F::from_canonical_u8(b)));
+    }
+    pub_in.extend(n_samples_active.map(F::from_canonical_usize));
+    pub_in
+}
+
+/// Verifies that `inner_public_input` is the public input of the inner proof at leaf `opening.index`,
+/// given the public input of the commitment proof whose first 4 elements are the Merkle root.
+/// a dummy leaf is the zero hash, so only a real inner proof can be opened.
+pub fn verify_public_input_opening<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>,
+>(
+    commitment_public_input: &[F],
+    inner_public_input: &[F],
+    opening: &MerkleProof<F, D, H>,
+) -> Result<()> {
+    ensure!(
+        commitment_public_input.len() >= 4,
+        "expected the Merkle root in the commitment public input, got {} elements", commitment_public_input.len()
+    );
+    let root = HashOut::<F>::from_partial(&commitment_public_input[0..4]);
+    let leaf = H::hash_no_pad(inner_public_input);
+    ensure!(
+        opening.verify(leaf, root)?,
+        "the public input is not committed at leaf {}", opening.index
+    );
+    Ok(())
+}
+
+/// Verifies that the sampling proof for slot `slot_index` of the dataset with root `dataset_root`
+/// and entropy `entropy` is aggregated at leaf `opening.index` (see `verify_public_input_opening`).
+/// `entropy_bytes` and `n_samples_active` are the optional public inputs, see `sampling_public_input`.
+pub fn verify_sampling_opening<
+    F: RichField + Extendable<D> + Poseidon2,
+    const D: usize,
+    H: AlgebraicHasher<F>,
+>(
+    commitment_public_input: &[F],
+    slot_index: usize,
+    dataset_root: HashOut<F>,
+    entropy: HashOut<F>,
+    entropy_bytes: Option<&[u8]>,
+    n_samples_active: Option<usize>,
+    opening: &MerkleProof<F, D, H>,
+) -> Result<()> {
+    let inner_public_input = sampling_public_input(slot_index, dataset_root, entropy, entropy_bytes, n_samples_active);
+    verify_public_input_opening(commitment_public_input, &inner_public_input, opening)
+}
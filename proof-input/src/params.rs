@@ -2,6 +2,8 @@
 use std::env;
 use anyhow::{Result, Context};
 use codex_plonky2_circuits::circuits::params::CircuitParams;
+use codex_plonky2_circuits::circuits::sample_cells::ENTROPY_BYTES;
+use crate::input_generator::entropy::{entropy_bytes_from_u64, parse_entropy};
 
 
 // hardcoded default params for generating proof input
@@ -11,7 +13,7 @@ const DEFAULT_CELL_SIZE: usize = 2048; // cell size in bytes
 const DEFAULT_BLOCK_SIZE: usize = 65536; // block size in bytes
 const DEFAULT_N_SAMPLES: usize = 5; // number of samples to prove
 
-const DEFAULT_ENTROPY: usize = 1234567; // external randomness
+const DEFAULT_ENTROPY: u64 = 1234567; // external randomness
 const DEFAULT_SEED: usize = 12345; // seed for creating fake data TODO: not used now
 
 const DEFAULT_N_SLOTS: usize = 11; // number of slots in the dataset
@@ -33,7 +35,10 @@ pub struct InputParams{
     pub cell_size: usize,
     pub block_size: usize,
     pub n_samples: usize,
-    pub entropy: usize,
+    pub max_samples: Option<usize>, // the maximum number of samples of a circuit with a variable number of samples
+    pub entropy: [u8; ENTROPY_BYTES], // raw bytes of the external randomness (see `input_generator::entropy`)
+    pub entropy_from_bytes: bool, // derive the entropy digest from the raw bytes with the bytes sponge
+    pub cells_from_bytes: bool, // input the cells as raw bytes, packed into field elements in-circuit
     pub seed: usize,
     pub n_slots: usize,
     pub testing_slot_index: usize,
@@ -49,7 +54,10 @@ impl Default for Params {
             cell_size: DEFAULT_CELL_SIZE,
             block_size: DEFAULT_BLOCK_SIZE,
             n_samples: DEFAULT_N_SAMPLES,
-            entropy: DEFAULT_ENTROPY,
+            max_samples: None,
+            entropy: entropy_bytes_from_u64(DEFAULT_ENTROPY),
+            entropy_from_bytes: false,
+            cells_from_bytes: false,
             seed: DEFAULT_SEED,
             n_slots: DEFAULT_N_SLOTS,
             testing_slot_index: DEFAULT_SLOT_INDEX,
@@ -70,6 +78,20 @@ impl Params {
         self.input_params.n_samples = n;
         self.circuit_params.n_samples = n;
     }
+
+    /// helper to build the circuit with a variable number of samples up to `max`,
+    /// the number of samples of the input (see `set_n_samples`) is then the number of active samples
+    pub fn set_max_samples(&mut self, max: usize){
+        self.input_params.max_samples = Some(max);
+        self.circuit_params.n_samples = max;
+        self.circuit_params.variable_n_samples = true;
+    }
+
+    /// helper to input the cells as raw bytes, packed in-circuit, for both input and circuit params
+    pub fn set_cells_from_bytes(&mut self){
+        self.input_params.cells_from_bytes = true;
+        self.circuit_params.cell_bytes = Some(self.input_params.cell_size);
+    }
 }
 
 /// Implement a new function to create Params with custom values
@@ -125,7 +147,10 @@ impl InputParams {
             max_log2_n_slots: self.dataset_max_depth(),
             block_tree_depth: self.bot_depth(),
             n_field_elems_per_cell: self.n_field_elems_per_cell(),
-            n_samples:self.n_samples,
+            n_samples: self.max_samples.unwrap_or(self.n_samples),
+            variable_n_samples: self.max_samples.is_some(),
+            entropy_from_bytes: self.entropy_from_bytes,
+            cell_bytes: self.cells_from_bytes.then_some(self.cell_size),
         }
     }
 }
@@ -170,11 +195,26 @@ impl InputParams {
             .parse::<usize>()
             .context("Invalid NSAMPLES")?;
 
-        let entropy = env::var("ENTROPY")
-            .context("ENTROPY not set")?
-            .parse::<usize>()
+        // with MAXSAMPLES, the circuit has a variable number of samples up to MAXSAMPLES
+        let max_samples = env::var("MAXSAMPLES")
+            .ok()
+            .map(|v| v.parse::<usize>().context("Invalid MAXSAMPLES"))
+            .transpose()?;
+
+        // a decimal integer or 32 bytes in hex
+        let entropy = parse_entropy(&env::var("ENTROPY").context("ENTROPY not set")?)
             .context("Invalid ENTROPY")?;
 
+        let entropy_from_bytes = match env::var("ENTROPY_FROM_BYTES") {
+            Ok(v) => v.parse::<bool>().context("Invalid ENTROPY_FROM_BYTES")?,
+            Err(_) => false,
+        };
+
+        let cells_from_bytes = match env::var("CELLS_FROM_BYTES") {
+            Ok(v) => v.parse::<bool>().context("Invalid CELLS_FROM_BYTES")?,
+            Err(_) => false,
+        };
+
         let seed = env::var("SEED")
             .context("SEED not set")?
             .parse::<usize>()
@@ -201,7 +241,10 @@ impl InputParams {
             cell_size,
             block_size,
             n_samples,
+            max_samples,
             entropy,
+            entropy_from_bytes,
+            cells_from_bytes,
             seed,
             n_slots,
             testing_slot_index,
@@ -2,9 +2,13 @@ use plonky2::plonk::circuit_data::CircuitConfig;
 use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher, PoseidonGoldilocksConfig};
 use plonky2::plonk::proof::ProofWithPublicInputs;
 use plonky2_field::goldilocks_field::GoldilocksField;
+use plonky2_field::types::{Field, PrimeField64};
 use codex_plonky2_circuits::circuit_trait::Plonky2Circuit;
-use codex_plonky2_circuits::circuits::sample_cells::SampleCircuit;
+use codex_plonky2_circuits::circuits::sample_cells::{SampleCircuit, SampleCircuitInput};
+use codex_plonky2_circuits::circuits::multi_slot_sample_cells::MultiSlotSampleCircuit;
 use proof_input::input_generator::InputGenerator;
+use proof_input::input_generator::data_structs::cell_field_elems;
+use proof_input::input_generator::entropy::{derive_entropy_digest, entropy_to_digest, parse_entropy};
 use proof_input::params::Params;
 
 // types used in all tests
@@ -47,6 +51,291 @@ fn test_sampling_proof_in_circuit<C: GenericConfig<D, F = F>, H: AlgebraicHasher
     Ok(())
 }
 
+fn test_multi_slot_sampling_proof_in_circuit<C: GenericConfig<D, F = F>, H: AlgebraicHasher<F>>(config: CircuitConfig) -> anyhow::Result<()> {
+    // get input for 3 slots of the same dataset
+    let mut params = Params::default();
+    params.set_n_samples(10);
+    let slot_indices = [1, 3, 7];
+    let input_gen = InputGenerator::<F,D,H>::new(params.input_params);
+    let circ_input = input_gen.gen_testing_multi_slot_circuit_input(&slot_indices)?;
+    assert!(input_gen.verify_multi_slot_circuit_input(&circ_input)?);
+    // the slot indices must be strictly increasing
+    assert!(input_gen.gen_testing_multi_slot_circuit_input(&[3, 1]).is_err());
+
+    // build the circuit
+    let circ = MultiSlotSampleCircuit::<F,D,H>::new(params.circuit_params, slot_indices.len());
+    let (targets, data) = circ.build(config)?;
+    let verifier_data = data.verifier_data();
+    let prover_data = data.prover_data();
+
+    // Prove the circuit using the circuit input
+    let proof_with_pis: ProofWithPublicInputs<F, C, D> = circ.prove(&targets, &circ_input, &prover_data)?;
+
+    // public input: [slot indices, dataset root, entropy]
+    let mut expected_pi: Vec<F> = slot_indices.iter().map(|&i| F::from_canonical_usize(i)).collect();
+    expected_pi.extend_from_slice(&circ_input.dataset_root.elements);
+    expected_pi.extend_from_slice(&circ_input.entropy.elements);
+    assert_eq!(proof_with_pis.public_inputs, expected_pi);
+
+    // Verify the proof
+    assert!(
+        verifier_data.verify(proof_with_pis).is_ok(),
+        "multi slot proof verification failed"
+    );
+
+    // the same slot can't be sampled twice
+    let mut repeated_input = circ_input.clone();
+    repeated_input.slots[1] = repeated_input.slots[0].clone();
+    assert!(circ.prove(&targets, &repeated_input, &prover_data).is_err());
+
+    Ok(())
+}
+
+fn test_sampling_out_of_range_params_in_circuit<C: GenericConfig<D, F = F>, H: AlgebraicHasher<F>>(config: CircuitConfig) -> anyhow::Result<()> {
+    // a full dataset of 16 slots, sampling slot 12
+    let mut params = Params::default();
+    params.set_n_samples(10);
+    params.input_params.n_slots = 16;
+    params.input_params.testing_slot_index = 12;
+    let input_gen = InputGenerator::<F,D,H>::new(params.input_params);
+    let circ_input = input_gen.gen_testing_circuit_input();
+    assert!(input_gen.verify_circuit_input(circ_input.clone()));
+
+    let circ = SampleCircuit::<F,D,H>::new(params.circuit_params.clone());
+    let (targets, data) = circ.build(config.clone())?;
+    let verifier_data = data.verifier_data();
+    let prover_data = data.prover_data();
+    let prove_and_verify = |input| -> bool {
+        circ.prove(&targets, input, &prover_data)
+            .map(|proof: ProofWithPublicInputs<F, C, D>| verifier_data.verify(proof).is_ok())
+            .unwrap_or(false)
+    };
+    assert!(prove_and_verify(&circ_input));
+
+    // claiming 9 slots gives the same dataset mask and keys for slot 12,
+    // so only the bound slot_index < n_slots_per_dataset rejects it
+    let mut input = circ_input.clone();
+    input.n_slots_per_dataset = F::from_canonical_usize(9);
+    assert!(!input_gen.verify_circuit_input(input.clone()));
+    assert!(!prove_and_verify(&input));
+
+    // slot_index == n_slots_per_dataset
+    let mut input = circ_input.clone();
+    input.n_slots_per_dataset = F::from_canonical_usize(12);
+    assert!(!prove_and_verify(&input));
+
+    // n_slots_per_dataset and n_cells_per_slot must be non-zero and at most 2^max_log2_n_slots and 2^max_depth
+    let mut input = circ_input.clone();
+    input.n_slots_per_dataset = F::ZERO;
+    assert!(!prove_and_verify(&input));
+    let mut input = circ_input.clone();
+    input.n_slots_per_dataset = F::from_canonical_usize((1 << params.circuit_params.max_log2_n_slots) + 1);
+    assert!(!prove_and_verify(&input));
+    let mut input = circ_input.clone();
+    input.n_cells_per_slot = F::ZERO;
+    assert!(!input_gen.verify_circuit_input(input.clone()));
+    assert!(!prove_and_verify(&input));
+    let mut input = circ_input.clone();
+    input.n_cells_per_slot = F::from_canonical_usize((1 << params.circuit_params.max_depth) + 1);
+    assert!(!input_gen.verify_circuit_input(input.clone()));
+    assert!(!prove_and_verify(&input));
+
+    // the multi slot circuit has the same bound on each slot index
+    let circ_input = input_gen.gen_testing_multi_slot_circuit_input(&[3, 12])?;
+    let circ = MultiSlotSampleCircuit::<F,D,H>::new(params.circuit_params, 2);
+    let (targets, data) = circ.build(config)?;
+    let verifier_data = data.verifier_data();
+    let prover_data = data.prover_data();
+    let prove_and_verify = |input| -> bool {
+        circ.prove(&targets, input, &prover_data)
+            .map(|proof: ProofWithPublicInputs<F, C, D>| verifier_data.verify(proof).is_ok())
+            .unwrap_or(false)
+    };
+    assert!(prove_and_verify(&circ_input));
+    let mut input = circ_input.clone();
+    input.n_slots_per_dataset = F::from_canonical_usize(9);
+    assert!(!prove_and_verify(&input));
+
+    Ok(())
+}
+
+fn test_entropy_encoding() -> anyhow::Result<()> {
+    // a decimal entropy is the digest [n, 0, 0, 0]
+    let digest = entropy_to_digest::<F>(&parse_entropy("1234567")?);
+    assert_eq!(digest.elements, [F::from_canonical_u64(1234567), F::ZERO, F::ZERO, F::ZERO]);
+
+    // 32 bytes in hex, four 64-bit little-endian limbs reduced modulo the field order
+    let hex = "0x0100000000000000ffffffffffffffff0200000000000000efcdab8967452301";
+    let bytes = parse_entropy(hex)?;
+    assert_eq!(parse_entropy(&hex[2..])?, bytes);
+    let digest = entropy_to_digest::<F>(&bytes);
+    assert_eq!(digest.elements, [
+        F::ONE,
+        F::from_noncanonical_u64(u64::MAX),
+        F::TWO,
+        F::from_canonical_u64(0x0123456789abcdef),
+    ]);
+
+    // wrong length or digits
+    assert!(parse_entropy("0x0102").is_err());
+    assert!(parse_entropy(&hex.replace('f', "g")).is_err());
+    assert!(parse_entropy("-1").is_err());
+    Ok(())
+}
+
+fn test_sampling_with_entropy_bytes_in_circuit<C: GenericConfig<D, F = F>, H: AlgebraicHasher<F>>(config: CircuitConfig) -> anyhow::Result<()> {
+    // the entropy is derived in-circuit from the raw bytes of a block hash
+    let mut params = Params::default();
+    params.set_n_samples(10);
+    params.input_params.entropy = parse_entropy("0x5c3f0e8d4b2a19f7e6d5c4b3a29180706f5e4d3c2b1a09f8e7d6c5b4a3928170")?;
+    params.input_params.entropy_from_bytes = true;
+    params.circuit_params.entropy_from_bytes = true;
+    let input_gen = InputGenerator::<F,D,H>::new(params.input_params.clone());
+    let circ_input = input_gen.gen_testing_circuit_input();
+    let entropy_bytes = circ_input.entropy_bytes.expect("entropy bytes");
+    assert_eq!(circ_input.entropy, derive_entropy_digest::<F,D,H>(&entropy_bytes));
+    assert!(input_gen.verify_circuit_input(circ_input.clone()));
+
+    let circ = SampleCircuit::<F,D,H>::new(params.circuit_params.clone());
+    let (targets, data) = circ.build(config)?;
+    let verifier_data = data.verifier_data();
+    let prover_data = data.prover_data();
+    let proof_with_pis: ProofWithPublicInputs<F, C, D> = circ.prove(&targets, &circ_input, &prover_data)?;
+
+    // public input: [slot_index, dataset_root, entropy, entropy_bytes]
+    let mut expected_pi = vec![circ_input.slot_index];
+    expected_pi.extend_from_slice(&circ_input.dataset_root.elements);
+    expected_pi.extend_from_slice(&circ_input.entropy.elements);
+    expected_pi.extend(entropy_bytes.iter().map(|&b| F::from_canonical_u8(b)));
+    assert_eq!(proof_with_pis.public_inputs, expected_pi);
+    assert!(verifier_data.verify(proof_with_pis).is_ok());
+
+    // other entropy bytes sample other cells
+    let mut input = circ_input.clone();
+    input.entropy_bytes = Some(parse_entropy("1234567")?);
+    assert!(!input_gen.verify_circuit_input(input.clone()));
+    assert!(circ.prove(&targets, &input, &prover_data)
+        .map(|proof: ProofWithPublicInputs<F, C, D>| verifier_data.verify(proof).is_ok())
+        .map_or(true, |ok| !ok));
+
+    // the raw bytes are required
+    let mut input = circ_input;
+    input.entropy_bytes = None;
+    assert!(circ.prove(&targets, &input, &prover_data).is_err());
+
+    Ok(())
+}
+
+fn test_variable_n_samples_in_circuit<C: GenericConfig<D, F = F>, H: AlgebraicHasher<F>>(config: CircuitConfig) -> anyhow::Result<()> {
+    // one circuit for up to 10 samples
+    let mut params = Params::default();
+    params.set_n_samples(6);
+    params.set_max_samples(10);
+    let circ = SampleCircuit::<F,D,H>::new(params.circuit_params.clone());
+    let (targets, data) = circ.build(config.clone())?;
+    let verifier_data = data.verifier_data();
+    let prover_data = data.prover_data();
+    let prove_and_verify = |input: &SampleCircuitInput<F, D>| -> Option<ProofWithPublicInputs<F, C, D>> {
+        circ.prove(&targets, input, &prover_data)
+            .ok()
+            .filter(|proof: &ProofWithPublicInputs<F, C, D>| verifier_data.verify(proof.clone()).is_ok())
+    };
+
+    // the number of active samples is the last public input
+    for n_samples in [6, 1, 10] {
+        let mut input_params = params.input_params.clone();
+        input_params.n_samples = n_samples;
+        let input_gen = InputGenerator::<F,D,H>::new(input_params);
+        let circ_input = input_gen.gen_testing_circuit_input();
+        assert!(input_gen.verify_circuit_input(circ_input.clone()));
+        let proof = prove_and_verify(&circ_input).expect("valid proof");
+        assert_eq!(proof.public_inputs.len(), 10);
+        assert_eq!(proof.public_inputs.last(), Some(&F::from_canonical_usize(n_samples)));
+    }
+
+    let input_gen = InputGenerator::<F,D,H>::new(params.input_params.clone());
+    let circ_input = input_gen.gen_testing_circuit_input();
+
+    // the active samples are checked
+    let mut input = circ_input.clone();
+    input.cell_data[5].data[0] += F::ONE;
+    assert!(prove_and_verify(&input).is_none());
+
+    // at least one and at most 10 samples
+    let mut input = circ_input.clone();
+    input.cell_data.clear();
+    input.merkle_paths.clear();
+    assert!(prove_and_verify(&input).is_none());
+    let mut input = circ_input.clone();
+    input.cell_data.extend(circ_input.cell_data[..5].iter().cloned());
+    input.merkle_paths.extend(circ_input.merkle_paths[..5].iter().cloned());
+    assert!(circ.prove(&targets, &input, &prover_data).is_err());
+
+    // the multi slot circuit has one number of active samples for all slots
+    let slot_indices = [1, 3];
+    let circ_input = input_gen.gen_testing_multi_slot_circuit_input(&slot_indices)?;
+    let circ = MultiSlotSampleCircuit::<F,D,H>::new(params.circuit_params, slot_indices.len());
+    let (targets, data) = circ.build(config)?;
+    let verifier_data = data.verifier_data();
+    let prover_data = data.prover_data();
+    let proof_with_pis: ProofWithPublicInputs<F, C, D> = circ.prove(&targets, &circ_input, &prover_data)?;
+    assert_eq!(proof_with_pis.public_inputs.last(), Some(&F::from_canonical_usize(6)));
+    assert!(verifier_data.verify(proof_with_pis).is_ok());
+    let mut input = circ_input.clone();
+    input.slots[1].cell_data.pop();
+    input.slots[1].merkle_paths.pop();
+    assert!(circ.prove(&targets, &input, &prover_data).is_err());
+
+    Ok(())
+}
+
+fn test_sampling_with_cell_bytes_in_circuit<C: GenericConfig<D, F = F>, H: AlgebraicHasher<F>>(config: CircuitConfig) -> anyhow::Result<()> {
+    // the cells are raw bytes, packed into field elements in-circuit
+    let mut params = Params::default();
+    params.set_n_samples(4);
+    params.set_cells_from_bytes();
+    let input_gen = InputGenerator::<F,D,H>::new(params.input_params.clone());
+    let circ_input = input_gen.gen_testing_circuit_input();
+    for cell in &circ_input.cell_data {
+        assert_eq!(cell.data.len(), params.input_params.cell_size);
+        assert_eq!(cell_field_elems(cell, &params.input_params)?.len(), params.input_params.n_field_elems_per_cell());
+    }
+    assert!(input_gen.verify_circuit_input(circ_input.clone()));
+
+    let circ = SampleCircuit::<F,D,H>::new(params.circuit_params.clone());
+    let (targets, data) = circ.build(config)?;
+    let verifier_data = data.verifier_data();
+    let prover_data = data.prover_data();
+    let proof_with_pis: ProofWithPublicInputs<F, C, D> = circ.prove(&targets, &circ_input, &prover_data)?;
+    assert!(verifier_data.verify(proof_with_pis).is_ok());
+    let prove_and_verify = |input: &SampleCircuitInput<F, D>| -> bool {
+        circ.prove(&targets, input, &prover_data)
+            .map(|proof: ProofWithPublicInputs<F, C, D>| verifier_data.verify(proof).is_ok())
+            .unwrap_or(false)
+    };
+
+    // changing one byte of a cell changes its leaf
+    let mut input = circ_input.clone();
+    input.cell_data[1].data[100] = F::from_canonical_u64((input.cell_data[1].data[100].to_canonical_u64() + 1) % 256);
+    assert!(!input_gen.verify_circuit_input(input.clone()));
+    assert!(!prove_and_verify(&input));
+
+    // a cell element that is not a byte is rejected, even if it packs to the same leaf:
+    // adding 256 to a byte and subtracting 1 from the next one keeps the packed limb
+    let mut input = circ_input.clone();
+    input.cell_data[0].data[0] += F::from_canonical_u64(256);
+    input.cell_data[0].data[1] -= F::ONE;
+    assert!(!input_gen.verify_circuit_input(input.clone()));
+    assert!(!prove_and_verify(&input));
+
+    // the cells must have `cell_size` bytes
+    let mut input = circ_input;
+    input.cell_data[0].data.pop();
+    assert!(circ.prove(&targets, &input, &prover_data).is_err());
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod poseidon2_sampling_tests {
     use super::*;
@@ -66,6 +355,47 @@ mod poseidon2_sampling_tests {
         let config = CircuitConfig::standard_recursion_config();
         test_sampling_proof_in_circuit::<C,H>(config)
     }
+
+    // Test sample cells in-circuit for several slots of the same dataset
+    #[test]
+    fn test_poseidon2_multi_slot_sampling_proof_in_circuit() -> anyhow::Result<()> {
+        let config = CircuitConfig::standard_recursion_config();
+        test_multi_slot_sampling_proof_in_circuit::<C,H>(config)
+    }
+
+    // Test the prover can't pick out-of-range slot index, number of slots or number of cells
+    #[test]
+    fn test_poseidon2_sampling_out_of_range_params_in_circuit() -> anyhow::Result<()> {
+        let config = CircuitConfig::standard_recursion_config();
+        test_sampling_out_of_range_params_in_circuit::<C,H>(config)
+    }
+
+    // Test the entropy encoding from decimal and hex
+    #[test]
+    fn test_poseidon2_entropy_encoding() -> anyhow::Result<()> {
+        test_entropy_encoding()
+    }
+
+    // Test sample cells in-circuit with the entropy derived from raw bytes
+    #[test]
+    fn test_poseidon2_sampling_with_entropy_bytes_in_circuit() -> anyhow::Result<()> {
+        let config = CircuitConfig::standard_recursion_config();
+        test_sampling_with_entropy_bytes_in_circuit::<C,H>(config)
+    }
+
+    // Test one sampling circuit with a variable number of samples
+    #[test]
+    fn test_poseidon2_variable_n_samples_in_circuit() -> anyhow::Result<()> {
+        let config = CircuitConfig::standard_recursion_config();
+        test_variable_n_samples_in_circuit::<C,H>(config)
+    }
+
+    // Test sample cells in-circuit with the cells input as raw bytes
+    #[test]
+    fn test_poseidon2_sampling_with_cell_bytes_in_circuit() -> anyhow::Result<()> {
+        let config = CircuitConfig::standard_recursion_config();
+        test_sampling_with_cell_bytes_in_circuit::<C,H>(config)
+    }
 }
 
 #[cfg(test)]
@@ -88,4 +418,11 @@ mod monolith_sampling_tests {
         let config = generate_config_for_monolith_gate::<F, D>();
         test_sampling_proof_in_circuit::<C,H>(config)
     }
+
+    // Test sample cells in-circuit for several slots of the same dataset
+    #[test]
+    fn test_monolith_multi_slot_sampling_proof_in_circuit() -> anyhow::Result<()> {
+        let config = generate_config_for_monolith_gate::<F, D>();
+        test_multi_slot_sampling_proof_in_circuit::<C,H>(config)
+    }
 }
\ No newline at end of file
@@ -9,7 +9,8 @@ pub(crate) mod serialization_test_functions {
     use codex_plonky2_circuits::circuits::sample_cells::SampleCircuitInput;
     use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
     use proof_input::input_generator::InputGenerator;
-    use proof_input::input_generator::serialization::{export_circ_input_to_json, import_circ_input_from_json};
+    use proof_input::input_generator::serialization::{export_circ_input_to_json, import_circ_input_from_json, CIRC_INPUT_JSON};
+    use proof_input::input_generator::entropy::{entropy_to_digest, parse_entropy};
     use std::path::Path;
     use plonky2::gates::noop::NoopGate;
     use plonky2::iop::target::Target;
@@ -121,6 +122,36 @@ pub(crate) mod serialization_test_functions {
         Ok(())
     }
 
+    // the json input with the raw entropy as hex instead of the digest elements, with and without the raw bytes
+    pub(crate) fn test_import_circ_input_with_raw_entropy<H: Hasher<F>>() -> anyhow::Result<()> {
+        let base_path = "../output/test/raw_entropy/";
+        let hex = "0x5c3f0e8d4b2a19f7e6d5c4b3a29180706f5e4d3c2b1a09f8e7d6c5b4a3928170";
+        let mut input_gen = InputGenerator::<F,D,H>::default();
+        input_gen.input_params.entropy = parse_entropy(hex)?;
+        let circ_input = input_gen.gen_testing_circuit_input();
+        export_circ_input_to_json(circ_input.clone(), base_path)?;
+
+        // replace the digest elements with the hex string
+        let json_path = Path::new(base_path).join(CIRC_INPUT_JSON);
+        let mut json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json_path)?)?;
+        json["entropy"] = serde_json::Value::String(hex.to_string());
+        std::fs::write(&json_path, serde_json::to_string_pretty(&json)?)?;
+        let imported: SampleCircuitInput<F, D> = import_circ_input_from_json(base_path)?;
+        assert_eq!(imported, circ_input);
+        assert_eq!(imported.entropy, entropy_to_digest(&parse_entropy(hex)?));
+
+        // with the in-circuit derivation, the raw bytes are exported too
+        input_gen.input_params.entropy_from_bytes = true;
+        let circ_input = input_gen.gen_testing_circuit_input();
+        export_circ_input_to_json(circ_input.clone(), base_path)?;
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json_path)?)?;
+        assert_eq!(json["entropyBytes"], serde_json::Value::String(hex.to_string()));
+        let imported: SampleCircuitInput<F, D> = import_circ_input_from_json(base_path)?;
+        assert_eq!(imported, circ_input);
+
+        Ok(())
+    }
+
 }
 
 #[cfg(test)]
@@ -138,6 +169,11 @@ mod poseidon_serialization_tests {
         test_export_import_circ_input::<H>()
     }
 
+    #[test]
+    fn test_poseidon_import_circ_input_with_raw_entropy() -> anyhow::Result<()> {
+        test_import_circ_input_with_raw_entropy::<H>()
+    }
+
     #[test]
     fn test_poseidon_export_and_import_circuit_data() -> anyhow::Result<()> {
         let config = CircuitConfig::standard_recursion_config();
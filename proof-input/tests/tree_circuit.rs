@@ -20,20 +20,20 @@ const D: usize = 2;
 type H = PoseidonHash;
 type C = PoseidonGoldilocksConfig;
 
-// A helper to build a minimal circuit and returns T proofs & circuit data.
-fn dummy_proofs<const T: usize>() -> (CircuitData<F, C, D>, Vec<ProofWithPublicInputs<F, C, D>>) {
+// A helper to build a minimal circuit and returns t proofs & circuit data.
+fn dummy_proofs(t: usize) -> (CircuitData<F, C, D>, Vec<ProofWithPublicInputs<F, C, D>>) {
     let config = CircuitConfig::standard_recursion_config();
     let mut builder = CircuitBuilder::<F, D>::new(config);
     for _ in 0..(4096+10) {
         builder.add_gate(NoopGate, vec![]);
     }
     // Add one virtual public input so that the circuit has minimal structure.
-    let t = builder.add_virtual_public_input();
+    let pi_target = builder.add_virtual_public_input();
     let circuit = builder.build::<C>();
     println!("inner circuit size = {}", circuit.common.degree_bits());
     let mut pw = PartialWitness::<F>::new();
-    pw.set_target(t, F::ZERO).expect("faulty assign");
-    let proofs = (0..T).map(|_i| circuit.prove(pw.clone()).unwrap()).collect();
+    pw.set_target(pi_target, F::ZERO).expect("faulty assign");
+    let proofs = (0..t).map(|_i| circuit.prove(pw.clone()).unwrap()).collect();
     (circuit, proofs)
 }
 
@@ -56,10 +56,10 @@ pub fn run_sampling_circ() -> anyhow::Result<(ProofWithPublicInputs<F, C, D>, Pr
     Ok((inner_proof, inner_prover_data, inner_verifier_data))
 }
 
-pub fn run_leaf_circ<const T: usize>(inner_proof: ProofWithPublicInputs<F, C, D>, inner_verifier_data: VerifierCircuitData<F, C, D>, flag: bool, index: usize) -> anyhow::Result<(ProofWithPublicInputs<F, C, D>, ProverCircuitData<F, C, D>, VerifierCircuitData<F, C, D>)> {
+pub fn run_leaf_circ(t: usize, inner_proof: ProofWithPublicInputs<F, C, D>, inner_verifier_data: VerifierCircuitData<F, C, D>, flag: bool, index: usize) -> anyhow::Result<(ProofWithPublicInputs<F, C, D>, ProverCircuitData<F, C, D>, VerifierCircuitData<F, C, D>)> {
 
     // ------------------- leaf --------------------
-    let leaf = LeafCircuit::<F,D, C,H,T>::new(inner_verifier_data.clone());
+    let leaf = LeafCircuit::<F,D, C,H>::new(inner_verifier_data.clone(), t);
 
     // build
     let (targets, data) = leaf.build_with_standard_config()?;
@@ -141,6 +141,36 @@ fn fill_buckets(index: usize, bucket_size: usize, num_buckets: usize) -> Vec<F>{
     buckets
 }
 
+/// a temporary directory unique to the test and process, removed when dropped (also if the test fails)
+struct TestDir(std::path::PathBuf);
+
+impl TestDir {
+    fn new(test_name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("codex_{}_{}", test_name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+}
+
+impl std::ops::Deref for TestDir {
+    type Target = std::path::Path;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<std::path::Path> for TestDir {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 fn check_flag_buckets(index: usize, flag_buckets: Vec<F>) {
     // Compute the bucket and bit position from the input index.
     let bucket = index / 32;
@@ -166,18 +196,18 @@ pub mod leaf_tests {
     fn test_real_leaf_circ() -> anyhow::Result<()> {
         let (inner_proof, _, inner_verifier) = run_sampling_circ()?;
 
-        run_leaf_circ::<128>(inner_proof, inner_verifier, true, 1)?;
+        run_leaf_circ(128, inner_proof, inner_verifier, true, 1)?;
         Ok(())
     }
 
     #[test]
     fn test_dummy_leaf_circuit() -> anyhow::Result<()> {
         // gen dummy inner common
-        let common_data = DummyProofGen::<F, D, C>::gen_dummy_common_data();
+        let common_data = DummyProofGen::<F, D, C>::gen_dummy_common_data(CircuitConfig::standard_recursion_config());
 
         // Generate a dummy inner proof for the leaf using DummyProofGen
         let (dummy_proof, dummy_vd) = DummyProofGen::<F, D, C>::gen_dummy_proof_and_vd_zero_pi(&common_data)?;
-        run_leaf_circ::<128>(dummy_proof, dummy_vd, true, 45)?;
+        run_leaf_circ(128, dummy_proof, dummy_vd, true, 45)?;
 
         Ok(())
     }
@@ -186,7 +216,294 @@ pub mod leaf_tests {
     fn test_dummy_leaf_with_sampling_circ() -> anyhow::Result<()> {
         let (_, _, inner_verifier) = run_sampling_circ()?;
         let (dummy_proof, dummy_vd) = DummyProofGen::gen_dummy_proof_and_vd_zero_pi(&inner_verifier.common)?;
-        run_leaf_circ::<128>(dummy_proof, dummy_vd, false, 0)?;
+        run_leaf_circ(128, dummy_proof, dummy_vd, false, 0)?;
+        Ok(())
+    }
+
+}
+
+// some tests for the batch leaf in tree recursion
+#[cfg(test)]
+mod batch_leaf_tests {
+    use codex_plonky2_circuits::recursion::batch_leaf::{BatchLeafCircuit, BatchLeafInput};
+    use codex_plonky2_circuits::recursion::dummy_gen::DummyProofGen;
+    use codex_plonky2_circuits::recursion::node::{NodeCircuit, NodeInput};
+    use codex_plonky2_circuits::recursion::utils::{get_hash_of_verifier_data, verify_batch_tree_public_input};
+    use codex_plonky2_circuits::recursion::tree::TreeRecursion;
+    use codex_plonky2_circuits::error::CircuitError;
+    use super::*;
+
+    #[test]
+    fn test_batch_leaf_with_node() -> anyhow::Result<()> {
+        const M: usize = 2;
+        const N: usize = 2;
+        let t: usize = 4;
+
+        let (inner_proof, _, inner_verifier) = run_sampling_circ()?;
+        let (dummy_proof, _) = DummyProofGen::<F, D, C>::gen_dummy_proof_and_vd_zero_pi(&inner_verifier.common)?;
+
+        // ------------------- Batch Leaf --------------------
+        let batch_leaf = BatchLeafCircuit::<F, D, C, H, M>::new(inner_verifier.clone(), t);
+        let (leaf_targets, leaf_data) = batch_leaf.build_with_standard_config()?;
+        let leaf_verifier_data = leaf_data.verifier_data();
+        let leaf_prover_data = leaf_data.prover_data();
+        println!("batch leaf circuit degree bits = {:?}", leaf_prover_data.common.degree_bits());
+
+        // leaf 0 holds two real proofs, leaf 1 holds a real and a dummy proof
+        let leaf_inputs = vec![
+            BatchLeafInput { inner_proofs: vec![inner_proof.clone(), inner_proof.clone()], flags: vec![true, true], index: 0 },
+            BatchLeafInput { inner_proofs: vec![inner_proof.clone(), dummy_proof], flags: vec![true, false], index: 1 },
+        ];
+        let mut leaf_proofs = vec![];
+        for input in &leaf_inputs {
+            let proof = batch_leaf.prove(&leaf_targets, input, &leaf_prover_data)?;
+            assert!(leaf_verifier_data.verify(proof.clone()).is_ok(), "batch leaf proof verification failed");
+            leaf_proofs.push(proof);
+        }
+        assert_eq!(leaf_proofs[1].public_inputs[9], F::from_canonical_u64(0b0100));
+
+        // out of range index is rejected
+        let bad_input = BatchLeafInput { index: t / M, ..leaf_inputs[0].clone() };
+        assert!(matches!(
+            batch_leaf.prove(&leaf_targets, &bad_input, &leaf_prover_data),
+            Err(CircuitError::IndexOutOfRange(_, _))
+        ));
+
+        // ------------------- Node --------------------
+        let node = NodeCircuit::<F, D, C, H, N>::new(leaf_verifier_data.clone(), t);
+        let (node_targets, node_data) = node.build_with_standard_config()?;
+        let node_verifier_data = node_data.verifier_data();
+        let node_prover_data = node_data.prover_data();
+
+        let input = NodeInput {
+            inner_proofs: leaf_proofs,
+            verifier_only_data: leaf_verifier_data.verifier_only.clone(),
+            condition: false,
+            flags: vec![true, true],
+            index: 0,
+        };
+        let proof = node.prove(&node_targets, &input, &node_prover_data)?;
+        assert!(node_verifier_data.verify(proof.clone()).is_ok(), "node proof verification failed");
+
+        // check the node public input natively
+        let inner_pi = Some(inner_proof.public_inputs.clone());
+        let inner_public_input = vec![inner_pi.clone(), inner_pi.clone(), inner_pi];
+        let leaf_vd_hash = get_hash_of_verifier_data::<F, D, C, H>(&leaf_verifier_data);
+        verify_batch_tree_public_input::<F, H>(&proof.public_inputs, &inner_public_input, leaf_vd_hash, t, M, N)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_leaf_tree() -> anyhow::Result<()> {
+        const M: usize = 2;
+        const N: usize = 2;
+        // 4 batch leaves, so 2 node levels
+        let t: usize = 8;
+
+        let (inner_proof, _, inner_verifier) = run_sampling_circ()?;
+        let config = CircuitConfig::standard_recursion_config();
+        let mut tree = TreeRecursion::<F, D, C, H, N>::build_with_batch_leaf::<M>(inner_verifier.clone(), config.clone(), t)?;
+        assert_eq!(tree.get_batch_size(), M);
+
+        // 5 real proofs: leaf 2 holds a real and a dummy proof and leaf 3 is dummy
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..5).map(|_i| inner_proof.clone()).collect();
+        let root = tree.prove_tree(&proofs)?;
+        tree.verify_proof(root.clone(), false)?;
+        let inner_pi: Vec<Vec<F>> = proofs.iter().map(|p| p.public_inputs.clone()).collect();
+        tree.verify_public_input(root.public_inputs.clone(), inner_pi.clone())?;
+        tree.verify_proven_indices(&root.public_inputs, &[0, 1, 2, 3, 4])?;
+
+        // the pi hash tree has the batch leaves at the bottom
+        assert!(matches!(
+            tree.verify_public_input(root.public_inputs.clone(), inner_pi[0..4].to_vec()),
+            Err(CircuitError::PublicInputHashMismatch(..))
+        ));
+
+        let compressed = tree.compress(root)?;
+        tree.verify_proof_and_public_input(compressed, inner_pi, true)?;
+
+        // the pi hash tree is rebuilt with one inner proof per leaf by these
+        assert!(tree.build_public_input_commitment().is_err());
+        assert!(tree.build_public_input_verification().is_err());
+
+        // a batch leaf of 4 inner proofs is larger than the node, so the node can't verify node proofs
+        // with the leaf common data and a tree with 2 node levels is rejected
+        match TreeRecursion::<F, D, C, H, N>::build_with_batch_leaf::<4>(inner_verifier.clone(), config.clone(), 16) {
+            Err(CircuitError::RecursionTreeError(e)) => assert!(e.contains("common data do not match"), "{}", e),
+            r => panic!("expected incompatible leaf and node common data, got {:?}", r.map(|_| ())),
+        }
+
+        // t must be a multiple of M and t / M a power of N
+        assert!(matches!(
+            TreeRecursion::<F, D, C, H, N>::build_with_batch_leaf::<4>(inner_verifier.clone(), config.clone(), 6),
+            Err(CircuitError::RecursionTreeError(_))
+        ));
+        assert!(matches!(
+            TreeRecursion::<F, D, C, H, N>::build_with_batch_leaf::<4>(inner_verifier, config, 4),
+            Err(CircuitError::RecursionTreeError(_))
+        ));
+
+        Ok(())
+    }
+
+}
+
+// tests for the heterogeneous leaf over several sampling circuit variants
+#[cfg(test)]
+mod hetero_leaf_tests {
+    use codex_plonky2_circuits::recursion::hetero_leaf::{HeterogeneousLeafCircuit, HeterogeneousLeafInput, VerifierKeyAllowList};
+    use codex_plonky2_circuits::recursion::dummy_gen::DummyProofGen;
+    use codex_plonky2_circuits::recursion::node::{NodeCircuit, NodeInput};
+    use codex_plonky2_circuits::recursion::tree::TreeRecursion;
+    use codex_plonky2_circuits::recursion::utils::{get_hash_of_verifier_data, get_hash_of_verifier_data_with_leaf_constant, verify_tree_public_input};
+    use codex_plonky2_circuits::error::CircuitError;
+    use proof_input::merkle_tree::merkle_safe::MerkleTree;
+    use super::*;
+
+    // builds the sampling circuit with `n_samples` samples and proves it
+    fn run_sampling_variant(n_samples: usize) -> anyhow::Result<(ProofWithPublicInputs<F, C, D>, VerifierCircuitData<F, C, D>)> {
+        let mut params = Params::default();
+        params.set_n_samples(n_samples);
+        let input_gen = InputGenerator::<F,D,H>::new(params.input_params.clone());
+        let circ_input = input_gen.gen_testing_circuit_input();
+        let samp_circ = SampleCircuit::<F,D,H>::new(params.circuit_params);
+        let (targets, data) = samp_circ.build_with_standard_config()?;
+        let verifier_data = data.verifier_data();
+        let proof = samp_circ.prove(&targets, &circ_input, &data.prover_data())?;
+        Ok((proof, verifier_data))
+    }
+
+    #[test]
+    fn test_hetero_leaf_with_node() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 2;
+
+        // two sampling variants with the same circuit shape but different verifier keys
+        let (proof_a, vd_a) = run_sampling_variant(100)?;
+        let (proof_b, vd_b) = run_sampling_variant(90)?;
+        assert_eq!(vd_a.common, vd_b.common, "the variants must share the same common data");
+        assert_ne!(vd_a.verifier_only, vd_b.verifier_only);
+
+        // ------------------- allow-list --------------------
+        let allow_list = VerifierKeyAllowList::<F, D, C, H>::new(vec![vd_a.clone(), vd_b.clone()])?;
+        let vd_hashes = vec![
+            get_hash_of_verifier_data::<F, D, C, H>(&vd_a),
+            get_hash_of_verifier_data::<F, D, C, H>(&vd_b),
+        ];
+        assert_eq!(allow_list.root(), MerkleTree::<F, D, H>::new(&vd_hashes)?.root()?);
+        assert_eq!(allow_list.position(&vd_b.verifier_only), Some(1));
+
+        // ------------------- leaf --------------------
+        let leaf = HeterogeneousLeafCircuit::<F, D, C, H>::new(allow_list.clone(), t);
+        let (leaf_targets, leaf_data) = leaf.build_with_standard_config()?;
+        let leaf_verifier_data = leaf_data.verifier_data();
+        let leaf_prover_data = leaf_data.prover_data();
+        println!("heterogeneous leaf circuit degree bits = {:?}", leaf_prover_data.common.degree_bits());
+
+        let leaf_inputs = vec![
+            HeterogeneousLeafInput { inner_proof: proof_a.clone(), vd_index: 0, flag: true, index: 0 },
+            HeterogeneousLeafInput { inner_proof: proof_b.clone(), vd_index: 1, flag: true, index: 1 },
+        ];
+        let mut leaf_proofs = vec![];
+        for input in &leaf_inputs {
+            let proof = leaf.prove(&leaf_targets, input, &leaf_prover_data)?;
+            assert!(leaf_verifier_data.verify(proof.clone()).is_ok(), "heterogeneous leaf proof verification failed");
+            // the allow-list root is public
+            assert_eq!(&proof.public_inputs[4..8], &allow_list.root().elements);
+            leaf_proofs.push(proof);
+        }
+
+        // a proof checked against the verifier data of another variant is rejected
+        let wrong_vd = HeterogeneousLeafInput { vd_index: 0, ..leaf_inputs[1].clone() };
+        assert!(leaf.prove(&leaf_targets, &wrong_vd, &leaf_prover_data).is_err());
+        // a position outside the allow-list is rejected
+        let bad_index = HeterogeneousLeafInput { vd_index: 2, ..leaf_inputs[1].clone() };
+        assert!(matches!(
+            leaf.prove(&leaf_targets, &bad_index, &leaf_prover_data),
+            Err(CircuitError::IndexOutOfRange(2, 2))
+        ));
+        // a proof from a circuit that is not in the allow-list is rejected
+        let (dummy_proof, _) = DummyProofGen::<F, D, C>::gen_dummy_proof_and_vd_zero_pi(&vd_a.common)?;
+        let not_allowed = HeterogeneousLeafInput { inner_proof: dummy_proof, vd_index: 0, flag: true, index: 0 };
+        assert!(leaf.prove(&leaf_targets, &not_allowed, &leaf_prover_data).is_err());
+
+        // ------------------- Node --------------------
+        let node = NodeCircuit::<F, D, C, H, N>::new(leaf_verifier_data.clone(), t);
+        let (node_targets, node_data) = node.build_with_standard_config()?;
+        let node_verifier_data = node_data.verifier_data();
+        let node_prover_data = node_data.prover_data();
+
+        let input = NodeInput {
+            inner_proofs: leaf_proofs,
+            verifier_only_data: leaf_verifier_data.verifier_only.clone(),
+            condition: false,
+            flags: vec![true, true],
+            index: 0,
+        };
+        let proof = node.prove(&node_targets, &input, &node_prover_data)?;
+        assert!(node_verifier_data.verify(proof.clone()).is_ok(), "node proof verification failed");
+
+        // check the node public input natively
+        let inner_public_input = vec![Some(proof_a.public_inputs.clone()), Some(proof_b.public_inputs.clone())];
+        let leaf_vd_hash = get_hash_of_verifier_data::<F, D, C, H>(&leaf_verifier_data);
+        verify_tree_public_input::<F, H>(&proof.public_inputs, &inner_public_input, leaf_vd_hash, t, N)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allow_list_tree() -> anyhow::Result<()> {
+        const N: usize = 2;
+        // 4 leaves, so 2 node levels
+        let t: usize = 4;
+
+        let (proof_a, vd_a) = run_sampling_variant(100)?;
+        let (proof_b, vd_b) = run_sampling_variant(90)?;
+        let allow_list = VerifierKeyAllowList::<F, D, C, H>::new(vec![vd_a.clone(), vd_b])?;
+        let config = CircuitConfig::standard_recursion_config();
+        let mut tree = TreeRecursion::<F, D, C, H, N>::build_with_allow_list(allow_list.clone(), config.clone(), t)?;
+
+        // proofs of both variants, the last leaf is dummy
+        let proofs = vec![proof_a.clone(), proof_b.clone(), proof_a];
+        // the pre-verification records the allow-list position of each proof for its leaf
+        assert_eq!(tree.pre_verify(&proofs)?.vd_indices, vec![Some(0), Some(1), Some(0)]);
+        let root = tree.prove_tree(&proofs)?;
+        tree.verify_proof(root.clone(), false)?;
+        let inner_pi: Vec<Vec<F>> = proofs.iter().map(|p| p.public_inputs.clone()).collect();
+        tree.verify_public_input(root.public_inputs.clone(), inner_pi.clone())?;
+        tree.verify_proven_indices(&root.public_inputs, &[0, 1, 2])?;
+
+        // the root exposes the allow-list root hashed with the node verifier data
+        let expected_vd_hash = get_hash_of_verifier_data_with_leaf_constant::<F, D, C, H>(
+            &tree.get_node_verifier_data(),
+            allow_list.root(),
+        );
+        assert_eq!(&root.public_inputs[4..8], &expected_vd_hash.elements);
+
+        let compressed = tree.compress(root.clone())?;
+        tree.verify_proof_and_public_input(compressed, inner_pi.clone(), true)?;
+
+        // a tree over a different allow-list rejects the root public input
+        let other_list = VerifierKeyAllowList::<F, D, C, H>::new(vec![vd_a])?;
+        let other_tree = TreeRecursion::<F, D, C, H, N>::build_with_allow_list(other_list, config, t)?;
+        assert!(matches!(
+            other_tree.verify_public_input(root.public_inputs, inner_pi),
+            Err(CircuitError::VerifierDataHashMismatch(..))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allow_list_rejects_different_common_data() -> anyhow::Result<()> {
+        let (_, vd) = run_sampling_variant(100)?;
+        let (dummy_circ, _) = dummy_proofs(1);
+        assert!(matches!(
+            VerifierKeyAllowList::<F, D, C, H>::new(vec![vd, dummy_circ.verifier_data()]),
+            Err(CircuitError::InvalidArgument(_))
+        ));
+        assert!(VerifierKeyAllowList::<F, D, C, H>::new(vec![]).is_err());
         Ok(())
     }
 
@@ -201,12 +518,12 @@ mod node_tests {
     use codex_plonky2_circuits::recursion::node::{NodeCircuit, NodeInput};
     use super::*;
 
-    fn run_node_circ<const N: usize, const T: usize>(leaf_proofs: Vec<ProofWithPublicInputs<F, C, D>>, leaf_verifier_data: VerifierCircuitData<F, C, D>, _flag: bool, index: usize) -> anyhow::Result<()> {
+    fn run_node_circ<const N: usize>(t: usize, leaf_proofs: Vec<ProofWithPublicInputs<F, C, D>>, leaf_verifier_data: VerifierCircuitData<F, C, D>, _flag: bool, index: usize) -> anyhow::Result<()> {
 
         // ------------------- Node --------------------
         // N leaf proofs
         assert_eq!(leaf_proofs.len(), N);
-        let node = NodeCircuit::<F,D,C,H, N, T>::new(leaf_verifier_data.clone());
+        let node = NodeCircuit::<F,D,C,H, N>::new(leaf_verifier_data.clone(), t);
 
         // build
         let (targets, data) = node.build_with_standard_config()?;
@@ -244,17 +561,17 @@ mod node_tests {
     fn test_real_node_circ() -> anyhow::Result<()> {
         let (inner_proof, _, inner_verifier) = run_sampling_circ()?;
         // this is a bit wasteful to build leaf twice, TODO: fix this
-        let (leaf_proof_1, _, _leaf_verifier_1) = run_leaf_circ::<128>(inner_proof.clone(), inner_verifier.clone(), true, 0)?;
-        let (leaf_proof_2, _, leaf_verifier_2) = run_leaf_circ::<128>(inner_proof, inner_verifier, true, 1)?;
+        let (leaf_proof_1, _, _leaf_verifier_1) = run_leaf_circ(128, inner_proof.clone(), inner_verifier.clone(), true, 0)?;
+        let (leaf_proof_2, _, leaf_verifier_2) = run_leaf_circ(128, inner_proof, inner_verifier, true, 1)?;
         let leaf_proofs = vec![leaf_proof_1,leaf_proof_2];
-        run_node_circ::<2,128>(leaf_proofs, leaf_verifier_2, true, 0)
+        run_node_circ::<2>(128, leaf_proofs, leaf_verifier_2, true, 0)
     }
 
     #[test]
     fn test_dummy_node_circuit() -> anyhow::Result<()> {
         const N: usize = 2;
         const B: usize = 4; // bucket size
-        const T: usize = 128;
+        let t: usize = 128;
 
         let (leaf_data, leaf_pi) = dummy_leaf::<B>();
         let leaf_vd = leaf_data.verifier_data();
@@ -262,7 +579,7 @@ mod node_tests {
         let indices = vec![0,1];
         let leaf_proofs = dummy_leaf_proofs::<B>(leaf_data,leaf_pi,indices);
 
-        let node = NodeCircuit::<F, D, C, H, N, T>::new(leaf_vd.clone());
+        let node = NodeCircuit::<F, D, C, H, N>::new(leaf_vd.clone(), t);
 
         // Build the node circuit.
         let (targets, circuit_data) = node.build_with_standard_config()?;
@@ -306,20 +623,28 @@ mod node_tests {
 mod tree_tests {
     use plonky2::plonk::proof::{ProofWithPublicInputs};
     use codex_plonky2_circuits::recursion::{tree::TreeRecursion};
+    use codex_plonky2_circuits::recursion::cross_tree::{CrossTreeCircuit, CrossTreeInput};
+    use codex_plonky2_circuits::recursion::utils::{get_hash_of_verifier_data, verify_tree_public_input};
+    use codex_plonky2_circuits::recursion::arity::{proofs_per_level, select_arity, ArityEstimator, ArityTarget, SUPPORTED_ARITIES};
+    use codex_plonky2_circuits::error::CircuitError;
+    use codex_plonky2_circuits::recursion::verifier::TreeVerifier;
+    use codex_plonky2_circuits::serialization::export_verifier_circuit_data;
+    use plonky2::hash::hash_types::HashOut;
+    use proof_input::merkle_tree::pi_commitment::{get_public_input_opening, public_input_merkle_tree, verify_public_input_opening, verify_sampling_opening};
     use super::*;
 
-    fn run_tree_recursion<const N: usize, const T: usize>(compress: bool) -> anyhow::Result<()> {
+    fn run_tree_recursion<const N: usize>(t: usize, compress: bool) -> anyhow::Result<()> {
 
         //------------ sampling inner circuit ----------------------
         // Circuit that does the sampling - 100 samples
         let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
 
-        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..T).map(|_i| inner_proof.clone()).collect();
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..t).map(|_i| inner_proof.clone()).collect();
 
         // ------------------- tree --------------------
         // N-to-1 tree aggregation
 
-        let mut tree = TreeRecursion::<F,D,C,H, N, T>::build_with_standard_config(inner_verifier_data.clone())?;
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data.clone(), t)?;
 
         // aggregate
         let root = if !compress {
@@ -345,25 +670,598 @@ mod tree_tests {
     #[test]
     fn test_tree_recursion() -> anyhow::Result<()> {
         // total number of proofs to aggregate
-        const T:usize = 4;
-        run_tree_recursion::<2, T>(false)
+        let t: usize = 4;
+        run_tree_recursion::<2>(t, false)
     }
 
     #[test]
     fn test_tree_recursion_with_compression() -> anyhow::Result<()> {
         // total number of proofs to aggregate
-        const T:usize = 4;
-        run_tree_recursion::<2, T>(true)
+        let t: usize = 4;
+        run_tree_recursion::<2>(t, true)
+    }
+
+    #[test]
+    fn test_tree_recursion_with_dummy_leaves() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 8;
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        // 3 real proofs, the other 5 leaves are dummy
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..3).map(|_i| inner_proof.clone()).collect();
+
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data.clone(), t)?;
+
+        // no proofs or more than t proofs can't be aggregated
+        assert!(tree.prove_tree(&[]).is_err());
+        assert!(tree.prove_tree(&vec![inner_proof.clone(); t + 1]).is_err());
+
+        let root = tree.prove_tree(&proofs)?;
+
+        // only the flags of the real leaves 0,1,2 are set
+        check_flag_buckets_of(&root.public_inputs[9..10], &[0, 1, 2]);
+
+        let inner_pi: Vec<Vec<F>> = proofs.iter().map(|p| p.public_inputs.clone()).collect();
+        assert!(
+            tree.verify_proof_and_public_input(root, inner_pi, false).is_ok(),
+            "proof verification failed"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_recursion_with_invalid_inner_proof() -> anyhow::Result<()> {
+        use codex_plonky2_circuits::error::CircuitError;
+
+        const N: usize = 2;
+        let t: usize = 4;
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        // the proof at index 1 does not verify since its public input was changed
+        let mut bad_proof = inner_proof.clone();
+        bad_proof.public_inputs[0] += F::ONE;
+        let proofs = vec![inner_proof.clone(), bad_proof, inner_proof.clone()];
+
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data.clone(), t)?;
+
+        // the pre-verification reports the invalid proof
+        let report = tree.pre_verify(&proofs)?;
+        assert_eq!(report.num_proofs, 3);
+        assert_eq!(report.failed_indices(), vec![1]);
+        assert_eq!(report.vd_indices, vec![Some(0), None, Some(0)]);
+
+        // by default, the aggregation fails before proving any leaf
+        assert!(matches!(
+            tree.prove_tree(&proofs),
+            Err(CircuitError::InvalidInnerProofs(indices)) if indices == vec![1]
+        ));
+
+        // with quarantine, the invalid proof becomes a dummy leaf and the rest is aggregated
+        tree.set_quarantine_invalid_proofs(true);
+        let root = tree.prove_tree(&proofs)?;
+        tree.verify_proof(root.clone(), false)?;
+        tree.verify_proven_indices(&root.public_inputs, &[0, 2])?;
+
+        let inner_pi = Some(inner_proof.public_inputs.clone());
+        tree.verify_public_input_with_dummies(root.public_inputs, vec![inner_pi.clone(), None, inner_pi])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prove_bundle() -> anyhow::Result<()> {
+        use codex_plonky2_circuits::bundle::Bundle;
+        use hashbrown::HashMap;
+
+        const N: usize = 2;
+        let t: usize = 4;
+
+        let mut params = Params::default();
+        params.set_n_samples(100);
+        let input_gen = InputGenerator::<F,D,H>::new(params.input_params.clone());
+        let circ_input = input_gen.gen_testing_circuit_input();
+
+        let mut bundle = Bundle::<F, C, D, H>::new(2, params.circuit_params)?;
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(bundle.verifier_data.clone(), t)?;
+
+        // index out of range
+        let out_of_range: HashMap<usize, _> = [(t, circ_input.clone())].into_iter().collect();
+        assert!(tree.prove_bundle(&mut bundle, out_of_range, false).is_err());
+
+        // proofs at indices 0 and 2, leaves 1 and 3 are dummy
+        let circ_inputs: HashMap<usize, _> = [(0, circ_input.clone()), (2, circ_input)].into_iter().collect();
+        let root = tree.prove_bundle(&mut bundle, circ_inputs, false)?;
+
+        check_flag_buckets_of(&root.public_inputs[9..10], &[0, 2]);
+        assert_eq!(tree.get_proven_indices(&root.public_inputs)?, vec![0, 2]);
+        tree.verify_proven_indices(&root.public_inputs, &[2, 0])?;
+
+        let inner_pi: Vec<Option<Vec<F>>> = (0..t)
+            .map(|i| bundle.bundle_proofs.get(&i).map(|p| p.public_inputs.clone()))
+            .collect();
+        tree.verify_proof(root.clone(), false)?;
+        tree.verify_public_input_with_dummies(root.public_inputs, inner_pi)?;
+
+        // the bundle is reused for the next job, the proofs of the first call are not aggregated again
+        let next_inputs: HashMap<usize, _> = [(1, input_gen.gen_testing_circuit_input())].into_iter().collect();
+        let root = tree.prove_bundle(&mut bundle, next_inputs, false)?;
+        assert_eq!(bundle.bundle_proofs.keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(tree.get_proven_indices(&root.public_inputs)?, vec![1]);
+        let inner_pi: Vec<Option<Vec<F>>> = (0..t)
+            .map(|i| bundle.bundle_proofs.get(&i).map(|p| p.public_inputs.clone()))
+            .collect();
+        tree.verify_proof(root.clone(), false)?;
+        tree.verify_public_input_with_dummies(root.public_inputs, inner_pi)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_recursion_num_threads() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 4;
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..3).map(|_i| inner_proof.clone()).collect();
+
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data.clone(), t)?;
+        let root = tree.prove_tree(&proofs)?;
+
+        // with the `parallel` feature, each level is proved with 2 threads
+        tree.set_num_threads(2)?;
+        let parallel_root = tree.prove_tree(&proofs)?;
+
+        // the proofs can differ in the proof-of-work witness but must have the same public input
+        assert_eq!(root.public_inputs, parallel_root.public_inputs);
+        tree.verify_proof(parallel_root, false)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_tree() -> anyhow::Result<()> {
+        use codex_plonky2_circuits::recursion::incremental::IncrementalTree;
+
+        const N: usize = 2;
+        let t: usize = 4;
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        let tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data.clone(), t)?;
+        let mut incremental = IncrementalTree::new(&tree);
+
+        // nothing to aggregate yet
+        assert!(incremental.finalize().is_err());
+
+        incremental.add_proof(2, &inner_proof)?;
+        incremental.add_proof(0, &inner_proof)?;
+        assert_eq!(incremental.num_subtrees(), 2);
+        // leaves 2 and 3 are merged as soon as both exist
+        incremental.add_proof(3, &inner_proof)?;
+        assert_eq!(incremental.num_subtrees(), 2);
+
+        // the same index can't be added twice, the index must be in range
+        assert!(incremental.add_proof(3, &inner_proof).is_err());
+        assert!(incremental.add_proof(t, &inner_proof).is_err());
+
+        // leaf 1 is dummy
+        let root = incremental.finalize()?;
+        check_flag_buckets_of(&root.public_inputs[9..10], &[0, 2, 3]);
+        tree.verify_proof(root.clone(), false)?;
+        let inner_pi: Vec<Option<Vec<F>>> = (0..t)
+            .map(|i| (i != 1).then(|| inner_proof.public_inputs.clone()))
+            .collect();
+        tree.verify_public_input_with_dummies(root.public_inputs, inner_pi)?;
+
+        // adding the last leaf completes the tree
+        incremental.add_proof(1, &inner_proof)?;
+        assert_eq!(incremental.num_subtrees(), 1);
+        let root = incremental.finalize()?;
+        check_flag_buckets_of(&root.public_inputs[9..10], &[0, 1, 2, 3]);
+        tree.verify_proof(root, false)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_recursion_with_checkpoints() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 4;
+        let work_dir = TestDir::new("tree_recursion_with_checkpoints");
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..3).map(|_i| inner_proof.clone()).collect();
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data.clone(), t)?;
+
+        let root = tree.prove_tree_with_checkpoints(&proofs, &work_dir)?;
+        // leaves 0,1,2 at level 0, nodes 0,1 at level 1 and the root at level 2
+        for (level, index) in [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (2, 0)] {
+            let path = work_dir.join(format!("level_{}/{}/verifier_data/proof_with_public_inputs.json", level, index));
+            assert!(path.exists(), "missing checkpoint {:?}", path);
+        }
+
+        // simulate an interrupted run: level 0 is the highest completed level
+        std::fs::remove_dir_all(work_dir.join("level_1/1"))?;
+        std::fs::remove_dir_all(work_dir.join("level_2"))?;
+        let resumed_root = tree.prove_tree_with_checkpoints(&proofs, &work_dir)?;
+        assert_eq!(root.public_inputs, resumed_root.public_inputs);
+        tree.verify_proof(resumed_root, false)?;
+
+        // the checkpoints don't match a different set of inputs
+        assert!(tree.prove_tree_with_checkpoints(&proofs[0..2], &work_dir).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_export_and_load() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 4;
+        let config = CircuitConfig::standard_recursion_config();
+        let base_path = TestDir::new("tree_export_and_load");
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        let tree = TreeRecursion::<F,D,C,H, N>::build(inner_verifier_data.clone(), config.clone(), t)?;
+        tree.export_circuits(&base_path)?;
+
+        // the loaded tree proves with the same circuits
+        let mut loaded = TreeRecursion::<F,D,C,H, N>::load(inner_verifier_data.clone(), config.clone(), t, &base_path)?;
+        assert_eq!(loaded.get_node_verifier_data().verifier_only, tree.get_node_verifier_data().verifier_only);
+        assert_eq!(loaded.get_compression_verifier_data().verifier_only, tree.get_compression_verifier_data().verifier_only);
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..3).map(|_i| inner_proof.clone()).collect();
+        let root = loaded.prove_tree(&proofs)?;
+        let inner_pi: Vec<Vec<F>> = proofs.iter().map(|p| p.public_inputs.clone()).collect();
+        tree.verify_proof_and_public_input(root, inner_pi, false)?;
+
+        // stale cache: different maximum number of proofs or inner circuit
+        let stale = TreeRecursion::<F,D,C,H, N>::load(inner_verifier_data.clone(), config.clone(), 8, &base_path);
+        assert!(matches!(stale, Err(CircuitError::StaleCircuitData(_))));
+        let (other_inner, _) = dummy_proofs(0);
+        let stale = TreeRecursion::<F,D,C,H, N>::load(other_inner.verifier_data(), config, t, &base_path);
+        assert!(matches!(stale, Err(CircuitError::StaleCircuitData(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_verifier_from_exported_data() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 4;
+        let base_path = TestDir::new("tree_verifier_from_exported_data");
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..3).map(|_i| inner_proof.clone()).collect();
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data, t)?;
+        let root = tree.prove_tree(&proofs)?;
+
+        // the verifier only sees the exported verifier data
+        export_verifier_circuit_data(tree.get_node_verifier_data(), base_path.join("tree"))?;
+        export_verifier_circuit_data(tree.get_compression_verifier_data(), base_path.join("compression"))?;
+        let verifier = TreeVerifier::<F,D,C,H, N>::load(base_path.join("tree"), base_path.join("compression"), t)?;
+
+        let inner_pi: Vec<Vec<F>> = proofs.iter().map(|p| p.public_inputs.clone()).collect();
+        verifier.verify_proof_and_public_input(root.clone(), inner_pi.clone(), false)?;
+
+        // every public input check reports which part is wrong
+        let tampered = |i: usize| {
+            let mut pi = root.public_inputs.clone();
+            pi[i] += F::ONE;
+            verifier.verify_public_input(pi, inner_pi.clone())
+        };
+        assert!(matches!(tampered(0), Err(CircuitError::PublicInputHashMismatch(..))));
+        assert!(matches!(tampered(4), Err(CircuitError::VerifierDataHashMismatch(..))));
+        assert!(matches!(tampered(9), Err(CircuitError::FlagBucketsMismatch(..))));
+        assert!(matches!(
+            verifier.verify_public_input(root.public_inputs[0..9].to_vec(), inner_pi.clone()),
+            Err(CircuitError::PublicInputLengthError(..))
+        ));
+        assert!(matches!(
+            tree.verify_public_input(root.public_inputs.clone(), inner_pi[0..2].to_vec()),
+            Err(CircuitError::PublicInputHashMismatch(..))
+        ));
+        // the root is not a compressed proof
+        assert!(verifier.verify_proof(root, true).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_input_verification() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 8;
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        let k = inner_verifier_data.common.num_public_inputs;
+        // 5 real proofs, leaf 5 and the subtree of leaves 6,7 are dummy
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..5).map(|_i| inner_proof.clone()).collect();
+
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data, t)?;
+        let compressed = tree.prove_tree_and_compress(&proofs)?;
+
+        // the stage must be built first
+        let inner_pi: Vec<Option<Vec<F>>> = proofs.iter().map(|p| Some(p.public_inputs.clone())).collect();
+        assert!(tree.prove_public_input_verification(compressed.clone(), inner_pi.clone()).is_err());
+
+        tree.build_public_input_verification()?;
+        let pi_proof = tree.prove_public_input_verification(compressed.clone(), inner_pi)?;
+        tree.verify_public_input_verification_proof(pi_proof.clone())?;
+
+        // public input: t×k raw inner public inputs (zero for dummy leaves), then vd hash, index and flag buckets
+        let pub_input = &pi_proof.public_inputs;
+        assert_eq!(pub_input.len(), t * k + compressed.public_inputs.len() - 4);
+        for i in 0..t {
+            let row = &pub_input[i * k..(i + 1) * k];
+            if i < proofs.len() {
+                assert_eq!(row, &inner_proof.public_inputs[..]);
+            } else {
+                assert!(row.iter().all(|f| *f == F::ZERO));
+            }
+        }
+        assert_eq!(&pub_input[t * k..], &compressed.public_inputs[4..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_input_commitment() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 4;
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        // 3 real proofs, leaf 3 is dummy
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..3).map(|_i| inner_proof.clone()).collect();
+
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data, t)?;
+        let compressed = tree.prove_tree_and_compress(&proofs)?;
+
+        let inner_pi: Vec<Option<Vec<F>>> = proofs.iter().map(|p| Some(p.public_inputs.clone())).collect();
+        tree.build_public_input_commitment()?;
+        let commitment_proof = tree.prove_public_input_commitment(compressed.clone(), inner_pi.clone())?;
+        tree.verify_public_input_commitment_proof(commitment_proof.clone())?;
+
+        // public input: the Merkle root of the inner pi hashes, then vd hash, index and flag buckets
+        let pub_input = &commitment_proof.public_inputs;
+        let merkle_tree = public_input_merkle_tree::<F,D,H>(&inner_pi, t)?;
+        assert_eq!(&pub_input[0..4], &merkle_tree.root()?.elements);
+        assert_eq!(&pub_input[4..], &compressed.public_inputs[4..]);
+
+        // open leaf 1: slot X of dataset Y with entropy E
+        let slot_index = inner_proof.public_inputs[0].to_canonical_u64() as usize;
+        let dataset_root = HashOut::<F>::from_partial(&inner_proof.public_inputs[1..5]);
+        let entropy = HashOut::<F>::from_partial(&inner_proof.public_inputs[5..9]);
+        let opening = get_public_input_opening::<F,D,H>(&inner_pi, t, 1)?;
+        verify_sampling_opening(pub_input, slot_index, dataset_root, entropy, None, None, &opening)?;
+
+        // wrong entropy
+        let wrong_entropy = HashOut::<F>::from_partial(&[F::ONE; 4]);
+        assert!(verify_sampling_opening(pub_input, slot_index, dataset_root, wrong_entropy, None, None, &opening).is_err());
+        // the dummy leaf can't be opened, and its zero leaf doesn't open to the sampling public input
+        assert!(get_public_input_opening::<F,D,H>(&inner_pi, t, 3).is_err());
+        let dummy_opening = merkle_tree.get_proof(3)?;
+        assert!(verify_sampling_opening(pub_input, slot_index, dataset_root, entropy, None, None, &dummy_opening).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_input_commitment_with_variable_n_samples() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 2;
+
+        // one sampling circuit for up to 10 samples, the number of active samples is the last public input
+        let mut params = Params::default();
+        params.set_n_samples(6);
+        params.set_max_samples(10);
+        let samp_circ = SampleCircuit::<F,D,H>::new(params.circuit_params.clone());
+        let (targets, data) = samp_circ.build_with_standard_config()?;
+        let verifier_data = data.verifier_data();
+        let prover_data = data.prover_data();
+        let proofs = [6, 3].into_iter().map(|n_samples| {
+            let mut input_params = params.input_params.clone();
+            input_params.n_samples = n_samples;
+            let circ_input = InputGenerator::<F,D,H>::new(input_params).gen_testing_circuit_input();
+            samp_circ.prove(&targets, &circ_input, &prover_data)
+        }).collect::<Result<Vec<ProofWithPublicInputs<F, C, D>>, _>>()?;
+        assert_eq!(proofs[1].public_inputs.len(), 10);
+
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(verifier_data, t)?;
+        let compressed = tree.prove_tree_and_compress(&proofs)?;
+        let inner_pi: Vec<Option<Vec<F>>> = proofs.iter().map(|p| Some(p.public_inputs.clone())).collect();
+        tree.build_public_input_commitment()?;
+        let commitment_proof = tree.prove_public_input_commitment(compressed, inner_pi.clone())?;
+        tree.verify_public_input_commitment_proof(commitment_proof.clone())?;
+        let pub_input = &commitment_proof.public_inputs;
+
+        // open leaf 1 with 3 active samples
+        let inner_pub_input = &proofs[1].public_inputs;
+        let slot_index = inner_pub_input[0].to_canonical_u64() as usize;
+        let dataset_root = HashOut::<F>::from_partial(&inner_pub_input[1..5]);
+        let entropy = HashOut::<F>::from_partial(&inner_pub_input[5..9]);
+        let opening = get_public_input_opening::<F,D,H>(&inner_pi, t, 1)?;
+        verify_public_input_opening(pub_input, inner_pub_input, &opening)?;
+        verify_sampling_opening(pub_input, slot_index, dataset_root, entropy, None, Some(3), &opening)?;
+
+        // the number of active samples is committed too
+        assert!(verify_sampling_opening(pub_input, slot_index, dataset_root, entropy, None, Some(6), &opening).is_err());
+        assert!(verify_sampling_opening(pub_input, slot_index, dataset_root, entropy, None, None, &opening).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_with_proof_count() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 4;
+
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..3).map(|_i| inner_proof.clone()).collect();
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data, t)?;
+        assert!(tree.get_proof_count(&[]).is_err());
+
+        // at least 3 real proofs
+        tree.build_compression_with_proof_count(Some(3))?;
+        let compressed = tree.prove_tree_and_compress(&proofs)?;
+        tree.verify_proof(compressed.clone(), true)?;
+        assert_eq!(compressed.public_inputs.len(), 9 + 1 + 1);
+        assert_eq!(compressed.public_inputs[10], F::from_canonical_u64(3));
+        assert_eq!(tree.get_proof_count(&compressed.public_inputs)?, 3);
+
+        // the public input of the tree proof is unchanged
+        let inner_pi: Vec<Vec<F>> = proofs.iter().map(|p| p.public_inputs.clone()).collect();
+        tree.verify_public_input(compressed.public_inputs, inner_pi)?;
+
+        // 2 real proofs are not enough: the range check on count - min_count can't be satisfied, proving fails
+        let root = tree.prove_tree(&proofs[0..2])?;
+        assert!(matches!(tree.compress(root), Err(CircuitError::InvalidProofError(_))));
+
+        // a minimum of more than t proofs can't be built
+        assert!(tree.build_compression_with_proof_count(Some(t + 1)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proven_indices() -> anyhow::Result<()> {
+        use codex_plonky2_circuits::recursion::utils::{get_proven_indices, verify_proven_indices};
+
+        let t: usize = 64;
+        // pi hash, vd hash, index, 2 flag buckets
+        let mut public_input = vec![F::ZERO; 11];
+        public_input[9] = F::from_canonical_u64((1 << 0) | (1 << 5) | (1 << 31));
+        public_input[10] = F::from_canonical_u64(1 << 1);
+
+        assert_eq!(get_proven_indices(&public_input, t)?, vec![0, 5, 31, 33]);
+        verify_proven_indices(&public_input, t, &[33, 31, 5, 0])?;
+        match verify_proven_indices(&public_input, t, &[0, 5, 6, 31]) {
+            Err(CircuitError::ProvenIndicesMismatch(missing, unexpected)) => {
+                assert_eq!(missing, vec![6]);
+                assert_eq!(unexpected, vec![33]);
+            }
+            r => panic!("expected a proven indices mismatch, got {:?}", r),
+        }
+
+        // missing flag buckets, flags beyond the bucket size or `t` are invalid
+        assert!(matches!(get_proven_indices(&public_input[..10], t), Err(CircuitError::PublicInputLengthError(..))));
+        public_input[10] = F::from_canonical_u64(1 << 32);
+        assert!(matches!(get_proven_indices(&public_input, t), Err(CircuitError::InvalidFlagBucket(1, _))));
+        let mut small = public_input[..10].to_vec();
+        small[9] = F::from_canonical_u64(1 << 4);
+        assert!(matches!(get_proven_indices(&small, 4), Err(CircuitError::InvalidFlagBucket(0, _))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_arity_selection() -> anyhow::Result<()> {
+        let t: usize = 4;
+        let (inner_proof, _, inner_verifier_data) = run_sampling_circ()?;
+
+        assert_eq!(proofs_per_level(16, 4)?, vec![16, 4, 1]);
+        assert!(proofs_per_level(8, 4).is_err());
+        assert!(proofs_per_level(2, 4).is_err());
+
+        let estimator = ArityEstimator::<F,D,C,H>::new(inner_verifier_data.clone(), CircuitConfig::standard_recursion_config(), t)?;
+        assert!(matches!(estimator.estimate_all(&[3]), Err(CircuitError::UnsupportedArity(3, _))));
+
+        // 4 is not a power of 8 and 16
+        let estimates = estimator.estimate_all(&SUPPORTED_ARITIES)?;
+        assert_eq!(estimates.iter().map(|e| e.arity).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(estimates[0].proofs_per_level, vec![4, 2, 1]);
+        assert_eq!(estimates[1].proofs_per_level, vec![4, 1]);
+        // a single node level does not need matching leaf and node common data
+        assert!(estimates[1].compatible);
+        for e in &estimates {
+            assert_eq!(e.memory_cost, 1 << e.leaf_degree_bits.max(e.node_degree_bits));
+        }
+
+        // the tree is built with the selected arity
+        let selected = select_arity(&estimates, ArityTarget::Latency)?;
+        assert_eq!(selected, estimator.select(ArityTarget::Latency)?);
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..3).map(|_i| inner_proof.clone()).collect();
+        let root = match selected.arity {
+            2 => TreeRecursion::<F,D,C,H,2>::build_with_standard_config(inner_verifier_data, t)?.prove_tree(&proofs)?,
+            4 => TreeRecursion::<F,D,C,H,4>::build_with_standard_config(inner_verifier_data, t)?.prove_tree(&proofs)?,
+            arity => panic!("unexpected arity {}", arity),
+        };
+        check_flag_buckets_of(&root.public_inputs[9..], &[0, 1, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_tree_aggregation() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 2;
+        let p: usize = 4;
+
+        let (inner_proof, _, inner_verifier_data) = run_sampling_circ()?;
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(inner_verifier_data, t)?;
+        // two provers, trees 1 and 3 are missing
+        let root_a = tree.prove_tree(&[inner_proof.clone(), inner_proof.clone()])?;
+        let root_b = tree.prove_tree(&[inner_proof.clone()])?;
+
+        // the root of a tree with a single node level carries the leaf verifier data hash
+        let vd_hash = get_hash_of_verifier_data::<F,D,C,H>(&tree.get_leaf_verifier_data());
+        let cross = CrossTreeCircuit::<F,D,C,H, N>::new(tree.get_node_verifier_data(), vd_hash, t, p);
+        let (targets, data) = cross.build_with_standard_config()?;
+        println!("cross tree circuit size = {:?}", data.common.degree_bits());
+        let verifier_data = data.verifier_data();
+        let prover_data = data.prover_data();
+
+        let dummy = cross.dummy_proof()?;
+        let input = CrossTreeInput {
+            inner_proofs: vec![root_a, dummy.clone(), root_b, dummy],
+            flags: vec![true, false, true, false],
+        };
+        let proof = cross.prove(&targets, &input, &prover_data)?;
+        assert!(verifier_data.verify(proof.clone()).is_ok());
+
+        // the public input of one tree over all p * t slots, with the leaf verifier data hash since t = N
+        let inner_pi = Some(inner_proof.public_inputs.clone());
+        let global_inner_pi = vec![inner_pi.clone(), inner_pi.clone(), None, None, inner_pi];
+        verify_tree_public_input::<F,H>(&proof.public_inputs, &global_inner_pi, vd_hash, cross.max_proofs(), N)?;
+        check_flag_buckets_of(&proof.public_inputs[9..], &[0, 1, 4]);
+
+        // wrong number of roots
+        let bad_input = CrossTreeInput { inner_proofs: input.inner_proofs[0..2].to_vec(), flags: input.flags.clone() };
+        assert!(matches!(
+            cross.prove(&targets, &bad_input, &prover_data),
+            Err(CircuitError::InnerProofsLengthMismatch(4, 2))
+        ));
+
+        // the maximum number of proofs of each tree must be a power of N
+        let bad_cross = CrossTreeCircuit::<F,D,C,H, 4>::new(tree.get_node_verifier_data(), vd_hash, t, 4);
+        assert!(matches!(bad_cross.build_with_standard_config(), Err(CircuitError::RecursionTreeError(_))));
+
+        Ok(())
+    }
+
+    /// helper: checks that exactly the given indices are set in the flag buckets
+    fn check_flag_buckets_of(flag_buckets: &[F], indices: &[usize]) {
+        let mut expected = vec![0u64; flag_buckets.len()];
+        for &i in indices {
+            expected[i / BUCKET_SIZE] |= 1 << (i % BUCKET_SIZE);
+        }
+        let given: Vec<u64> = flag_buckets.iter().map(|f| f.to_canonical_u64()).collect();
+        assert_eq!(given, expected, "flag buckets mismatch");
     }
 
     #[test]
     fn test_dummy_tree_circuit() -> anyhow::Result<()> {
         const N: usize = 2;
-        const T: usize = 128;
+        // the leaf over the dummy inner circuit is smaller than the node,
+        // so the tree is built with a single node level
+        let t: usize = N;
+
+        let (data, proofs) = dummy_proofs(t);
 
-        let (data, proofs) = dummy_proofs::<T>();
+        // with more node levels the node can't verify node proofs with the leaf common data
+        match TreeRecursion::<F,D,C,H, N>::build_with_standard_config(data.verifier_data(), 128) {
+            Err(CircuitError::RecursionTreeError(e)) => assert!(e.contains("common data do not match"), "{}", e),
+            r => panic!("expected incompatible leaf and node common data, got {:?}", r.map(|_| ())),
+        }
 
-        let mut tree = TreeRecursion::<F,D,C,H, N, T>::build_with_standard_config(data.verifier_data())?;
+        let mut tree = TreeRecursion::<F,D,C,H, N>::build_with_standard_config(data.verifier_data(), t)?;
 
         // aggregate - no compression
         let root = tree.prove_tree(&proofs)?;
@@ -378,6 +1276,22 @@ mod tree_tests {
         Ok(())
     }
 
+    #[test]
+    fn test_tree_invalid_max_proofs() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let (data, _) = dummy_proofs(1);
+
+        // the maximum number of proofs must be a power of N and at least N
+        for t in [0, 1, 3, 6, 100] {
+            assert!(
+                TreeRecursion::<F,D,C,H, N>::build_with_standard_config(data.verifier_data(), t).is_err(),
+                "building the tree should fail for t = {}", t
+            );
+        }
+
+        Ok(())
+    }
+
 }
 
 
@@ -392,6 +1306,7 @@ mod bn254wrap_tests {
     use codex_plonky2_circuits::bn254_wrapper::config::PoseidonBN254GoldilocksConfig;
     use codex_plonky2_circuits::bn254_wrapper::wrap::{WrapCircuit, WrapInput, WrappedOutput};
     use codex_plonky2_circuits::circuit_trait::Plonky2Circuit;
+    use codex_plonky2_circuits::recursion::compress_chain::{compression_config, CompressionChain, CompressionTarget};
     use codex_plonky2_circuits::recursion::tree::TreeRecursion;
     use super::*;
 
@@ -503,18 +1418,18 @@ mod bn254wrap_tests {
         Ok(())
     }
 
-    fn run_tree_recursion<const N: usize, const T: usize>(compress: bool) -> anyhow::Result<()> {
+    fn run_tree_recursion<const N: usize>(t: usize, compress: bool) -> anyhow::Result<()> {
 
         //------------ sampling inner circuit ----------------------
         // Circuit that does the sampling - 100 samples
         let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
 
-        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..T).map(|_i| inner_proof.clone()).collect();
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..t).map(|_i| inner_proof.clone()).collect();
 
         // ------------------- tree --------------------
         // N-to-1 tree aggregation
 
-        let mut tree = TreeRecursion::<F, D,C,H, N, T>::build_with_standard_config(inner_verifier_data.clone())?;
+        let mut tree = TreeRecursion::<F, D,C,H, N>::build_with_standard_config(inner_verifier_data.clone(), t)?;
 
         // aggregate
         let root = if !compress {
@@ -542,16 +1457,52 @@ mod bn254wrap_tests {
     #[test]
     fn test_wrap_tree_recursion() -> anyhow::Result<()> {
         // total number of proofs to aggregate
-        const T:usize = 4;
-        run_tree_recursion::<2, T>(false)
+        let t: usize = 4;
+        run_tree_recursion::<2>(t, false)
     }
 
     #[test]
     fn test_wrap_tree_recursion_with_compression() -> anyhow::Result<()> {
         // total number of proofs to aggregate
-        const T:usize = 4;
-        run_tree_recursion::<2, T>(true)
+        let t: usize = 4;
+        run_tree_recursion::<2>(t, true)
+    }
+    #[test]
+    fn test_wrap_compression_chain() -> anyhow::Result<()> {
+        const N: usize = 2;
+        let t: usize = 2;
+        let (inner_proof, _inner_prover_data, inner_verifier_data) = run_sampling_circ()?;
+        let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..t).map(|_i| inner_proof.clone()).collect();
+
+        let mut tree = TreeRecursion::<F, D,C,H, N>::build_with_standard_config(inner_verifier_data, t)?;
+        let compressed = tree.prove_tree_and_compress(&proofs)?;
+        let compressed_bytes = compressed.to_bytes().len();
+
+        // the last round has a higher FRI rate and fewer query rounds
+        let configs = vec![CircuitConfig::standard_recursion_config(), compression_config(7, 12)];
+        let chain = CompressionChain::build(tree.get_compression_verifier_data(), configs.clone(), CompressionTarget::default())?;
+        assert_eq!(chain.num_rounds(), 2);
+        let output = chain.prove(compressed.clone())?;
+        chain.verify(&output)?;
+        assert_eq!(output.rounds, 2);
+        assert_eq!(output.proof.public_inputs, compressed.public_inputs);
+        assert_eq!(output.round_degree_bits, chain.degree_bits());
+        assert_eq!(output.round_proof_bytes.len(), 2);
+        assert_eq!(output.round_proof_bytes[1], output.proof.to_bytes().len());
+        println!("compressed proof size = {} bytes, chain proof sizes = {:?} bytes", compressed_bytes, output.round_proof_bytes);
+        assert!(output.proof.to_bytes().len() < compressed_bytes);
+
+        // stops after the first round that reaches the target
+        let target = CompressionTarget{ max_degree_bits: None, max_proof_bytes: Some(usize::MAX) };
+        let early = CompressionChain::build(tree.get_compression_verifier_data(), configs.clone(), target)?.prove(compressed.clone())?;
+        assert_eq!(early.rounds, 1);
+        assert_eq!(early.round_proof_bytes, output.round_proof_bytes[0..1]);
+        let target = CompressionTarget{ max_degree_bits: Some(usize::MAX), max_proof_bytes: None };
+        assert_eq!(CompressionChain::build(tree.get_compression_verifier_data(), configs, target)?.num_rounds(), 1);
+
+        bn254_wrap(output.proof, output.verifier_data)
     }
+
 }
 
 #[cfg(test)]
@@ -4,4 +4,6 @@ export MAX_DEPTH=32                # maximum depth of the slot tree
 export MAX_LOG2_N_SLOTS=8          # Depth of the dataset tree = ceiling_log2(max_slots)
 export BLOCK_TREE_DEPTH=5          # depth of the mini tree (block tree)
 export N_FIELD_ELEMS_PER_CELL=272  # number of field elements per cell
-export N_SAMPLES=100                 # number of samples to prove
\ No newline at end of file
+# export CELL_BYTES=2048          # optional: cells as raw bytes of this cell size, packed in-circuit
+export N_SAMPLES=100                 # number of samples to prove
+# export MAX_SAMPLES=160             # optional: variable number of samples up to MAX_SAMPLES, the number of samples is then public input
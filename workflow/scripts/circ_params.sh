@@ -4,6 +4,13 @@ export MAX_DEPTH=32                # maximum depth of the slot tree
 export MAX_LOG2_N_SLOTS=8          # Depth of the dataset tree = ceiling_log2(max_slots)
 export BLOCK_TREE_DEPTH=5          # depth of the mini tree (block tree)
 export N_FIELD_ELEMS_PER_CELL=272  # number of field elements per cell
+# export CELL_BYTES=2048          # optional: cells as raw bytes of this cell size, packed in-circuit
 export N_SAMPLES=100               # number of samples to prove
+# export MAX_SAMPLES=160           # optional: variable number of samples up to MAX_SAMPLES, the number of samples is then public input
 
-export T=4 # number of proofs to aggregate
\ No newline at end of file
+export T=4 # number of proofs to aggregate, any power of the arity
+# export ARITY=2 # optional: node arity (2, 4, 8 or 16), or auto to select it for ARITY_TARGET
+# export ARITY_TARGET=latency # optional: with ARITY=auto, latency or memory
+# export PROVERS=4 # optional: maximum number of provers combined by aggregate-trees, a power of the arity
+# export COMPRESS_ROUNDS=3:28,7:12 # optional: compression chain rounds <rate_bits>:<num_query_rounds>
+# export AGG_THREADS=8 # optional: number of threads for proving each tree level (with the parallel feature)
\ No newline at end of file
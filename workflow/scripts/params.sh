@@ -3,10 +3,13 @@
 export MAXDEPTH=32        # maximum depth of the slot tree
 export MAXSLOTS=256       # maximum number of slots
 export CELLSIZE=2048      # cell size in bytes
+# export CELLS_FROM_BYTES=true # optional: input the cells as CELLSIZE raw bytes, packed into field elements in-circuit
 export BLOCKSIZE=65536    # block size in bytes
 export NSAMPLES=100         # number of samples to prove
+# export MAXSAMPLES=160     # optional: build the circuit for a variable number of samples up to MAXSAMPLES
 
-export ENTROPY=1234567    # external randomness
+export ENTROPY=1234567    # external randomness, a decimal integer or 32 bytes in hex (e.g. a block hash)
+# export ENTROPY_FROM_BYTES=true # optional: derive the entropy in-circuit from its raw bytes
 export SEED=12345         # seed for creating fake data
 
 export NSLOTS=11          # number of slots in the dataset
@@ -11,16 +11,41 @@ Usage: $(basename "$0") [OPTIONS]
 
 OPTIONS:
   --gen-input             Generate witness inputs
+  --entropy <VALUE>       With --gen-input, the entropy as 32 bytes in hex (e.g. a block hash)
+                          or a decimal integer instead of ENTROPY in params.sh
   --build                 Compile/build the circuit
   --prove                 Run the prover
+  --select-arity          Print the estimated cost of each node arity for T proofs
   --aggregate             Aggregate proofs
   --aggregate-and-compress  Aggregate proofs and compress
+  --prove-public-input    With --aggregate-and-compress, also prove the public input
+                          verification circuit over the compressed proof
+  --commit-public-input   With --aggregate-and-compress, also prove the public input
+                          commitment circuit over the compressed proof
+  --proofs <PATH>         Aggregate the sampling proofs in PATH (a directory of sampling
+                          run outputs or a manifest file listing them) instead of
+                          duplicating the single sampling proof, with --verify-tree
+                          and --verify-compressed also check the tree public input against them
+  --quarantine            With --aggregate and --aggregate-and-compress, aggregate the sampling
+                          proofs that do not verify as dummy leaves instead of failing
+  --aggregate-trees <PATH>  Aggregate the tree root proofs of several provers in PATH (a directory
+                          of exported root proofs or a manifest file listing them)
+  --compress-chain        Compress the compressed proof further with a chain of compression
+                          rounds, see COMPRESS_ROUNDS in circ_params.sh
   --wrap-sampling         Wrap sampling proof
   --wrap-tree             Wrap tree proof
   --wrap-compress         Wrap compressed-tree proof
+  --wrap-public-input     Wrap public input verification proof
+  --wrap-compress-chain   Wrap compression chain proof
+  --wrap-cross-tree       Wrap cross-prover aggregated proof
   --verify-sampling       Verify sampling proof
   --verify-tree           Verify tree proof
   --verify-compressed     Verify compressed-tree proof
+  --verify-public-input   Verify public input verification proof
+  --verify-public-input-commitment  Verify public input commitment proof
+  --verify-compress-chain Verify compression chain proof
+  --verify-cross-tree     Verify cross-prover aggregated proof
+  --proven-indices        Print the indices of the sampling proofs covered by the tree proof
   --verify-wrapped        Verify wrapped proof
   --all                   Run the full pipeline in order
   -h, --help              Show this help and exit
@@ -30,23 +55,43 @@ EOF
 
 # operation flags
 DO_GEN=false DO_BUILD=false DO_PROVE=false DO_AGG=false DO_AGG_COMP=false
-DO_WRAP_SAMP=false DO_WRAP_TREE=false DO_WRAP_COMP=false
-DO_VER_SAMP=false DO_VER_TREE=false DO_VER_COMP=false DO_VER_WRAP=false
+DO_WRAP_SAMP=false DO_WRAP_TREE=false DO_WRAP_COMP=false DO_WRAP_PI=false
+DO_VER_SAMP=false DO_VER_TREE=false DO_VER_COMP=false DO_VER_PI=false DO_VER_WRAP=false
+DO_INDICES=false DO_ARITY=false
+DO_CHAIN=false DO_WRAP_CHAIN=false DO_VER_CHAIN=false
+DO_CROSS=false DO_WRAP_CROSS=false DO_VER_CROSS=false DO_VER_PI_COMMIT=false
+AGG_ARGS="" COMP_ARGS="" QUAR_ARGS="" GEN_ARGS="" ROOTS=""
 
 # parse args
 while [[ $# -gt 0 ]]; do
   case $1 in
     --gen-input)             DO_GEN=true; shift ;;
+    --entropy)               GEN_ARGS="--entropy $2"; shift 2 ;;
     --build)                 DO_BUILD=true; shift ;;
     --prove)                 DO_PROVE=true; shift ;;
+    --select-arity)          DO_ARITY=true; shift ;;
     --aggregate)             DO_AGG=true; shift ;;
     --aggregate-and-compress) DO_AGG_COMP=true; shift ;;
+    --prove-public-input)    COMP_ARGS="$COMP_ARGS --prove-public-input"; shift ;;
+    --commit-public-input)   COMP_ARGS="$COMP_ARGS --commit-public-input"; shift ;;
+    --proofs)                AGG_ARGS="--proofs $2"; shift 2 ;;
+    --quarantine)            QUAR_ARGS="--quarantine"; shift ;;
+    --aggregate-trees)       DO_CROSS=true; ROOTS="$2"; shift 2 ;;
+    --compress-chain)        DO_CHAIN=true; shift ;;
     --wrap-sampling)         DO_WRAP_SAMP=true; shift ;;
     --wrap-tree)             DO_WRAP_TREE=true; shift ;;
     --wrap-compress)         DO_WRAP_COMP=true; shift ;;
+    --wrap-public-input)     DO_WRAP_PI=true; shift ;;
+    --wrap-compress-chain)   DO_WRAP_CHAIN=true; shift ;;
+    --wrap-cross-tree)       DO_WRAP_CROSS=true; shift ;;
     --verify-sampling)       DO_VER_SAMP=true; shift ;;
     --verify-tree)           DO_VER_TREE=true; shift ;;
     --verify-compressed)     DO_VER_COMP=true; shift ;;
+    --verify-public-input)   DO_VER_PI=true; shift ;;
+    --verify-public-input-commitment) DO_VER_PI_COMMIT=true; shift ;;
+    --verify-compress-chain) DO_VER_CHAIN=true; shift ;;
+    --verify-cross-tree)     DO_VER_CROSS=true; shift ;;
+    --proven-indices)        DO_INDICES=true; shift ;;
     --verify-wrapped)        DO_VER_WRAP=true; shift ;;
     --all)
       DO_GEN=true; DO_BUILD=true; DO_PROVE=true
@@ -62,8 +107,10 @@ done
 
 # If nothing selected, show help
 if ! $DO_GEN && ! $DO_BUILD && ! $DO_PROVE && ! $DO_AGG && ! $DO_AGG_COMP \
-   && ! $DO_WRAP_SAMP && ! $DO_WRAP_TREE && ! $DO_WRAP_COMP \
-   && ! $DO_VER_SAMP && ! $DO_VER_TREE && ! $DO_VER_COMP && ! $DO_VER_WRAP; then
+   && ! $DO_WRAP_SAMP && ! $DO_WRAP_TREE && ! $DO_WRAP_COMP && ! $DO_WRAP_PI \
+   && ! $DO_VER_SAMP && ! $DO_VER_TREE && ! $DO_VER_COMP && ! $DO_VER_PI && ! $DO_VER_WRAP \
+   && ! $DO_INDICES && ! $DO_ARITY && ! $DO_CHAIN && ! $DO_WRAP_CHAIN && ! $DO_VER_CHAIN \
+   && ! $DO_CROSS && ! $DO_WRAP_CROSS && ! $DO_VER_CROSS && ! $DO_VER_PI_COMMIT; then
   echo "No stages selected."
   usage
 fi
@@ -82,17 +129,28 @@ run_cmd() {
   echo "[$name] Completed"
 }
 
-$DO_GEN        && run_cmd "GenInput"           gen-input
+$DO_GEN        && run_cmd "GenInput"           "gen-input $GEN_ARGS"
 $DO_BUILD      && run_cmd "Build"              build
 $DO_PROVE      && run_cmd "Prove"              prove
-$DO_AGG        && run_cmd "Aggregate"          aggregate
-$DO_AGG_COMP   && run_cmd "AggregateAndCompress" aggregate-and-compress
+$DO_ARITY      && run_cmd "SelectArity"        select-arity
+$DO_AGG        && run_cmd "Aggregate"          "aggregate $AGG_ARGS $QUAR_ARGS"
+$DO_AGG_COMP   && run_cmd "AggregateAndCompress" "aggregate-and-compress $AGG_ARGS $COMP_ARGS $QUAR_ARGS"
+$DO_CROSS      && run_cmd "AggregateTrees"     "aggregate-trees --roots $ROOTS"
+$DO_CHAIN      && run_cmd "CompressChain"      "compress-chain --rounds ${COMPRESS_ROUNDS:-3:28,7:12}"
 $DO_WRAP_SAMP  && run_cmd "WrapSampling"       wrap
 $DO_WRAP_TREE  && run_cmd "WrapTree"           wrap-tree
 $DO_WRAP_COMP  && run_cmd "WrapCompress"       wrap-compress
+$DO_WRAP_PI    && run_cmd "WrapPublicInput"    wrap-public-input
+$DO_WRAP_CHAIN && run_cmd "WrapCompressChain"  wrap-compress-chain
+$DO_WRAP_CROSS && run_cmd "WrapCrossTree"      wrap-cross-tree
 $DO_VER_SAMP   && run_cmd "VerifySampling"     verify
-$DO_VER_TREE   && run_cmd "VerifyTree"         verify-tree
-$DO_VER_COMP   && run_cmd "VerifyCompressed"   verify-compressed
+$DO_VER_TREE   && run_cmd "VerifyTree"         "verify-tree $AGG_ARGS"
+$DO_VER_COMP   && run_cmd "VerifyCompressed"   "verify-compressed $AGG_ARGS"
+$DO_VER_PI     && run_cmd "VerifyPublicInput"  verify-public-input
+$DO_VER_PI_COMMIT && run_cmd "VerifyPublicInputCommitment" verify-public-input-commitment
+$DO_VER_CHAIN  && run_cmd "VerifyCompressChain" verify-compress-chain
+$DO_VER_CROSS  && run_cmd "VerifyCrossTree"    verify-cross-tree
+$DO_INDICES    && run_cmd "ProvenIndices"      proven-indices
 $DO_VER_WRAP   && run_cmd "VerifyWrapped"      verify-wrapped
 
 echo "All requested steps done."
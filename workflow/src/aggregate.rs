@@ -1,60 +1,156 @@
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use plonky2::plonk::circuit_data::{CircuitConfig, VerifierCircuitData};
 use plonky2::plonk::proof::ProofWithPublicInputs;
+use codex_plonky2_circuits::recursion::arity::{select_arity, ArityEstimator, ArityTarget, SUPPORTED_ARITIES};
 use codex_plonky2_circuits::recursion::tree::TreeRecursion;
 use crate::params::{D, C, F, H};
-use codex_plonky2_circuits::serialization::{export_proof_with_pi, export_verifier_circuit_data, import_proof_with_pi, import_verifier_circuit_data};
-use crate::file_paths::{SAMPLING_CIRC_BASE_PATH, TREE_CIRC_BASE_PATH, COMPRESS_CIRC_BASE_PATH};
-pub fn run(compress: bool) -> Result<()> {
-    // load the parameters from environment variables
-    const N: usize = 2;
+use codex_plonky2_circuits::serialization::{export_proof_with_pi, export_verifier_circuit_data, import_proof_with_pi, import_verifier_circuit_data, VERIFIER_CIRC_DATA_JSON};
+use crate::file_paths::{SAMPLING_CIRC_BASE_PATH, TREE_CIRC_BASE_PATH, TREE_CIRCUITS_BASE_PATH, COMPRESS_CIRC_BASE_PATH, PI_COMMITMENT_CIRC_BASE_PATH, PI_VERIFIER_CIRC_BASE_PATH};
 
+/// aggregate the sampling proofs
+/// if `proofs_path` is given, the proofs are loaded from it (see `import_sampling_proofs`),
+/// otherwise the single sampling proof in `SAMPLING_CIRC_BASE_PATH` is duplicated `T` times.
+/// `T` is the maximum number of proofs the tree is built for, it must be a power of the arity.
+/// the node arity is read from `ARITY` (see `arity_from_env`), 2 by default.
+/// if `checkpoint_dir` is given, every leaf and node proof is checkpointed there and an interrupted run resumes from it.
+/// the tree circuits are loaded from `TREE_CIRCUITS_BASE_PATH` when they were built for the same sampling circuit, `T` and arity,
+/// otherwise they are built and exported there.
+/// if `prove_public_input` is true, the compressed proof is also proved with the public input verification circuit
+/// and the proof is written to `PI_VERIFIER_CIRC_BASE_PATH`.
+/// if `commit_public_input` is true, the compressed proof is also proved with the public input commitment circuit
+/// and the proof is written to `PI_COMMITMENT_CIRC_BASE_PATH`, see `verify::run_open_public_input`.
+/// if `quarantine` is true, the sampling proofs that do not verify are aggregated as dummy leaves
+/// instead of failing the aggregation, and their indices are printed.
+pub fn run(compress: bool, prove_public_input: bool, commit_public_input: bool, quarantine: bool, proofs_path: Option<PathBuf>, checkpoint_dir: Option<PathBuf>) -> Result<()> {
     // take k = "number of proofs" from env
     let t: usize = env::var("T")
         .context("T not set")?
         .parse::<usize>()
         .context("Invalid T")?;
 
-    match t {
-        2 => run_tree::<N,2>(compress)?,
-        4 => run_tree::<N, 4>(compress)?,
-        8 => run_tree::<N, 8>(compress)?,
-        16 => run_tree::<N, 16>(compress)?,
-        32 => run_tree::<N, 32>(compress)?,
-        64 => run_tree::<N, 64>(compress)?,
-        128 => run_tree::<N, 128>(compress)?,
-        256 => run_tree::<N, 256>(compress)?,
-        512 => run_tree::<N, 512>(compress)?,
-        1024 => run_tree::<N, 1024>(compress)?,
-        other => panic!("unsupported proof count: {}", other),
+    let arity = arity_from_env(t)?;
+    println!("node arity = {}", arity);
+
+    match arity {
+        2 => run_tree::<2>(t, compress, prove_public_input, commit_public_input, quarantine, proofs_path, checkpoint_dir)?,
+        4 => run_tree::<4>(t, compress, prove_public_input, commit_public_input, quarantine, proofs_path, checkpoint_dir)?,
+        8 => run_tree::<8>(t, compress, prove_public_input, commit_public_input, quarantine, proofs_path, checkpoint_dir)?,
+        16 => run_tree::<16>(t, compress, prove_public_input, commit_public_input, quarantine, proofs_path, checkpoint_dir)?,
+        _ => bail!("unsupported arity {}, expected one of {:?}", arity, SUPPORTED_ARITIES),
     }
 
     Ok(())
 }
 
-fn run_tree<const N: usize, const T: usize>(compress: bool) -> Result<()> {
-    let circuit_path = SAMPLING_CIRC_BASE_PATH;
-    // Read the proof
-    let proof_with_pi = import_proof_with_pi::<F,C,D,_>(circuit_path)?;
-    println!("Proof with public input imported from: {}", circuit_path);
+/// build the candidate node circuits for `T` proofs and print the degree bits, proofs per level
+/// and estimated cost of each arity, and the arity selected for latency and memory.
+pub fn run_select_arity() -> Result<()> {
+    let t: usize = env::var("T")
+        .context("T not set")?
+        .parse::<usize>()
+        .context("Invalid T")?;
+
+    let estimator = arity_estimator(t)?;
+    let estimates = estimator.estimate_all(&SUPPORTED_ARITIES)?;
+    for e in &estimates {
+        println!(
+            "arity {}: leaf degree bits = {}, node degree bits = {}, proofs per level = {:?}, total proofs = {}, latency cost = {}, memory cost = {}, compatible = {}",
+            e.arity, e.leaf_degree_bits, e.node_degree_bits, e.proofs_per_level, e.total_proofs(), e.latency_cost, e.memory_cost, e.compatible
+        );
+    }
+    for target in [ArityTarget::Latency, ArityTarget::Memory] {
+        println!("best arity for {:?}: {}", target, select_arity(&estimates, target)?.arity);
+    }
+
+    Ok(())
+}
 
+/// the node arity of the tree from the `ARITY` env var, 2 if not set.
+/// `ARITY=auto` builds the candidate node circuits and selects the best arity for
+/// `ARITY_TARGET` (`latency` or `memory`, `latency` if not set).
+fn arity_from_env(t: usize) -> Result<usize> {
+    let arity = env::var("ARITY").unwrap_or_else(|_| "2".to_string());
+    if arity != "auto" {
+        return arity.parse::<usize>().context("Invalid ARITY");
+    }
+    let target: ArityTarget = env::var("ARITY_TARGET")
+        .unwrap_or_else(|_| "latency".to_string())
+        .parse()?;
+
+    let start_time = Instant::now();
+    let selected = arity_estimator(t)?.select(target)?;
+    println!("arity selection time: {:?}", start_time.elapsed());
+    println!("selected arity {} for {:?}: {:?}", selected.arity, target, selected);
+    Ok(selected.arity)
+}
+
+fn arity_estimator(t: usize) -> Result<ArityEstimator<F, D, C, H>> {
+    let verifier_data = import_verifier_circuit_data::<F,C,D,_>(SAMPLING_CIRC_BASE_PATH)?;
+    Ok(ArityEstimator::new(verifier_data, CircuitConfig::standard_recursion_config(), t)?)
+}
+
+fn run_tree<const N: usize>(t: usize, compress: bool, prove_public_input: bool, commit_public_input: bool, quarantine: bool, proofs_path: Option<PathBuf>, checkpoint_dir: Option<PathBuf>) -> Result<()> {
+    let circuit_path = SAMPLING_CIRC_BASE_PATH;
     // read the circuit data
     let verifier_data = import_verifier_circuit_data::<F,C,D,_>(circuit_path)?;
     println!("Verifier circuit data imported from: {}", circuit_path);
 
-    // duplicate the proof to get k proofs
-    // this is just for testing - in real scenario we would need to load k proofs
-    let proofs: Vec<ProofWithPublicInputs<F, C, D>> = (0..T).map(|_i| proof_with_pi.clone()).collect();
+    let proofs: Vec<ProofWithPublicInputs<F, C, D>> = match proofs_path {
+        Some(path) => {
+            // with quarantine, the proofs are verified by the tree before aggregation
+            let proofs = import_sampling_proofs(&path, &verifier_data, !quarantine)?;
+            println!("{} proofs with public input imported from: {:?}", proofs.len(), path);
+            if proofs.is_empty() || proofs.len() > t {
+                bail!("expected between 1 and {} proofs to aggregate (T), found {} in {:?}", t, proofs.len(), path);
+            }
+            proofs
+        }
+        None => {
+            // Read the proof
+            let proof_with_pi = import_proof_with_pi::<F,C,D,_>(circuit_path)?;
+            println!("Proof with public input imported from: {}", circuit_path);
+
+            // duplicate the proof to get k proofs
+            // this is just for testing - use `proofs_path` to aggregate real proofs
+            (0..t).map(|_i| proof_with_pi.clone()).collect()
+        }
+    };
 
     let start_time = Instant::now();
-    let mut tree = TreeRecursion::<F,D,C,H, N, T>::build_with_standard_config(verifier_data.clone()).unwrap();
-    println!("build tree time: {:?}", start_time.elapsed());
+    let mut tree = TreeRecursion::<F,D,C,H, N>::load_or_build(
+        verifier_data.clone(),
+        CircuitConfig::standard_recursion_config(),
+        t,
+        TREE_CIRCUITS_BASE_PATH,
+    )?;
+    // optional: number of threads used to prove each level of the tree
+    if let Ok(threads) = env::var("AGG_THREADS") {
+        tree.set_num_threads(threads.parse::<usize>().context("Invalid AGG_THREADS")?)?;
+    }
+    tree.set_quarantine_invalid_proofs(quarantine);
+    if prove_public_input && !tree.has_public_input_verification() {
+        tree.build_public_input_verification()?;
+        tree.export_circuits(TREE_CIRCUITS_BASE_PATH)?;
+    }
+    if commit_public_input && !tree.has_public_input_commitment() {
+        tree.build_public_input_commitment()?;
+        tree.export_circuits(TREE_CIRCUITS_BASE_PATH)?;
+    }
+    println!("load/build tree time: {:?}", start_time.elapsed());
 
     let start_time = Instant::now();
-    let tree_proof = if !compress {
-        tree.prove_tree(&proofs)?
-    } else { tree.prove_tree_and_compress(&proofs)? };
+    let tree_proof = match checkpoint_dir {
+        Some(work_dir) => {
+            println!("Checkpoints in: {:?}", work_dir);
+            tree.prove_tree_with_checkpoints(&proofs, work_dir)?
+        }
+        None => tree.prove_tree(&proofs)?,
+    };
+    let tree_proof = if compress { tree.compress(tree_proof)? } else { tree_proof };
     println!("aggregate time: {:?}", start_time.elapsed());
 
     //export the proof to json file
@@ -68,9 +164,148 @@ fn run_tree<const N: usize, const T: usize>(compress: bool) -> Result<()> {
     let compression_ver_data = tree.get_compression_verifier_data();
     export_verifier_circuit_data(compression_ver_data, COMPRESS_CIRC_BASE_PATH)?;
 
-    let inner_pi: Vec<Vec<F>> = proofs.iter().map(|p| p.public_inputs.clone()).collect();
+    // the quarantined proofs are dummy leaves, i.e. not in the proven indices
+    let proven_indices = tree.get_proven_indices(&tree_proof.public_inputs)?;
+    let inner_pi: Vec<Option<Vec<F>>> = proofs.iter().enumerate()
+        .map(|(i, p)| proven_indices.contains(&i).then(|| p.public_inputs.clone()))
+        .collect();
+    let quarantined: Vec<usize> = (0..proofs.len()).filter(|i| !proven_indices.contains(i)).collect();
+    if !quarantined.is_empty() {
+        println!("{} invalid proofs quarantined as dummy leaves: {:?}", quarantined.len(), quarantined);
+    }
+
+    tree.verify_proof(tree_proof.clone(), compress)?;
+    tree.verify_public_input_with_dummies(tree_proof.public_inputs.clone(), inner_pi.clone())?;
+
+    if prove_public_input {
+        let start_time = Instant::now();
+        let pi_proof = tree.prove_public_input_verification(tree_proof.clone(), inner_pi.clone())?;
+        println!("public input verification time: {:?}", start_time.elapsed());
+        tree.verify_public_input_verification_proof(pi_proof.clone())?;
+
+        export_proof_with_pi(&pi_proof, PI_VERIFIER_CIRC_BASE_PATH)?;
+        export_verifier_circuit_data(tree.get_public_input_verification_verifier_data()?, PI_VERIFIER_CIRC_BASE_PATH)?;
+        println!("Public input verification proof written to: {}", PI_VERIFIER_CIRC_BASE_PATH);
+    }
+
+    if commit_public_input {
+        let start_time = Instant::now();
+        let commitment_proof = tree.prove_public_input_commitment(tree_proof, inner_pi)?;
+        println!("public input commitment time: {:?}", start_time.elapsed());
+        tree.verify_public_input_commitment_proof(commitment_proof.clone())?;
 
-    assert!(tree.verify_proof_and_public_input(tree_proof,inner_pi.clone(),compress).is_ok());
+        export_proof_with_pi(&commitment_proof, PI_COMMITMENT_CIRC_BASE_PATH)?;
+        export_verifier_circuit_data(tree.get_public_input_commitment_verifier_data()?, PI_COMMITMENT_CIRC_BASE_PATH)?;
+        println!("Public input commitment proof written to: {}", PI_COMMITMENT_CIRC_BASE_PATH);
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Import the sampling proofs to aggregate from `proofs_path`.
+///
+/// `proofs_path` is either:
+/// - a directory where each sub-directory is the output of one sampling run
+///   i.e. it contains `verifier_data/proof_with_public_inputs.json`, or
+/// - a manifest file listing one such sampling run directory per line
+///   (relative paths are resolved against the manifest's directory, empty lines and `#` comments are skipped).
+///
+/// The proofs are ordered by their run directory path, so the leaf index of each proof is stable
+/// across runs. Each proof is checked against the given sampling `verifier_data`:
+/// - if the run directory also contains the exported sampling verifier data,
+///   its circuit digest must match the digest of `verifier_data`.
+/// - the number of public inputs must match the sampling circuit.
+/// - the proof must verify with `verifier_data`, only if `verify` is true.
+pub fn import_sampling_proofs<P: AsRef<Path>>(
+    proofs_path: P,
+    verifier_data: &VerifierCircuitData<F, C, D>,
+    verify: bool,
+) -> Result<Vec<ProofWithPublicInputs<F, C, D>>> {
+    let proofs_path = proofs_path.as_ref();
+    let mut run_dirs = if proofs_path.is_dir() {
+        let mut dirs = vec![];
+        for entry in fs::read_dir(proofs_path)
+            .with_context(|| format!("Failed to read proofs directory {:?}", proofs_path))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            }
+        }
+        dirs
+    } else {
+        let manifest = fs::read_to_string(proofs_path)
+            .with_context(|| format!("Failed to read proofs manifest {:?}", proofs_path))?;
+        let manifest_dir = proofs_path.parent().unwrap_or(Path::new("."));
+        manifest
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| manifest_dir.join(line))
+            .collect()
+    };
+    if run_dirs.is_empty() {
+        bail!("no sampling proofs found in {:?}", proofs_path);
+    }
+    // stable order
+    run_dirs.sort();
+
+    let mut proofs = Vec::with_capacity(run_dirs.len());
+    for dir in run_dirs {
+        let proof = if verify {
+            import_checked_proof(&dir, verifier_data, "sampling")?
+        } else {
+            import_matching_proof(&dir, verifier_data, "sampling")?
+        };
+        proofs.push(proof);
+    }
+
+    Ok(proofs)
+}
+
+/// Import the proof in `dir` and check it against `verifier_data` of the `circuit_name` circuit:
+/// - if `dir` also contains the exported verifier data, its circuit digest must match the digest of `verifier_data`.
+/// - the number of public inputs must match the circuit.
+/// - the proof must verify with `verifier_data`.
+pub fn import_checked_proof(
+    dir: &Path,
+    verifier_data: &VerifierCircuitData<F, C, D>,
+    circuit_name: &str,
+) -> Result<ProofWithPublicInputs<F, C, D>> {
+    let proof = import_matching_proof(dir, verifier_data, circuit_name)?;
+    verifier_data.verify(proof.clone()).map_err(|e| anyhow::anyhow!(
+        "proof in {:?} does not verify with the {} circuit (digest {:?}): {}",
+        dir, circuit_name, verifier_data.verifier_only.circuit_digest.elements, e
+    ))?;
+
+    Ok(proof)
+}
+
+/// same as `import_checked_proof` without verifying the proof.
+fn import_matching_proof(
+    dir: &Path,
+    verifier_data: &VerifierCircuitData<F, C, D>,
+    circuit_name: &str,
+) -> Result<ProofWithPublicInputs<F, C, D>> {
+    let expected_digest = verifier_data.verifier_only.circuit_digest;
+    // check the circuit digest if the run also exported its verifier data
+    if dir.join(VERIFIER_CIRC_DATA_JSON).exists() {
+        let run_verifier_data = import_verifier_circuit_data::<F,C,D,_>(dir)?;
+        let digest = run_verifier_data.verifier_only.circuit_digest;
+        if digest != expected_digest {
+            bail!(
+                "proof in {:?} was made with circuit digest {:?}, expected {} circuit digest {:?}",
+                dir, digest.elements, circuit_name, expected_digest.elements
+            );
+        }
+    }
+
+    let proof = import_proof_with_pi::<F,C,D,_>(dir)?;
+    if proof.public_inputs.len() != verifier_data.common.num_public_inputs {
+        bail!(
+            "proof in {:?} has {} public inputs, expected {} for the {} circuit",
+            dir, proof.public_inputs.len(), verifier_data.common.num_public_inputs, circuit_name
+        );
+    }
+
+    Ok(proof)
+}
@@ -0,0 +1,53 @@
+use std::time::Instant;
+use anyhow::{Context, Result};
+use codex_plonky2_circuits::recursion::compress_chain::{compression_config, CompressionChain, CompressionTarget};
+use codex_plonky2_circuits::serialization::{export_proof_with_pi, export_verifier_circuit_data, import_proof_with_pi, import_verifier_circuit_data};
+use crate::params::{C, D, F};
+use crate::file_paths::{COMPRESS_CHAIN_CIRC_BASE_PATH, COMPRESS_CIRC_BASE_PATH};
+
+/// compress the compressed tree proof in `COMPRESS_CIRC_BASE_PATH` further with a chain of compression rounds,
+/// one round per `(rate_bits, num_query_rounds)` in `rounds`.
+/// the chain stops after the first round that reaches `target`, the proof of that round and its verifier data
+/// are written to `COMPRESS_CHAIN_CIRC_BASE_PATH`, ready to be wrapped.
+pub fn run(rounds: Vec<(usize, usize)>, target: CompressionTarget) -> Result<()> {
+    let proof_with_pi = import_proof_with_pi::<F,C,D,_>(COMPRESS_CIRC_BASE_PATH)?;
+    let verifier_data = import_verifier_circuit_data::<F,C,D,_>(COMPRESS_CIRC_BASE_PATH)?;
+    println!("Compressed proof imported from: {}", COMPRESS_CIRC_BASE_PATH);
+    println!("compressed proof size = {} bytes", proof_with_pi.to_bytes().len());
+
+    let configs = rounds.iter()
+        .map(|&(rate_bits, num_query_rounds)| compression_config(rate_bits, num_query_rounds))
+        .collect();
+
+    let start_time = Instant::now();
+    let chain = CompressionChain::build(verifier_data, configs, target)?;
+    println!("build compression chain time: {:?}", start_time.elapsed());
+    for (i, degree_bits) in chain.degree_bits().iter().enumerate() {
+        println!("compression round {} circuit size = {}", i, degree_bits);
+    }
+
+    let start_time = Instant::now();
+    let output = chain.prove(proof_with_pi)?;
+    println!("compression chain time: {:?}", start_time.elapsed());
+    chain.verify(&output)?;
+    for (i, (degree_bits, proof_bytes)) in output.round_degree_bits.iter().zip(&output.round_proof_bytes).enumerate() {
+        println!("compression round {}: circuit size = {}, proof size = {} bytes", i, degree_bits, proof_bytes);
+    }
+    println!("{} of {} rounds applied, proof size = {} bytes", output.rounds, chain.num_rounds(), output.proof.to_bytes().len());
+
+    export_proof_with_pi(&output.proof, COMPRESS_CHAIN_CIRC_BASE_PATH)?;
+    export_verifier_circuit_data(output.verifier_data, COMPRESS_CHAIN_CIRC_BASE_PATH)?;
+    println!("Compression chain proof written to: {}", COMPRESS_CHAIN_CIRC_BASE_PATH);
+
+    Ok(())
+}
+
+/// parses a compression round `<rate_bits>:<num_query_rounds>`, e.g. `7:12`
+pub fn parse_round(round: &str) -> Result<(usize, usize)> {
+    let (rate_bits, num_query_rounds) = round.split_once(':')
+        .with_context(|| format!("Invalid compression round {}, expected <rate_bits>:<num_query_rounds>", round))?;
+    Ok((
+        rate_bits.trim().parse().context("Invalid rate_bits")?,
+        num_query_rounds.trim().parse().context("Invalid num_query_rounds")?,
+    ))
+}
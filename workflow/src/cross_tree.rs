@@ -0,0 +1,158 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use anyhow::{bail, Context, Result};
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use codex_plonky2_circuits::circuit_trait::Plonky2Circuit;
+use codex_plonky2_circuits::recursion::arity::SUPPORTED_ARITIES;
+use codex_plonky2_circuits::recursion::cross_tree::{CrossTreeCircuit, CrossTreeInput};
+use codex_plonky2_circuits::recursion::tree::{exported_arity, LEAF_CIRC_DIR};
+use codex_plonky2_circuits::recursion::utils::{get_hash_of_verifier_data, get_proven_indices};
+use codex_plonky2_circuits::serialization::{export_proof_with_pi, export_verifier_circuit_data, import_verifier_circuit_data};
+use crate::aggregate::import_checked_proof;
+use crate::params::{C, D, F, H};
+use crate::file_paths::{COMPRESS_CIRC_BASE_PATH, CROSS_TREE_CIRC_BASE_PATH, TREE_CIRC_BASE_PATH, TREE_CIRCUITS_BASE_PATH};
+
+/// aggregate the tree root proofs of several provers into one proof over all their slots.
+/// `roots_path` is either a directory where each sub-directory is the exported tree (or compressed) proof of
+/// one prover, ordered by path, or a manifest file listing one such directory per line where `-` marks a missing prover
+/// (see `import_roots`). the root at position `i` covers the slots [`i` * T, (`i` + 1) * T - 1].
+/// `T` is the maximum number of proofs of each prover's tree, the arity is the one of the exported tree circuits,
+/// all provers must use the same tree circuits as the ones in `TREE_CIRCUITS_BASE_PATH`.
+/// `PROVERS` is the maximum number of provers, a power of the arity, by default the smallest one that fits the roots.
+/// the proof and its verifier data are written to `CROSS_TREE_CIRC_BASE_PATH`.
+pub fn run(roots_path: PathBuf, compressed: bool) -> Result<()> {
+    let t: usize = env::var("T")
+        .context("T not set")?
+        .parse::<usize>()
+        .context("Invalid T")?;
+
+    match exported_arity(TREE_CIRCUITS_BASE_PATH)? {
+        2 => run_with_arity::<2>(t, roots_path, compressed),
+        4 => run_with_arity::<4>(t, roots_path, compressed),
+        8 => run_with_arity::<8>(t, roots_path, compressed),
+        16 => run_with_arity::<16>(t, roots_path, compressed),
+        arity => bail!("unsupported arity {}, expected one of {:?}", arity, SUPPORTED_ARITIES),
+    }
+}
+
+fn run_with_arity<const N: usize>(t: usize, roots_path: PathBuf, compressed: bool) -> Result<()> {
+    // the root proofs are node (or compression) proofs
+    let (circuit_path, circuit_name) = if compressed {
+        (COMPRESS_CIRC_BASE_PATH, "compression")
+    } else {
+        (TREE_CIRC_BASE_PATH, "node")
+    };
+    let verifier_data = import_verifier_circuit_data::<F,C,D,_>(circuit_path)?;
+    println!("Verifier circuit data imported from: {}", circuit_path);
+
+    // the root of a tree with a single node level carries the leaf verifier data hash
+    let vd_hash = if t == N {
+        let leaf_path = Path::new(TREE_CIRCUITS_BASE_PATH).join(LEAF_CIRC_DIR);
+        get_hash_of_verifier_data::<F,D,C,H>(&import_verifier_circuit_data::<F,C,D,_>(leaf_path)?)
+    } else {
+        get_hash_of_verifier_data::<F,D,C,H>(&import_verifier_circuit_data::<F,C,D,_>(TREE_CIRC_BASE_PATH)?)
+    };
+
+    let roots = import_roots(&roots_path, &verifier_data, circuit_name)?;
+    let p = match env::var("PROVERS") {
+        Ok(p) => p.parse::<usize>().context("Invalid PROVERS")?,
+        Err(_) => {
+            let mut p = N;
+            while p < roots.len() {
+                p *= N;
+            }
+            p
+        }
+    };
+    if roots.len() > p {
+        bail!("expected at most {} root proofs (PROVERS), found {} in {:?}", p, roots.len(), roots_path);
+    }
+    println!("{} of {} root proofs imported from: {:?}", roots.iter().flatten().count(), p, roots_path);
+
+    let start_time = Instant::now();
+    let circuit = CrossTreeCircuit::<F,D,C,H,N>::new(verifier_data, vd_hash, t, p);
+    let (targets, circ_data) = circuit.build_with_standard_config()?;
+    println!("build cross tree circuit time: {:?}", start_time.elapsed());
+    println!("cross tree circuit size = {:?}", circ_data.common.degree_bits());
+    let cross_verifier_data = circ_data.verifier_data();
+    let prover_data = circ_data.prover_data();
+
+    // fill the missing provers with dummy roots
+    let dummy = circuit.dummy_proof()?;
+    let mut input = CrossTreeInput { inner_proofs: vec![], flags: vec![] };
+    for i in 0..p {
+        match roots.get(i).cloned().flatten() {
+            Some(root) => {
+                input.inner_proofs.push(root);
+                input.flags.push(true);
+            }
+            None => {
+                input.inner_proofs.push(dummy.clone());
+                input.flags.push(false);
+            }
+        }
+    }
+
+    let start_time = Instant::now();
+    let proof = circuit.prove(&targets, &input, &prover_data)?;
+    println!("cross tree aggregation time: {:?}", start_time.elapsed());
+    cross_verifier_data.verify(proof.clone())
+        .map_err(|e| anyhow::anyhow!("cross tree proof is NOT VALID: {}", e))?;
+    println!("proven indices: {:?}", get_proven_indices(&proof.public_inputs, circuit.max_proofs())?);
+
+    export_proof_with_pi(&proof, CROSS_TREE_CIRC_BASE_PATH)?;
+    export_verifier_circuit_data(cross_verifier_data, CROSS_TREE_CIRC_BASE_PATH)?;
+    println!("Cross tree proof written to: {}", CROSS_TREE_CIRC_BASE_PATH);
+
+    Ok(())
+}
+
+/// Import the tree root proofs to aggregate from `roots_path`, `None` for a missing prover.
+///
+/// `roots_path` is either:
+/// - a directory where each sub-directory contains the exported root proof of one prover
+///   i.e. `proof_with_public_inputs.json`, ordered by path, or
+/// - a manifest file listing one such directory per line, or `-` for a missing prover
+///   (relative paths are resolved against the manifest's directory, empty lines and `#` comments are skipped).
+///
+/// Each root proof is checked against `verifier_data` of the `circuit_name` circuit (see `aggregate::import_checked_proof`).
+fn import_roots(
+    roots_path: &Path,
+    verifier_data: &VerifierCircuitData<F, C, D>,
+    circuit_name: &str,
+) -> Result<Vec<Option<ProofWithPublicInputs<F, C, D>>>> {
+    let root_dirs: Vec<Option<PathBuf>> = if roots_path.is_dir() {
+        let mut dirs = vec![];
+        for entry in fs::read_dir(roots_path)
+            .with_context(|| format!("Failed to read roots directory {:?}", roots_path))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            }
+        }
+        // stable order
+        dirs.sort();
+        dirs.into_iter().map(Some).collect()
+    } else {
+        let manifest = fs::read_to_string(roots_path)
+            .with_context(|| format!("Failed to read roots manifest {:?}", roots_path))?;
+        let manifest_dir = roots_path.parent().unwrap_or(Path::new("."));
+        manifest
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| if line == "-" { None } else { Some(manifest_dir.join(line)) })
+            .collect()
+    };
+    if root_dirs.iter().flatten().next().is_none() {
+        bail!("no root proofs found in {:?}", roots_path);
+    }
+
+    root_dirs
+        .iter()
+        .map(|dir| dir.as_ref().map(|dir| import_checked_proof(dir, verifier_data, circuit_name)).transpose())
+        .collect()
+}
@@ -1,4 +1,9 @@
 pub(crate) const SAMPLING_CIRC_BASE_PATH: &str = "../output/sampling_circuit/";
 pub(crate) const TREE_CIRC_BASE_PATH: &str = "../output/tree/";
+pub(crate) const TREE_CIRCUITS_BASE_PATH: &str = "../output/tree_circuits/";
 pub(crate) const COMPRESS_CIRC_BASE_PATH: &str = "../output/compression/";
+pub(crate) const COMPRESS_CHAIN_CIRC_BASE_PATH: &str = "../output/compression_chain/";
+pub(crate) const CROSS_TREE_CIRC_BASE_PATH: &str = "../output/cross_tree/";
+pub(crate) const PI_VERIFIER_CIRC_BASE_PATH: &str = "../output/pi_verifier/";
+pub(crate) const PI_COMMITMENT_CIRC_BASE_PATH: &str = "../output/pi_commitment/";
 pub(crate) const WRAP_CIRC_BASE_PATH: &str = "../output/wrap/";
\ No newline at end of file
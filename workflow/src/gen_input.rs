@@ -2,13 +2,17 @@ use std::time::Instant;
 use anyhow::Result;
 use proof_input::input_generator::serialization::export_circ_input_to_json;
 use proof_input::input_generator::InputGenerator;
+use proof_input::input_generator::entropy::parse_entropy;
 use proof_input::params::Params;
 use crate::params::{D, H, F};
 use crate::file_paths::SAMPLING_CIRC_BASE_PATH;
 
-pub fn run() -> Result<()> {
+pub fn run(entropy: Option<String>) -> Result<()> {
     // Load the parameters from environment variables
-    let params = Params::from_env()?;
+    let mut params = Params::from_env()?;
+    if let Some(entropy) = entropy {
+        params.input_params.entropy = parse_entropy(&entropy)?;
+    }
 
     // generate circuit input with given parameters
     let start_time = Instant::now();
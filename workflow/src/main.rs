@@ -1,7 +1,9 @@
+use std::path::PathBuf;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use codex_plonky2_circuits::bn254_wrapper::config::PoseidonBN254GoldilocksConfig;
-use crate::file_paths::{COMPRESS_CIRC_BASE_PATH, SAMPLING_CIRC_BASE_PATH, TREE_CIRC_BASE_PATH, WRAP_CIRC_BASE_PATH};
+use codex_plonky2_circuits::recursion::compress_chain::CompressionTarget;
+use crate::file_paths::{COMPRESS_CHAIN_CIRC_BASE_PATH, COMPRESS_CIRC_BASE_PATH, CROSS_TREE_CIRC_BASE_PATH, PI_COMMITMENT_CIRC_BASE_PATH, PI_VERIFIER_CIRC_BASE_PATH, SAMPLING_CIRC_BASE_PATH, TREE_CIRC_BASE_PATH, WRAP_CIRC_BASE_PATH};
 use crate::params::C;
 
 type OuterParameters = PoseidonBN254GoldilocksConfig;
@@ -11,6 +13,8 @@ mod prove;
 mod verify;
 mod gen_input;
 mod aggregate;
+mod compress_chain;
+mod cross_tree;
 mod bn254_wrap;
 mod file_paths;
 mod params;
@@ -26,27 +30,122 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Generate witness inputs
-    GenInput,
+    GenInput {
+        /// the entropy as 32 bytes in hex (e.g. a block hash) or a decimal integer, overrides `ENTROPY`
+        #[arg(long)]
+        entropy: Option<String>,
+    },
     /// build the circuit
     Build,
     /// Run the prover
     Prove,
     /// Aggregate proofs
-    Aggregate,
+    Aggregate {
+        /// directory of sampling run outputs (or a manifest file listing them) to aggregate
+        #[arg(long)]
+        proofs: Option<PathBuf>,
+        /// work directory for the leaf and node proof checkpoints, an interrupted run resumes from it
+        #[arg(long)]
+        checkpoint_dir: Option<PathBuf>,
+        /// aggregate the sampling proofs that do not verify as dummy leaves instead of failing
+        #[arg(long)]
+        quarantine: bool,
+    },
     /// Aggregate and compress proofs
-    AggregateAndCompress,
+    AggregateAndCompress {
+        /// directory of sampling run outputs (or a manifest file listing them) to aggregate
+        #[arg(long)]
+        proofs: Option<PathBuf>,
+        /// work directory for the leaf and node proof checkpoints, an interrupted run resumes from it
+        #[arg(long)]
+        checkpoint_dir: Option<PathBuf>,
+        /// also prove the public input verification circuit over the compressed proof,
+        /// its public input is the raw public input of all sampling proofs and the verifier data hash
+        #[arg(long)]
+        prove_public_input: bool,
+        /// also prove the public input commitment circuit over the compressed proof,
+        /// its public input is the Merkle root of the public input hashes of all sampling proofs
+        #[arg(long)]
+        commit_public_input: bool,
+        /// aggregate the sampling proofs that do not verify as dummy leaves instead of failing
+        #[arg(long)]
+        quarantine: bool,
+    },
+    /// Compress the compressed proof further with a chain of compression rounds
+    CompressChain {
+        /// comma separated compression rounds `<rate_bits>:<num_query_rounds>`, applied in order
+        #[arg(long, value_delimiter = ',', value_parser = compress_chain::parse_round, default_value = "3:28,7:12")]
+        rounds: Vec<(usize, usize)>,
+        /// stop after the first round whose circuit has at most this many degree bits
+        #[arg(long)]
+        target_degree_bits: Option<usize>,
+        /// stop after the first round whose proof has at most this many bytes
+        #[arg(long)]
+        target_proof_bytes: Option<usize>,
+    },
+    /// Aggregate the tree root proofs of several provers into one proof over all their slots
+    AggregateTrees {
+        /// directory of the provers' exported root proofs (or a manifest file listing them, `-` for a missing prover)
+        #[arg(long)]
+        roots: PathBuf,
+        /// the root proofs are compressed tree proofs
+        #[arg(long)]
+        compressed: bool,
+    },
+    /// Build the node circuit for each supported arity and print its estimated cost for `T` proofs
+    SelectArity,
     /// Wrap sampling proof
     Wrap,
     /// Wrap aggregated tree proof
     WrapTree,
     /// Wrap compressed proof
     WrapCompress,
+    /// Wrap compression chain proof
+    WrapCompressChain,
+    /// Wrap cross-prover aggregated proof
+    WrapCrossTree,
+    /// Wrap public input verification proof
+    WrapPublicInput,
     /// Verify a sampling proof
     Verify,
     /// Verify a tree proof
-    VerifyTree,
+    VerifyTree {
+        /// directory of the aggregated sampling run outputs (or a manifest file listing them) to check the public input against
+        #[arg(long)]
+        proofs: Option<PathBuf>,
+    },
     /// Verify a compressed tree proof
-    VerifyCompressed,
+    VerifyCompressed {
+        /// directory of the aggregated sampling run outputs (or a manifest file listing them) to check the public input against
+        #[arg(long)]
+        proofs: Option<PathBuf>,
+    },
+    /// Verify a compression chain proof
+    VerifyCompressChain,
+    /// Verify a cross-prover aggregated proof
+    VerifyCrossTree,
+    /// Verify a public input verification proof
+    VerifyPublicInput,
+    /// Verify a public input commitment proof
+    VerifyPublicInputCommitment,
+    /// Check that one aggregated sampling proof is committed in the public input commitment proof
+    OpenPublicInput {
+        /// directory of the aggregated sampling run outputs (or a manifest file listing them)
+        #[arg(long)]
+        proofs: PathBuf,
+        /// index of the sampling proof to open
+        #[arg(long)]
+        index: usize,
+    },
+    /// Print the indices of the real sampling proofs in a tree (or compressed) proof
+    ProvenIndices {
+        /// read the compressed tree proof instead of the tree proof
+        #[arg(long)]
+        compressed: bool,
+        /// comma separated indices the proof must cover exactly, e.g. `0,1,5`
+        #[arg(long, value_delimiter = ',')]
+        expected: Option<Vec<usize>>,
+    },
     /// Verify a wrapped proof
     VerifyWrapped,
 }
@@ -55,17 +154,31 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::GenInput   => gen_input::run()?,
+        Commands::GenInput { entropy } => gen_input::run(entropy)?,
         Commands::Build      => build_circ::run()?,
         Commands::Prove      => prove::run()?,
-        Commands::Aggregate  => aggregate::run(false)?,
-        Commands::AggregateAndCompress  => aggregate::run(true)?,
+        Commands::Aggregate { proofs, checkpoint_dir, quarantine } => aggregate::run(false, false, false, quarantine, proofs, checkpoint_dir)?,
+        Commands::AggregateAndCompress { proofs, checkpoint_dir, prove_public_input, commit_public_input, quarantine } =>
+            aggregate::run(true, prove_public_input, commit_public_input, quarantine, proofs, checkpoint_dir)?,
+        Commands::CompressChain { rounds, target_degree_bits, target_proof_bytes } =>
+            compress_chain::run(rounds, CompressionTarget{ max_degree_bits: target_degree_bits, max_proof_bytes: target_proof_bytes })?,
+        Commands::AggregateTrees { roots, compressed } => cross_tree::run(roots, compressed)?,
+        Commands::SelectArity => aggregate::run_select_arity()?,
         Commands::Wrap       => bn254_wrap::run(SAMPLING_CIRC_BASE_PATH)?,
         Commands::WrapTree   => bn254_wrap::run(TREE_CIRC_BASE_PATH)?,
         Commands::WrapCompress => bn254_wrap::run(COMPRESS_CIRC_BASE_PATH)?,
+        Commands::WrapCompressChain => bn254_wrap::run(COMPRESS_CHAIN_CIRC_BASE_PATH)?,
+        Commands::WrapCrossTree => bn254_wrap::run(CROSS_TREE_CIRC_BASE_PATH)?,
+        Commands::WrapPublicInput => bn254_wrap::run(PI_VERIFIER_CIRC_BASE_PATH)?,
         Commands::Verify     => verify::run::<C>(SAMPLING_CIRC_BASE_PATH)?,
-        Commands::VerifyTree => verify::run::<C>(TREE_CIRC_BASE_PATH)?,
-        Commands::VerifyCompressed => verify::run::<C>(COMPRESS_CIRC_BASE_PATH)?,
+        Commands::VerifyTree { proofs } => verify::run_tree(false, proofs)?,
+        Commands::VerifyCompressed { proofs } => verify::run_tree(true, proofs)?,
+        Commands::VerifyCompressChain => verify::run::<C>(COMPRESS_CHAIN_CIRC_BASE_PATH)?,
+        Commands::VerifyCrossTree => verify::run::<C>(CROSS_TREE_CIRC_BASE_PATH)?,
+        Commands::VerifyPublicInput => verify::run::<C>(PI_VERIFIER_CIRC_BASE_PATH)?,
+        Commands::VerifyPublicInputCommitment => verify::run::<C>(PI_COMMITMENT_CIRC_BASE_PATH)?,
+        Commands::OpenPublicInput { proofs, index } => verify::run_open_public_input(proofs, index)?,
+        Commands::ProvenIndices { compressed, expected } => verify::run_proven_indices(compressed, expected)?,
         Commands::VerifyWrapped => verify::run::<OuterParameters>(WRAP_CIRC_BASE_PATH)?,
     }
 
@@ -1,9 +1,19 @@
+use std::env;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use plonky2::plonk::config::GenericConfig;
+use plonky2::hash::hash_types::HashOut;
+use plonky2::field::types::PrimeField64;
 use serde::Serialize;
-use crate::params::{D, F};
+use crate::params::{C, D, F, H};
+use codex_plonky2_circuits::recursion::arity::SUPPORTED_ARITIES;
+use codex_plonky2_circuits::recursion::tree::{exported_arity, LEAF_CIRC_DIR};
+use codex_plonky2_circuits::recursion::verifier::TreeVerifier;
 use codex_plonky2_circuits::serialization::{import_proof_with_pi, import_verifier_circuit_data};
+use proof_input::merkle_tree::pi_commitment::{get_public_input_opening, verify_public_input_opening};
+use crate::aggregate::import_sampling_proofs;
+use crate::file_paths::{COMPRESS_CIRC_BASE_PATH, PI_COMMITMENT_CIRC_BASE_PATH, SAMPLING_CIRC_BASE_PATH, TREE_CIRC_BASE_PATH, TREE_CIRCUITS_BASE_PATH};
 
 pub fn run<
     // F: RichField + Extendable<D> + Poseidon2 + Serialize,
@@ -25,3 +35,125 @@ pub fn run<
 
     Ok(())
 }
+
+/// verify the tree proof (or compressed tree proof) with the exported verifier data only.
+/// if `proofs_path` is given (see `aggregate::import_sampling_proofs`), the public input of the tree proof
+/// is also checked against the public input of the aggregated sampling proofs.
+/// `T` is the maximum number of proofs the tree was built for, the arity is the one of the exported tree circuits.
+pub fn run_tree(compressed: bool, proofs_path: Option<PathBuf>) -> Result<()> {
+    let t: usize = env::var("T")
+        .context("T not set")?
+        .parse::<usize>()
+        .context("Invalid T")?;
+
+    match exported_arity(TREE_CIRCUITS_BASE_PATH)? {
+        2 => run_tree_with_arity::<2>(t, compressed, proofs_path),
+        4 => run_tree_with_arity::<4>(t, compressed, proofs_path),
+        8 => run_tree_with_arity::<8>(t, compressed, proofs_path),
+        16 => run_tree_with_arity::<16>(t, compressed, proofs_path),
+        arity => bail!("unsupported arity {}, expected one of {:?}", arity, SUPPORTED_ARITIES),
+    }
+}
+
+fn run_tree_with_arity<const N: usize>(t: usize, compressed: bool, proofs_path: Option<PathBuf>) -> Result<()> {
+    let mut verifier = TreeVerifier::<F,D,C,H,N>::load(TREE_CIRC_BASE_PATH, COMPRESS_CIRC_BASE_PATH, t)?;
+    if t == N {
+        // the root of the tree is a node over the leaves, which carries the leaf verifier data
+        let leaf_path = Path::new(TREE_CIRCUITS_BASE_PATH).join(LEAF_CIRC_DIR);
+        verifier = verifier.with_leaf_verifier_data(import_verifier_circuit_data::<F,C,D,_>(leaf_path)?);
+    }
+    let circuit_path = if compressed { COMPRESS_CIRC_BASE_PATH } else { TREE_CIRC_BASE_PATH };
+    let proof_with_pi = import_proof_with_pi::<F,C,D,_>(circuit_path)?;
+    println!("Proof with public input imported from: {}", circuit_path);
+
+    let start_time = Instant::now();
+    match proofs_path {
+        Some(path) => {
+            let sampling_verifier_data = import_verifier_circuit_data::<F,C,D,_>(SAMPLING_CIRC_BASE_PATH)?;
+            let proofs = import_sampling_proofs(&path, &sampling_verifier_data, true)?;
+            let inner_pi: Vec<Vec<F>> = proofs.iter().map(|p| p.public_inputs.clone()).collect();
+            verifier.verify_proof_and_public_input(proof_with_pi, inner_pi, compressed)?;
+            println!("public input matches the {} proofs in {:?}", proofs.len(), path);
+        }
+        None => verifier.verify_proof(proof_with_pi, compressed)?,
+    }
+    println!("Verifying time: {:?}", start_time.elapsed());
+
+    Ok(())
+}
+
+/// verify the tree proof (or compressed tree proof) and print the indices of the real sampling proofs it covers,
+/// decoded from its flag buckets. if `expected` is given, the proof must cover exactly these indices.
+/// `T` is the maximum number of proofs the tree was built for, the arity is the one of the exported tree circuits.
+pub fn run_proven_indices(compressed: bool, expected: Option<Vec<usize>>) -> Result<()> {
+    let t: usize = env::var("T")
+        .context("T not set")?
+        .parse::<usize>()
+        .context("Invalid T")?;
+
+    match exported_arity(TREE_CIRCUITS_BASE_PATH)? {
+        2 => run_proven_indices_with_arity::<2>(t, compressed, expected),
+        4 => run_proven_indices_with_arity::<4>(t, compressed, expected),
+        8 => run_proven_indices_with_arity::<8>(t, compressed, expected),
+        16 => run_proven_indices_with_arity::<16>(t, compressed, expected),
+        arity => bail!("unsupported arity {}, expected one of {:?}", arity, SUPPORTED_ARITIES),
+    }
+}
+
+fn run_proven_indices_with_arity<const N: usize>(t: usize, compressed: bool, expected: Option<Vec<usize>>) -> Result<()> {
+    let verifier = TreeVerifier::<F,D,C,H,N>::load(TREE_CIRC_BASE_PATH, COMPRESS_CIRC_BASE_PATH, t)?;
+    let circuit_path = if compressed { COMPRESS_CIRC_BASE_PATH } else { TREE_CIRC_BASE_PATH };
+    let proof_with_pi = import_proof_with_pi::<F,C,D,_>(circuit_path)?;
+    println!("Proof with public input imported from: {}", circuit_path);
+
+    let public_input = proof_with_pi.public_inputs.clone();
+    verifier.verify_proof(proof_with_pi, compressed)?;
+
+    let indices = verifier.get_proven_indices(&public_input)?;
+    println!("{} proven indices: {:?}", indices.len(), indices);
+    if let Some(expected) = expected {
+        verifier.verify_proven_indices(&public_input, &expected)?;
+        println!("proven indices match the expected indices");
+    }
+
+    Ok(())
+}
+
+/// check that the sampling proof at `index` of the aggregated proofs in `proofs_path` (see `aggregate::import_sampling_proofs`)
+/// is committed in the public input commitment proof in `PI_COMMITMENT_CIRC_BASE_PATH`:
+/// verifies the commitment proof, then the Merkle opening of its full public input:
+/// the slot index, dataset root and entropy, and the optional entropy bytes and number of active samples.
+/// `T` is the maximum number of proofs the tree was built for.
+pub fn run_open_public_input(proofs_path: PathBuf, index: usize) -> Result<()> {
+    let t: usize = env::var("T")
+        .context("T not set")?
+        .parse::<usize>()
+        .context("Invalid T")?;
+
+    let verifier_data = import_verifier_circuit_data::<F,C,D,_>(PI_COMMITMENT_CIRC_BASE_PATH)?;
+    let commitment_proof = import_proof_with_pi::<F,C,D,_>(PI_COMMITMENT_CIRC_BASE_PATH)?;
+    println!("Proof with public input imported from: {}", PI_COMMITMENT_CIRC_BASE_PATH);
+    verifier_data.verify(commitment_proof.clone())
+        .map_err(|e| anyhow::anyhow!("public input commitment proof is NOT VALID: {}", e))?;
+
+    let sampling_verifier_data = import_verifier_circuit_data::<F,C,D,_>(SAMPLING_CIRC_BASE_PATH)?;
+    let proofs = import_sampling_proofs(&proofs_path, &sampling_verifier_data, true)?;
+    let inner_pi: Vec<Option<Vec<F>>> = proofs.iter().map(|p| Some(p.public_inputs.clone())).collect();
+    let opening = get_public_input_opening::<F,D,H>(&inner_pi, t, index)?;
+
+    // the sampling public input: [slot_index, dataset_root, entropy, ...],
+    // the whole of it is opened since its length depends on the circuit params
+    let pub_in = proofs.get(index)
+        .ok_or_else(|| anyhow::anyhow!("no sampling proof at index {}", index))?
+        .public_inputs.as_slice();
+    verify_public_input_opening(&commitment_proof.public_inputs, pub_in, &opening)?;
+    let slot_index = pub_in[0].to_canonical_u64() as usize;
+    let dataset_root = HashOut::<F>::from_partial(&pub_in[1..5]);
+    let entropy = HashOut::<F>::from_partial(&pub_in[5..9]);
+    println!(
+        "slot {} of dataset {:?} with entropy {:?} ({} public inputs) is committed at index {}, opening path = {:?}",
+        slot_index, dataset_root.elements, entropy.elements, pub_in.len(), index, opening.path
+    );
+
+    Ok(())
+}